@@ -48,6 +48,7 @@ struct VideoEncoderSettings
 struct VideoEncoder
 {
 	bool initialized;
+	bool force_key_frame;
     const AVCodec* codec;
 	AVCodecContext* context;
 	AVPacket* packet;
@@ -116,6 +117,7 @@ extern "C"
 	EXPORT VideoEncoder* codec_create_video_encoder(VideoEncoderSettings* settings);
     EXPORT bool codec_video_encoder_copy_frame(VideoEncoder* codec, VideoFrame* frame);
 	EXPORT bool codec_video_encoder_send_frame(VideoEncoder* codec);
+	EXPORT void codec_video_encoder_request_key_frame(VideoEncoder* codec);
 	EXPORT Packet* codec_video_encoder_read_packet(VideoEncoder* codec);
 	EXPORT void codec_unref_video_encoder_packet(VideoEncoder* codec);
 	EXPORT void codec_release_video_encoder(VideoEncoder* codec);
@@ -165,6 +165,7 @@ bool codec_video_decoder_send_packet(VideoDecoder* codec,
 
         if (codec->packet->size)
         {
+            codec->packet->pts = codec->parser->pts;
             if (avcodec_send_packet(codec->context, codec->packet) != 0)
             {
                 return false;
@@ -191,6 +192,7 @@ VideoFrame* codec_video_decoder_read_frame(VideoDecoder* codec)
 
     codec->output_frame->width = codec->frame->width;
     codec->output_frame->height = codec->frame->height;
+    codec->output_frame->timestamp = codec->frame->pts == AV_NOPTS_VALUE ? 0 : codec->frame->pts;
 
     if (codec->frame->format != AV_PIX_FMT_NV12 && !codec->format_format.has_value())
     {
@@ -289,6 +291,7 @@ VideoEncoder* codec_create_video_encoder(VideoEncoderSettings* settings)
         av_opt_set_int(codec->context->priv_data, "async_depth", 1, 0);
         av_opt_set_int(codec->context->priv_data, "low_power", 1 /* true */, 0);
         av_opt_set_int(codec->context->priv_data, "vcm", 1 /* true */, 0);
+        av_opt_set_int(codec->context->priv_data, "forced_idr", 1 /* true */, 0);
 	}
 	else if (name == "h264_nvenc")
 	{
@@ -298,6 +301,7 @@ VideoEncoder* codec_create_video_encoder(VideoEncoderSettings* settings)
 		av_opt_set_int(codec->context->priv_data, "cbr", 1 /* true */, 0);
 		av_opt_set_int(codec->context->priv_data, "preset", 7 /* low latency */, 0);
 		av_opt_set_int(codec->context->priv_data, "tune", 3 /* ultra low latency */, 0);
+		av_opt_set_int(codec->context->priv_data, "forced-idr", 1 /* true */, 0);
 	}
 	else if (name == "libx264")
 	{
@@ -305,6 +309,7 @@ VideoEncoder* codec_create_video_encoder(VideoEncoderSettings* settings)
 		av_opt_set(codec->context->priv_data, "tune", "zerolatency", 0);
         av_opt_set_int(codec->context->priv_data, "nal-hrd", 2 /* cbr */, 0);
         av_opt_set_int(codec->context->priv_data, "sc_threshold", settings->key_frame_interval, 0);
+        av_opt_set_int(codec->context->priv_data, "forced-idr", 1 /* true */, 0);
 	}
 
 	if (avcodec_open2(codec->context, codec->codec, nullptr) != 0)
@@ -359,6 +364,25 @@ bool codec_video_encoder_copy_frame(VideoEncoder* codec, VideoFrame* frame)
 		return false;
 	}
 
+	// A capture shared by several encoders is captured at the size of the main
+	// encoder, the simulcast layers scale the frames down to their own size.
+	if (frame->width != (uint32_t)codec->frame->width || frame->height != (uint32_t)codec->frame->height)
+	{
+		return libyuv::NV12Scale(frame->data[0],
+								 (int)frame->linesize[0],
+								 frame->data[1],
+								 (int)frame->linesize[1],
+								 (int)frame->width,
+								 (int)frame->height,
+								 codec->frame->data[0],
+								 codec->frame->linesize[0],
+								 codec->frame->data[1],
+								 codec->frame->linesize[1],
+								 codec->frame->width,
+								 codec->frame->height,
+								 libyuv::kFilterBilinear) == 0;
+	}
+
 	const uint8_t* buffer[4] = 
 	{
 		frame->data[0],
@@ -395,6 +419,19 @@ bool codec_video_encoder_send_frame(VideoEncoder* codec)
 	codec->frame->pts = av_rescale_q(codec->context->frame_num,
 									 codec->context->pkt_timebase,
 									 codec->context->time_base);
+
+	// If a key frame has been requested, force the current frame to be encoded as
+	// an IDR frame, otherwise let the encoder decide the picture type.
+	if (codec->force_key_frame)
+	{
+		codec->force_key_frame = false;
+		codec->frame->pict_type = AV_PICTURE_TYPE_I;
+	}
+	else
+	{
+		codec->frame->pict_type = AV_PICTURE_TYPE_NONE;
+	}
+
 	if (avcodec_send_frame(codec->context, codec->frame) != 0)
 	{
 		return false;
@@ -403,6 +440,11 @@ bool codec_video_encoder_send_frame(VideoEncoder* codec)
 	return true;
 }
 
+void codec_video_encoder_request_key_frame(VideoEncoder* codec)
+{
+	codec->force_key_frame = true;
+}
+
 Packet* codec_video_encoder_read_packet(VideoEncoder* codec)
 {
 	if (codec->context == nullptr)
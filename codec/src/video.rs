@@ -14,6 +14,7 @@ extern "C" {
     fn codec_create_video_encoder(settings: *const RawVideoEncoderSettings) -> *const c_void;
     fn codec_video_encoder_copy_frame(codec: *const c_void, frame: *const VideoFrame) -> bool;
     fn codec_video_encoder_send_frame(codec: *const c_void) -> bool;
+    fn codec_video_encoder_request_key_frame(codec: *const c_void);
     fn codec_video_encoder_read_packet(codec: *const c_void) -> *const RawPacket;
     fn codec_unref_video_encoder_packet(codec: *const c_void);
     fn codec_release_video_encoder(codec: *const c_void);
@@ -141,6 +142,12 @@ impl VideoEncoder {
         unsafe { codec_video_encoder_send_frame(self.0) }
     }
 
+    /// Force the next frame supplied to the encoder to be encoded as a key
+    /// frame (IDR).
+    pub fn request_key_frame(&mut self) {
+        unsafe { codec_video_encoder_request_key_frame(self.0) }
+    }
+
     /// Read encoded data from the encoder.
     pub fn read(&mut self) -> Option<VideoEncodePacket> {
         let packet = unsafe { codec_video_encoder_read_packet(self.0) };
@@ -95,6 +95,9 @@ bool MirrorServiceExt::CreateMirrorSender()
     options.video = &video_options;
     options.audio = &audio_options;
     options.multicast = false;
+    options.title = nullptr;
+    options.layers = nullptr;
+    options.layers_size = 0;
 
     FrameSink sink;
     sink.video = video_proc;
@@ -135,6 +138,7 @@ bool MirrorServiceExt::CreateMirrorReceiver()
     _receiver = mirror_create_receiver(_mirror, 
                                        _args.ArgsParams.id, 
                                        _args.ArgsParams.decoder.c_str(), 
+                                       100,
                                        sink);
     if (_receiver == nullptr)
     {
@@ -192,7 +196,18 @@ bool MirrorServiceExt::_create_mirror()
     MirrorOptions mirror_options;
     mirror_options.server = const_cast<char*>(_args.ArgsParams.server.c_str());
     mirror_options.multicast = const_cast<char*>("239.0.0.1");
+    mirror_options.interface_index = 0;
+    mirror_options.min_jitter_delay = 10;
+    mirror_options.max_jitter_delay = 200;
     mirror_options.mtu = 1400;
+    mirror_options.fec = 10;
+    mirror_options.token = nullptr;
+    mirror_options.token_ctx = nullptr;
+    mirror_options.secret = nullptr;
+    mirror_options.passphrase = nullptr;
+    mirror_options.key_length = 0;
+    mirror_options.encrypt_multicast = false;
+    mirror_options.direct = false;
 
     _mirror = mirror_create(mirror_options);
     return _mirror != nullptr;
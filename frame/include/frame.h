@@ -18,6 +18,7 @@ typedef struct
     uint32_t height;
     uint8_t* data[2];
     size_t linesize[2];
+    uint64_t timestamp;
 } VideoFrame;
 
 typedef struct
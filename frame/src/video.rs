@@ -26,6 +26,10 @@ pub struct VideoFrame {
     pub height: u32,
     pub data: [*const u8; 2],
     pub linesize: [usize; 2],
+    /// The presentation time of a decoded frame in microseconds, it is the
+    /// timestamp of the packet the frame was decoded from, 0 for the captured
+    /// frames.
+    pub timestamp: u64,
 }
 
 unsafe impl Sync for VideoFrame {}
@@ -38,6 +42,7 @@ impl Default for VideoFrame {
             height: 0,
             linesize: [0, 0],
             data: [null(), null()],
+            timestamp: 0,
         }
     }
 }
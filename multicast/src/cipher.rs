@@ -0,0 +1,253 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, RwLock},
+};
+
+use bytes::{BufMut, BytesMut};
+use chacha20poly1305::{aead::AeadInPlace, ChaCha20Poly1305, KeyInit, Nonce, Tag};
+
+/// A ChaCha20-Poly1305 key.
+pub type Key = [u8; 32];
+
+/// The number of recent keys kept for decryption, the packets encrypted with
+/// the previous keys may still be in flight or be repaired after a rotation.
+const CAPACITY: usize = 4;
+
+/// The size of the key id, which follows the header of an encrypted packet.
+pub(crate) const KEY_ID_SIZE: usize = 1;
+
+/// The size of the authentication tag at the end of an encrypted packet.
+pub(crate) const TAG_SIZE: usize = 16;
+
+struct KeyRing {
+    keys: VecDeque<(u8, ChaCha20Poly1305, Key)>,
+    current: Option<u8>,
+}
+
+/// The keys of an encrypted multicast stream.
+///
+/// The keys are shared between the multicast sockets and the owner of the
+/// stream, which distributes them to the receivers over a secure channel,
+/// cloning it only clones the handle.
+///
+/// The server encrypts with the current key, which is the last activated key,
+/// and the receiver encrypts the nacks with the last inserted key. All the
+/// recent keys are accepted for decryption, so that the key can be rotated
+/// without interrupting the stream.
+///
+/// A key must only be used by one server, the nonces of the fragments are
+/// derived from their sequence numbers.
+#[derive(Clone)]
+pub struct Keys(Arc<RwLock<KeyRing>>);
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self(Arc::new(RwLock::new(KeyRing {
+            keys: VecDeque::with_capacity(CAPACITY),
+            current: None,
+        })))
+    }
+}
+
+impl Keys {
+    /// Generate a new random key, the key is accepted for decryption but is
+    /// not used for encryption by the server until it is activated.
+    pub fn generate(&self) -> (u8, Key) {
+        let id = self
+            .0
+            .read()
+            .unwrap()
+            .keys
+            .back()
+            .map(|(id, _, _)| id.wrapping_add(1))
+            .unwrap_or_default();
+
+        let key = rand::random::<Key>();
+        self.insert(id, key);
+        (id, key)
+    }
+
+    /// Add a key, a key with the same id is replaced and the oldest key is
+    /// dropped when there are too many keys.
+    pub fn insert(&self, id: u8, key: Key) {
+        let mut ring = self.0.write().unwrap();
+        ring.keys.retain(|(it, _, _)| *it != id);
+        if ring.keys.len() >= CAPACITY {
+            ring.keys.pop_front();
+        }
+
+        ring.keys
+            .push_back((id, ChaCha20Poly1305::new(&key.into()), key));
+    }
+
+    /// Use the key for encryption from now on.
+    pub fn activate(&self, id: u8) {
+        self.0.write().unwrap().current = Some(id);
+    }
+
+    /// All the keys, from the oldest to the newest.
+    pub fn all(&self) -> Vec<(u8, Key)> {
+        self.0
+            .read()
+            .unwrap()
+            .keys
+            .iter()
+            .map(|(id, _, key)| (*id, *key))
+            .collect()
+    }
+
+    /// Encrypt the body of the packet in place and append the tag, the header
+    /// including the key id is authenticated but not encrypted.
+    ///
+    /// Returns false if there is no key to encrypt with.
+    pub(crate) fn seal(&self, buf: &mut BytesMut, head: usize, nonce: &Nonce) -> bool {
+        let ring = self.0.read().unwrap();
+        let key = match ring.current {
+            Some(id) => ring.keys.iter().find(|(it, _, _)| *it == id),
+            None => ring.keys.back(),
+        };
+
+        let (id, cipher, _) = if let Some(key) = key {
+            key
+        } else {
+            return false;
+        };
+
+        buf[head - KEY_ID_SIZE] = *id;
+
+        let (aad, body) = buf.split_at_mut(head);
+        if let Ok(tag) = cipher.encrypt_in_place_detached(nonce, &aad[8..], body) {
+            buf.put(&tag[..]);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Authenticate the packet and decrypt the body, returns the plaintext
+    /// body without the tag.
+    pub(crate) fn open(&self, bytes: &[u8], head: usize, nonce: &Nonce) -> Option<Vec<u8>> {
+        if bytes.len() < head + TAG_SIZE {
+            return None;
+        }
+
+        let id = bytes[head - KEY_ID_SIZE];
+        let ring = self.0.read().unwrap();
+        let (_, cipher, _) = ring.keys.iter().find(|(it, _, _)| *it == id)?;
+
+        let (body, tag) = bytes[head..].split_at(bytes.len() - head - TAG_SIZE);
+        let mut body = body.to_vec();
+        cipher
+            .decrypt_in_place_detached(nonce, &bytes[8..head], &mut body, Tag::from_slice(tag))
+            .ok()?;
+
+        Some(body)
+    }
+}
+
+/// The nonce is the kind of the packet and a number that is unique for the
+/// kind under the same key, the chunk sequence for the fragments and the
+/// parity fragments, a random number for the nacks.
+pub(crate) fn nonce(kind: u8, number: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[0] = kind;
+    nonce[4..].copy_from_slice(&number.to_be_bytes());
+    Nonce::from(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A header of 16 bytes, the check digit, which is not authenticated, then the
+    // rest of the header and the key id.
+    const HEAD: usize = 16;
+
+    fn packet() -> BytesMut {
+        let mut buf = BytesMut::with_capacity(100);
+        buf.put_u64(0);
+        buf.put_bytes(7, HEAD - 8);
+        buf.put(&b"the body of the packet"[..]);
+        buf
+    }
+
+    fn seal(keys: &Keys, number: u64) -> BytesMut {
+        let mut buf = packet();
+        assert!(keys.seal(&mut buf, HEAD, &nonce(0, number)));
+        buf
+    }
+
+    #[test]
+    fn open_a_sealed_packet() {
+        let keys = Keys::default();
+        keys.generate();
+
+        let buf = seal(&keys, 1);
+        assert_eq!(buf.len(), packet().len() + TAG_SIZE);
+        assert_ne!(buf[HEAD..buf.len() - TAG_SIZE], packet()[HEAD..]);
+        assert_eq!(
+            keys.open(&buf, HEAD, &nonce(0, 1)).unwrap(),
+            &packet()[HEAD..]
+        );
+    }
+
+    #[test]
+    fn not_seal_without_a_key() {
+        let mut buf = packet();
+        assert!(!Keys::default().seal(&mut buf, HEAD, &nonce(0, 1)));
+    }
+
+    #[test]
+    fn reject_a_tampered_packet() {
+        let keys = Keys::default();
+        keys.generate();
+
+        let buf = seal(&keys, 1);
+        for index in [8, HEAD, buf.len() - 1] {
+            let mut tampered = buf.clone();
+            tampered[index] ^= 1;
+            assert!(keys.open(&tampered, HEAD, &nonce(0, 1)).is_none());
+        }
+
+        assert!(keys.open(&buf, HEAD, &nonce(0, 2)).is_none());
+        assert!(keys.open(&buf, HEAD, &nonce(1, 1)).is_none());
+        assert!(keys
+            .open(&buf[..HEAD + TAG_SIZE - 1], HEAD, &nonce(0, 1))
+            .is_none());
+    }
+
+    #[test]
+    fn reject_an_unknown_key() {
+        let keys = Keys::default();
+        keys.generate();
+
+        let other = Keys::default();
+        other.insert(0, rand::random());
+        assert!(other.open(&seal(&keys, 1), HEAD, &nonce(0, 1)).is_none());
+
+        let mut buf = seal(&keys, 1);
+        buf[HEAD - KEY_ID_SIZE] = 1;
+        assert!(keys.open(&buf, HEAD, &nonce(0, 1)).is_none());
+    }
+
+    #[test]
+    fn open_with_the_recent_keys() {
+        let keys = Keys::default();
+        let (first, _) = keys.generate();
+        keys.activate(first);
+        let old = seal(&keys, 1);
+
+        let (second, _) = keys.generate();
+        assert_eq!(seal(&keys, 2)[HEAD - KEY_ID_SIZE], first);
+
+        keys.activate(second);
+        assert_eq!(seal(&keys, 3)[HEAD - KEY_ID_SIZE], second);
+        assert!(keys.open(&old, HEAD, &nonce(0, 1)).is_some());
+
+        for _ in 0..CAPACITY {
+            keys.generate();
+        }
+
+        assert!(keys.open(&old, HEAD, &nonce(0, 1)).is_none());
+    }
+}
@@ -1,23 +1,88 @@
-use std::{collections::BTreeMap, time::Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 
 use super::fragments::Fragment;
 
+/// The number of recently skipped sequence numbers that are remembered, so
+/// that the packets arriving after being skipped can be told apart from
+/// duplicates.
+const SKIPPED_CAPACITY: usize = 1024;
+
+/// Statistics of the packet reordering queue.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DequeueStats {
+    /// The current hold time of the packets in the queue.
+    pub delay: Duration,
+    /// Smoothed inter-arrival jitter of the packets.
+    pub jitter: Duration,
+    /// The largest distance between a packet that arrived out of order and the
+    /// newest packet at that time.
+    pub max_reorder_depth: u64,
+    /// The number of packets that arrived out of order.
+    pub reordered: u64,
+    /// The number of packets that arrived after the queue had skipped them.
+    pub late: u64,
+    /// The number of packets skipped by the queue, which were lost and could
+    /// not be recovered in time.
+    pub lost: u64,
+}
+
 /// Packet reordering queue.
+///
+/// The packets are held in the queue for a while to wait for the packets
+/// that arrived out of order. The hold time adapts to the network, it follows
+/// how late the out of order packets are and the inter-arrival jitter, and is
+/// limited between the minimum and maximum delay.
 pub struct Dequeue {
     queue: BTreeMap<u64, (Fragment, Instant)>,
+    skipped: BTreeSet<u64>,
     last_queue: u64,
-    delay: usize,
+    min: Duration,
+    max: Duration,
+    newest: Option<(u64, Instant)>,
+    last_arrival: Option<(Instant, Duration)>,
+    // Recent peak of how late the out of order packets are, in seconds.
+    lateness: f64,
+    // Smoothed inter-arrival jitter, in seconds.
+    jitter: f64,
+    stats: DequeueStats,
 }
 
 impl Dequeue {
-    pub fn new(delay: usize) -> Self {
+    /// `min` and `max` are the bounds of the hold time in milliseconds, the
+    /// queue starts with the minimum delay.
+    pub fn new(min: usize, max: usize) -> Self {
+        let min = Duration::from_millis(min as u64);
+        let max = Duration::from_millis(max as u64).max(min);
+
         Self {
             queue: BTreeMap::new(),
+            skipped: BTreeSet::new(),
             last_queue: 0,
-            delay,
+            newest: None,
+            last_arrival: None,
+            lateness: 0.0,
+            jitter: 0.0,
+            stats: DequeueStats {
+                delay: min,
+                ..Default::default()
+            },
+            min,
+            max,
         }
     }
 
+    /// The current hold time of the packets in the queue.
+    pub fn delay(&self) -> Duration {
+        self.stats.delay
+    }
+
+    pub fn stats(&self) -> DequeueStats {
+        self.stats
+    }
+
     /// Add a data packet to the queue, and the queue will sort all the data
     /// packets from small to large according to the sequence number.
     ///
@@ -29,13 +94,24 @@ impl Dequeue {
         if !(self.last_queue >= u64::MAX - 100 && packet.chunk_sequence <= 100)
             && self.last_queue >= packet.chunk_sequence
         {
+            // The packet arrived after the queue had skipped it, the delay is too short,
+            // increase it at once.
+            if self.skipped.remove(&packet.chunk_sequence) {
+                self.lateness = self.lateness.max(self.stats.delay.as_secs_f64() * 1.5);
+                self.stats.late += 1;
+            }
+
+            return;
+        }
+
+        // Duplicates have no effect on the statistics.
+        if self.queue.contains_key(&packet.chunk_sequence) {
             return;
         }
 
-        // To avoid duplicate insertion, check here first.
-        self.queue
-            .entry(packet.chunk_sequence)
-            .or_insert_with(|| (packet, Instant::now()));
+        let now = Instant::now();
+        self.update(packet.chunk_sequence, now);
+        self.queue.insert(packet.chunk_sequence, (packet, now));
     }
 
     /// According to the set delay, the data packets are taken out from the
@@ -47,7 +123,20 @@ impl Dequeue {
         // whether it has timed out.
         let mut sequence = None;
         if let Some((seq, (_, time))) = self.queue.first_key_value() {
-            if time.elapsed().as_millis() as usize >= self.delay {
+            if time.elapsed() >= self.stats.delay {
+                // Remember the sequence numbers skipped by this packet.
+                let gap = seq.wrapping_sub(self.last_queue);
+                if gap > 1 && gap <= SKIPPED_CAPACITY as u64 {
+                    self.stats.lost += gap - 1;
+                    for i in 1..gap {
+                        self.skipped.insert(self.last_queue.wrapping_add(i));
+                    }
+
+                    while self.skipped.len() > SKIPPED_CAPACITY {
+                        self.skipped.pop_first();
+                    }
+                }
+
                 sequence.replace(*seq);
                 self.last_queue = *seq;
             }
@@ -57,4 +146,39 @@ impl Dequeue {
             self.queue.remove(&seq).map(|(packet, _)| packet)
         })
     }
+
+    fn update(&mut self, sequence: u64, now: Instant) {
+        // Inter-arrival jitter, which is the smoothed difference between two
+        // consecutive arrival intervals, see RFC 3550.
+        if let Some((time, interval)) = self.last_arrival {
+            let current = now - time;
+            let diff = (current.as_secs_f64() - interval.as_secs_f64()).abs();
+            self.jitter += (diff - self.jitter) / 16.0;
+            self.last_arrival = Some((now, current));
+
+            // The peak decays slowly, 1ms every 100ms, so that the delay shrinks
+            // again after the network has recovered.
+            self.lateness = (self.lateness - current.as_secs_f64() / 100.0).max(0.0);
+        } else {
+            self.last_arrival = Some((now, Duration::ZERO));
+        }
+
+        match self.newest {
+            Some((newest, time)) if newest.wrapping_sub(sequence) < u64::MAX / 2 => {
+                // The packet arrived out of order, it should have arrived before the newest
+                // packet, and the queue has to hold the packets at least this long.
+                let depth = newest.wrapping_sub(sequence);
+                self.lateness = self.lateness.max((now - time).as_secs_f64());
+                self.stats.max_reorder_depth = self.stats.max_reorder_depth.max(depth);
+                self.stats.reordered += 1;
+            }
+            _ => {
+                self.newest = Some((sequence, now));
+            }
+        }
+
+        let delay = Duration::from_secs_f64((self.lateness * 1.5).max(self.jitter * 4.0));
+        self.stats.jitter = Duration::from_secs_f64(self.jitter);
+        self.stats.delay = delay.clamp(self.min, self.max);
+    }
 }
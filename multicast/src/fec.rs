@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+use crate::fragments::{Fragment, Parity};
+
+/// The number of recent fragments and parity fragments kept for recovery.
+const CAPACITY: usize = 512;
+
+/// Xor all chunks together, the result is as long as the longest chunk, and
+/// the lengths of all chunks are also xor together so that the length of a
+/// rebuilt chunk is known.
+pub fn xor<'a, T: Iterator<Item = &'a [u8]>>(chunks: T) -> (u16, Vec<u8>) {
+    let mut length = 0;
+    let mut parity = Vec::new();
+
+    for chunk in chunks {
+        if parity.len() < chunk.len() {
+            parity.resize(chunk.len(), 0);
+        }
+
+        for (a, b) in parity.iter_mut().zip(chunk) {
+            *a ^= b;
+        }
+
+        length ^= chunk.len() as u16;
+    }
+
+    (length, parity)
+}
+
+/// Forward error correction decoder.
+///
+/// The sender inserts a parity fragment after each group of fragments, when
+/// exactly one fragment of the group is lost, it can be rebuilt from the
+/// other fragments of the group and the parity fragment.
+#[derive(Default)]
+pub struct FecDecoder {
+    fragments: BTreeMap<u64, Bytes>,
+    parities: BTreeMap<u64, Parity>,
+}
+
+impl FecDecoder {
+    /// Record a received fragment, returns the fragment rebuilt with its help,
+    /// if any.
+    pub fn push_fragment(&mut self, fragment: &Fragment) -> Option<Fragment> {
+        self.fragments
+            .insert(fragment.chunk_sequence, fragment.bytes.clone());
+        if self.fragments.len() > CAPACITY {
+            self.fragments.pop_first();
+        }
+
+        // Find the parity fragment of the group that contains this fragment.
+        let first = self
+            .parities
+            .range(..=fragment.chunk_sequence)
+            .next_back()
+            .map(|(seq, _)| *seq)?;
+
+        self.recover(first)
+    }
+
+    /// Record a received parity fragment, returns the fragment rebuilt with
+    /// its help, if any.
+    pub fn push_parity(&mut self, parity: Parity) -> Option<Fragment> {
+        let first = parity.chunk_sequence;
+        self.parities.insert(first, parity);
+        if self.parities.len() > CAPACITY {
+            self.parities.pop_first();
+        }
+
+        self.recover(first)
+    }
+
+    fn recover(&mut self, first: u64) -> Option<Fragment> {
+        let parity = self.parities.get(&first)?;
+        let sequences = (0..parity.count as u64).map(|i| first.wrapping_add(i));
+
+        let mut missing = None;
+        for sequence in sequences.clone() {
+            if !self.fragments.contains_key(&sequence) {
+                // More than one fragment of the group is lost, it cannot be rebuilt yet.
+                if missing.replace(sequence).is_some() {
+                    return None;
+                }
+            }
+        }
+
+        // Either the whole group has arrived, or the missing fragment can be rebuilt,
+        // in both cases the parity fragment is no longer needed.
+        let parity = self.parities.remove(&first)?;
+        let chunk_sequence = missing?;
+
+        let (length, bytes) = xor(sequences
+            .filter_map(|seq| self.fragments.get(&seq))
+            .map(|bytes| &bytes[..]));
+
+        // The xor of all the other fragments of the group and the parity fragment is
+        // the missing fragment.
+        let (_, mut bytes) = xor([&bytes[..], &parity.bytes[..]].into_iter());
+        bytes.truncate((length ^ parity.length) as usize);
+
+        let bytes = Bytes::from(bytes);
+        self.fragments.insert(chunk_sequence, bytes.clone());
+
+        Some(Fragment {
+            sequence: parity.sequence,
+            size: parity.size,
+            chunk_sequence,
+            bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group() -> (Vec<Fragment>, Parity) {
+        let chunks: [&[u8]; 4] = [b"first chunk", b"second", b"the third chunk", b"4th"];
+        let fragments = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| Fragment {
+                chunk_sequence: 10 + i as u64,
+                sequence: 1,
+                size: 35,
+                bytes: Bytes::copy_from_slice(chunk),
+            })
+            .collect::<Vec<_>>();
+
+        let (length, bytes) = xor(chunks.into_iter());
+        let parity = Parity {
+            chunk_sequence: 10,
+            sequence: 1,
+            size: 35,
+            count: 4,
+            bytes: Bytes::from(bytes),
+            length,
+        };
+
+        (fragments, parity)
+    }
+
+    #[test]
+    fn recover_a_lost_fragment() {
+        for lost in 0..4 {
+            let (fragments, parity) = group();
+            let mut decoder = FecDecoder::default();
+
+            for fragment in fragments.iter().filter(|it| it.chunk_sequence != 10 + lost) {
+                assert!(decoder.push_fragment(fragment).is_none());
+            }
+
+            let recovered = decoder.push_parity(parity).unwrap();
+            assert_eq!(recovered.chunk_sequence, 10 + lost);
+            assert_eq!(recovered.sequence, 1);
+            assert_eq!(recovered.size, 35);
+            assert_eq!(recovered.bytes, fragments[lost as usize].bytes);
+        }
+    }
+
+    #[test]
+    fn recover_when_the_parity_comes_first() {
+        let (fragments, parity) = group();
+        let mut decoder = FecDecoder::default();
+
+        assert!(decoder.push_parity(parity).is_none());
+        assert!(decoder.push_fragment(&fragments[0]).is_none());
+        assert!(decoder.push_fragment(&fragments[1]).is_none());
+
+        let recovered = decoder.push_fragment(&fragments[3]).unwrap();
+        assert_eq!(recovered.chunk_sequence, 12);
+        assert_eq!(recovered.bytes, fragments[2].bytes);
+    }
+
+    #[test]
+    fn not_recover_two_lost_fragments() {
+        let (fragments, parity) = group();
+        let mut decoder = FecDecoder::default();
+
+        assert!(decoder.push_fragment(&fragments[0]).is_none());
+        assert!(decoder.push_fragment(&fragments[2]).is_none());
+        assert!(decoder.push_parity(parity).is_none());
+    }
+
+    #[test]
+    fn not_recover_a_complete_group() {
+        let (fragments, parity) = group();
+        let mut decoder = FecDecoder::default();
+
+        for fragment in &fragments {
+            assert!(decoder.push_fragment(fragment).is_none());
+        }
+
+        assert!(decoder.push_parity(parity).is_none());
+    }
+}
@@ -1,6 +1,77 @@
+use std::io::Error;
+
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use xxhash_rust::xxh3::xxh3_64;
 
+use crate::{
+    cipher::{nonce, Keys, KEY_ID_SIZE, TAG_SIZE},
+    fec,
+    nack::MAX_GAP,
+};
+
+// ```text
+//  0                   1                   2                   3
+// 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                        Check Digit                            |
+// |                                                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |     kind      |              chunk sequence...
+//                 ...chunk sequence...
+//       ...       |                 sequence...
+//                       ...sequence...
+//       ...       |                   size...
+//       ...       |                  payload...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// ```
+//
+// The parity fragment additionally carries the number of protected fragments
+// (u8) and the xor of their payload lengths (u16) before the payload, the nack
+// carries the number of lost fragments (u16) and has no payload.
+//
+// When the stream is encrypted, the highest bit of the kind is set, and the
+// header is followed by the id of the key, the rest of the packet is encrypted
+// and followed by the authentication tag (16 bytes). The header and the key id
+// are authenticated, and the checksum covers the encrypted packet.
+
+const FRAGMENT_KIND: u8 = 0;
+const PARITY_KIND: u8 = 1;
+const NACK_KIND: u8 = 2;
+const ENCRYPTED: u8 = 0x80;
+
+/// The size of the common header, from the check digit to the size.
+const COMMON_HEAD_SIZE: usize = 29;
+
+/// The size of the header, including the additional fields of the parity
+/// fragment, the payload of each fragment does not exceed `mtu - HEAD_SIZE`.
+const HEAD_SIZE: usize = 32;
+
+/// Write the common header, the check digit and the key id are filled in by
+/// `finish`.
+fn put_head(buf: &mut BytesMut, kind: u8, chunk_sequence: u64, sequence: u64, size: u32) {
+    buf.put_u64(0);
+    buf.put_u8(kind);
+    buf.put_u64(chunk_sequence);
+    buf.put_u64(sequence);
+    buf.put_u32(size);
+}
+
+/// Encrypt the packet if the stream is encrypted, and fill in the check digit.
+fn finish(buf: &mut BytesMut, keys: Option<&Keys>, number: u64) -> Result<(), Error> {
+    if let Some(keys) = keys {
+        let kind = buf[8];
+        buf[8] = kind | ENCRYPTED;
+        if !keys.seal(buf, COMMON_HEAD_SIZE + KEY_ID_SIZE, &nonce(kind, number)) {
+            return Err(Error::other("no key to encrypt the multicast"));
+        }
+    }
+
+    let hash = xxh3_64(&buf[8..]);
+    buf[0..8].copy_from_slice(&hash.to_be_bytes());
+    Ok(())
+}
+
+#[derive(Clone)]
 pub struct Fragment {
     pub chunk_sequence: u64,
     pub sequence: u64,
@@ -8,68 +79,213 @@ pub struct Fragment {
     pub bytes: Bytes,
 }
 
-impl TryFrom<&[u8]> for Fragment {
-    type Error = std::io::Error;
+/// Forward error correction fragment, which is the xor of the payloads of
+/// `count` consecutive fragments of the same frame starting from
+/// `chunk_sequence`.
+pub struct Parity {
+    pub chunk_sequence: u64,
+    pub sequence: u64,
+    pub size: usize,
+    pub count: u8,
+    pub length: u16,
+    pub bytes: Bytes,
+}
 
-    fn try_from(mut bytes: &[u8]) -> Result<Self, Self::Error> {
-        if bytes.get_u64() == xxh3_64(bytes) {
-            Ok(Self {
-                chunk_sequence: bytes.get_u64(),
-                sequence: bytes.get_u64(),
-                size: bytes.get_u32() as usize,
-                bytes: Bytes::copy_from_slice(bytes),
-            })
-        } else {
-            Err(std::io::Error::other("invalid data"))
+/// Negative acknowledgement, `count` consecutive fragments starting from
+/// `chunk_sequence` have been lost.
+///
+/// The receiver sends it to the publisher, and the publisher multicasts it
+/// back to the group as a confirmation before the repair, so that the other
+/// receivers that have lost the same fragments do not send it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nack {
+    pub chunk_sequence: u64,
+    pub count: u16,
+}
+
+impl Nack {
+    /// Returns `None` if the stream is encrypted and there is no key yet.
+    pub fn encode(&self, keys: Option<&Keys>) -> Option<Bytes> {
+        // The nacks are encrypted by all the receivers with the same key, the random
+        // sequence is used as the nonce.
+        let sequence = if keys.is_some() { rand::random() } else { 0 };
+
+        let mut buf = BytesMut::with_capacity(HEAD_SIZE + KEY_ID_SIZE + TAG_SIZE);
+        put_head(&mut buf, NACK_KIND, self.chunk_sequence, sequence, 0);
+        if keys.is_some() {
+            buf.put_u8(0);
         }
+
+        buf.put_u16(self.count);
+        finish(&mut buf, keys, sequence).ok()?;
+        Some(buf.freeze())
+    }
+}
+
+pub enum Packet {
+    Fragment(Fragment),
+    Parity(Parity),
+    Nack(Nack),
+}
+
+impl Packet {
+    /// Decode a packet, when the stream is encrypted only the packets that
+    /// can be authenticated with one of the keys are accepted.
+    pub fn decode(buf: &[u8], keys: Option<&Keys>) -> Result<Self, Error> {
+        let mut bytes = buf;
+        if bytes.len() < COMMON_HEAD_SIZE || bytes.get_u64() != xxh3_64(bytes) {
+            return Err(Error::other("invalid data"));
+        }
+
+        let kind = bytes.get_u8();
+        let chunk_sequence = bytes.get_u64();
+        let sequence = bytes.get_u64();
+        let size = bytes.get_u32() as usize;
+
+        let body;
+        let mut bytes = match (kind & ENCRYPTED != 0, keys) {
+            (false, None) => bytes,
+            (true, Some(keys)) => {
+                let kind = kind & !ENCRYPTED;
+                let number = if kind == NACK_KIND {
+                    sequence
+                } else {
+                    chunk_sequence
+                };
+
+                body = keys
+                    .open(buf, COMMON_HEAD_SIZE + KEY_ID_SIZE, &nonce(kind, number))
+                    .ok_or_else(|| Error::other("invalid data"))?;
+                &body[..]
+            }
+            _ => return Err(Error::other("invalid data")),
+        };
+
+        Ok(match kind & !ENCRYPTED {
+            FRAGMENT_KIND => Self::Fragment(Fragment {
+                bytes: Bytes::copy_from_slice(bytes),
+                chunk_sequence,
+                sequence,
+                size,
+            }),
+            PARITY_KIND if bytes.len() >= 3 => Self::Parity(Parity {
+                count: bytes.get_u8(),
+                length: bytes.get_u16(),
+                bytes: Bytes::copy_from_slice(bytes),
+                chunk_sequence,
+                sequence,
+                size,
+            }),
+            // The publisher does not repair more fragments than a receiver can request
+            // at once.
+            NACK_KIND if bytes.len() >= 2 => {
+                let count = bytes.get_u16();
+                if count == 0 || count as u64 > MAX_GAP {
+                    return Err(Error::other("invalid data"));
+                }
+
+                Self::Nack(Nack {
+                    chunk_sequence,
+                    count,
+                })
+            }
+            _ => return Err(Error::other("invalid data")),
+        })
     }
 }
 
 pub struct FragmentEncoder {
     packets: Vec<BytesMut>,
+    fragments: usize,
     chunk_sequence: u64,
     sequence: u64,
+    keys: Option<Keys>,
     mtu: usize,
+    fec: usize,
 }
 
 impl FragmentEncoder {
-    pub fn new(mtu: usize) -> Self {
+    /// `fec` is the number of fragments protected by one parity fragment, for
+    /// example 10 means 10% redundancy, 0 disables forward error correction.
+    ///
+    /// The fragments are encrypted with the current key if `keys` is set.
+    pub fn new(mtu: usize, fec: u8, keys: Option<Keys>) -> Self {
         Self {
             packets: Default::default(),
+            fragments: 0,
             chunk_sequence: 0,
             sequence: 0,
+            fec: fec as usize,
+            keys,
             mtu,
         }
     }
 
-    pub fn encode(&mut self, bytes: &[u8]) -> &[BytesMut] {
+    pub fn encode(&mut self, bytes: &[u8]) -> Result<&[BytesMut], Error> {
+        // The key id and the authentication tag take up a part of the payload.
+        let payload_size = if self.keys.is_some() {
+            self.mtu - HEAD_SIZE - KEY_ID_SIZE - TAG_SIZE
+        } else {
+            self.mtu - HEAD_SIZE
+        };
+
         let mut size = 0;
-        for (i, chunk) in bytes.chunks(self.mtu - 28).enumerate() {
-            {
-                if self.packets.get(i).is_none() {
-                    self.packets.push(BytesMut::with_capacity(self.mtu));
-                }
+        for chunk in bytes.chunks(payload_size) {
+            let buf = alloc(&mut self.packets, size, self.mtu);
+            put_head(
+                buf,
+                FRAGMENT_KIND,
+                self.chunk_sequence,
+                self.sequence,
+                bytes.len() as u32,
+            );
+
+            if self.keys.is_some() {
+                buf.put_u8(0);
             }
 
-            if let Some(buf) = self.packets.get_mut(i) {
-                buf.clear();
+            buf.put(chunk);
+            finish(buf, self.keys.as_ref(), self.chunk_sequence)?;
 
-                buf.put_u64(0);
-                buf.put_u64(self.chunk_sequence);
-                buf.put_u64(self.sequence);
-                buf.put_u32(bytes.len() as u32);
-                buf.put(chunk);
+            size += 1;
 
-                let hash = xxh3_64(&buf[8..]);
-                buf[0..8].copy_from_slice(&hash.to_be_bytes());
+            self.chunk_sequence = if self.chunk_sequence == u64::MAX {
+                0
+            } else {
+                self.chunk_sequence + 1
+            };
+        }
 
-                size += 1;
+        self.fragments = size;
 
-                self.chunk_sequence = if self.chunk_sequence == u64::MAX {
-                    0
-                } else {
-                    self.chunk_sequence + 1
-                };
+        // Each group of fragments of the current frame is followed by a parity
+        // fragment, the receiver can rebuild any one lost fragment of the group.
+        if self.fec > 0 {
+            let first_sequence = self.chunk_sequence.wrapping_sub(size as u64);
+            for (i, group) in bytes.chunks(payload_size * self.fec).enumerate() {
+                let (length, parity) = fec::xor(group.chunks(payload_size));
+                let count = group.len().div_ceil(payload_size);
+                let chunk_sequence = first_sequence.wrapping_add((i * self.fec) as u64);
+
+                let buf = alloc(&mut self.packets, size, self.mtu);
+                put_head(
+                    buf,
+                    PARITY_KIND,
+                    chunk_sequence,
+                    self.sequence,
+                    bytes.len() as u32,
+                );
+
+                if self.keys.is_some() {
+                    buf.put_u8(0);
+                }
+
+                buf.put_u8(count as u8);
+                buf.put_u16(length);
+                buf.put(&parity[..]);
+                finish(buf, self.keys.as_ref(), chunk_sequence)?;
+
+                size += 1;
             }
         }
 
@@ -79,10 +295,31 @@ impl FragmentEncoder {
             self.sequence + 1
         };
 
-        &self.packets[..size]
+        Ok(&self.packets[..size])
+    }
+
+    /// The fragments of the last encoding and their chunk sequences, not
+    /// including the parity fragments.
+    pub fn fragments(&self) -> impl Iterator<Item = (u64, &BytesMut)> {
+        let first = self.chunk_sequence.wrapping_sub(self.fragments as u64);
+        self.packets[..self.fragments]
+            .iter()
+            .enumerate()
+            .map(move |(i, buf)| (first.wrapping_add(i as u64), buf))
     }
 }
 
+// Reuse the buffers of the previous encoding as much as possible.
+fn alloc(packets: &mut Vec<BytesMut>, index: usize, mtu: usize) -> &mut BytesMut {
+    if packets.get(index).is_none() {
+        packets.push(BytesMut::with_capacity(mtu));
+    }
+
+    let buf = &mut packets[index];
+    buf.clear();
+    buf
+}
+
 pub struct FragmentDecoder {
     bytes: BytesMut,
     sequence: i128,
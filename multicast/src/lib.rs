@@ -1,9 +1,15 @@
+mod cipher;
 mod dequeue;
+mod fec;
 mod fragments;
+mod nack;
 
 use std::{
-    io::Error,
-    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    io::{Error, ErrorKind},
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
@@ -12,11 +18,32 @@ use fragments::FragmentEncoder;
 use once_cell::sync::Lazy;
 use tokio::{runtime::Runtime, sync::mpsc::unbounded_channel};
 
+pub use crate::{
+    cipher::{Key, Keys},
+    dequeue::DequeueStats,
+};
+
 use crate::{
     dequeue::Dequeue,
-    fragments::{Fragment, FragmentDecoder},
+    fec::FecDecoder,
+    fragments::{FragmentDecoder, Packet},
+    nack::{History, LossDetector, RepairLimiter},
 };
 
+/// Statistics of the multicast receiver.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    /// Statistics of the reordering queue.
+    pub queue: DequeueStats,
+    /// The number of fragments received, including the repaired fragments.
+    pub received: u64,
+    /// The number of packets dropped because the checksum does not match, or
+    /// because they cannot be authenticated when the stream is encrypted.
+    pub checksum_dropped: u64,
+    /// The number of fragments rebuilt by forward error correction.
+    pub recovered: u64,
+}
+
 static RUNTIME: Lazy<Runtime> =
     Lazy::new(|| Runtime::new().expect("failed to create tokio runtime, this is a bug"));
 
@@ -37,6 +64,7 @@ static RUNTIME: Lazy<Runtime> =
 /// multicast packets.
 pub struct Socket {
     rx: Receiver<(u64, Bytes)>,
+    stats: Arc<Mutex<Stats>>,
     close_signal: tokio::sync::mpsc::UnboundedSender<()>,
 }
 
@@ -49,11 +77,25 @@ impl Socket {
     /// You need to specify the multicast group for the udp session to join to
     /// the specified multicast group.
     ///
-    /// Note that only IPV4 is supported.
-    pub fn new(multicast: Ipv4Addr, bind: SocketAddr) -> Result<Self, Error> {
-        assert!(bind.is_ipv4());
-
-        RUNTIME.block_on(Self::create(multicast, bind))
+    /// Both IPV4 and IPV6 are supported, the multicast group and the bind
+    /// address must be of the same address family. For IPV6, the scope id of
+    /// the bind address is the index of the interface that joins the group, 0
+    /// lets the system choose the interface.
+    ///
+    /// The received packets are held in a reordering queue, the hold time
+    /// adapts to the network jitter between `min_delay` and `max_delay`
+    /// milliseconds.
+    ///
+    /// When `keys` is set, the stream is expected to be encrypted, only the
+    /// packets that can be authenticated with one of the keys are accepted.
+    pub fn new(
+        multicast: IpAddr,
+        bind: SocketAddr,
+        min_delay: usize,
+        max_delay: usize,
+        keys: Option<Keys>,
+    ) -> Result<Self, Error> {
+        RUNTIME.block_on(Self::create(multicast, bind, min_delay, max_delay, keys))
     }
 
     /// Reads packets sent from the multicast server.
@@ -66,44 +108,121 @@ impl Socket {
         self.rx.recv().ok()
     }
 
+    /// Get the statistics of the receiver, including the current delay and
+    /// the reordering statistics of the receive queue.
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
+    }
+
     pub fn close(&self) {
         let _ = self.close_signal.send(());
     }
 
-    async fn create(multicast: Ipv4Addr, bind: SocketAddr) -> Result<Self, Error> {
+    async fn create(
+        multicast: IpAddr,
+        bind: SocketAddr,
+        min_delay: usize,
+        max_delay: usize,
+        keys: Option<Keys>,
+    ) -> Result<Self, Error> {
         let socket = socket2::Socket::from(UdpSocket::bind(bind)?);
         socket.set_recv_buffer_size(4 * 1024 * 1024)?;
         socket.set_nonblocking(true)?;
 
-        let socket = tokio::net::UdpSocket::from_std(socket.into())?;
-        if let IpAddr::V4(bind) = bind.ip() {
-            socket.join_multicast_v4(multicast, bind)?;
+        join_multicast(&socket, multicast, bind)?;
+        if bind.is_ipv4() {
             socket.set_broadcast(true)?;
         }
 
+        let socket = tokio::net::UdpSocket::from_std(socket.into())?;
+
         let (close_signal, mut closed) = unbounded_channel();
         let (tx, rx) = bounded(5);
 
+        let queue = Dequeue::new(min_delay, max_delay);
+        let stats = Arc::new(Mutex::new(Stats {
+            queue: queue.stats(),
+            ..Default::default()
+        }));
+
+        let stats_ = stats.clone();
         tokio::spawn(async move {
             let mut buf = vec![0u8; 2048];
-            let mut queue = Dequeue::new(50);
+            let mut queue = queue;
+            let mut fec = FecDecoder::default();
+            let mut loss = LossDetector::default();
             let mut decoder = FragmentDecoder::new();
 
+            // The source address of the multicast packets is the publisher's socket, the
+            // nacks are sent to it.
+            let mut publisher = None;
+
             'a: loop {
+                let deadline = loss
+                    .deadline()
+                    .unwrap_or_else(|| Instant::now() + Duration::from_secs(1));
+
                 tokio::select! {
-                    Ok(size) = socket.recv(&mut buf[..]) => {
+                    Ok((size, addr)) = socket.recv_from(&mut buf[..]) => {
                         if size == 0 {
                             break;
                         }
 
-                        if let Ok(packet) = Fragment::try_from(&buf[..size]) {
-                            queue.push(packet);
+                        let packet = if let Ok(packet) = Packet::decode(&buf[..size], keys.as_ref()) {
+                            packet
+                        } else {
+                            stats_.lock().unwrap().checksum_dropped += 1;
+                            continue;
+                        };
 
-                            while let Some(chunk) = queue.pop() {
-                                if let Some(packet) = decoder.decode(chunk) {
-                                    if tx.send(packet).is_err() {
-                                        break 'a;
-                                    }
+                        // The lost fragment may be rebuilt from the parity fragments, the rebuilt
+                        // fragment is reordered together with other fragments.
+                        let recovered = match packet {
+                            Packet::Fragment(fragment) => {
+                                stats_.lock().unwrap().received += 1;
+                                publisher.replace(addr);
+                                loss.push(fragment.chunk_sequence);
+
+                                let recovered = fec.push_fragment(&fragment);
+                                queue.push(fragment);
+                                recovered
+                            }
+                            Packet::Parity(parity) => fec.push_parity(parity),
+                            Packet::Nack(nack) => {
+                                loss.confirm(&nack);
+                                None
+                            }
+                        };
+
+                        if let Some(fragment) = recovered {
+                            loss.push(fragment.chunk_sequence);
+                            queue.push(fragment);
+                            stats_.lock().unwrap().recovered += 1;
+                        }
+
+                        while let Some(chunk) = queue.pop() {
+                            if let Some(packet) = decoder.decode(chunk) {
+                                if tx.send(packet).is_err() {
+                                    break 'a;
+                                }
+                            }
+                        }
+
+                        stats_.lock().unwrap().queue = queue.stats();
+                    }
+                    _ = tokio::time::sleep_until(deadline.into()) => {
+                        if let Some(publisher) = publisher {
+                            for nack in loss.poll(queue.delay()) {
+                                // The nacks of an encrypted stream cannot be sent before the key
+                                // is known.
+                                let bytes = if let Some(bytes) = nack.encode(keys.as_ref()) {
+                                    bytes
+                                } else {
+                                    continue;
+                                };
+
+                                if socket.send_to(&bytes, publisher).await.is_err() {
+                                    break 'a;
                                 }
                             }
                         }
@@ -116,7 +235,11 @@ impl Socket {
             }
         });
 
-        Ok(Self { close_signal, rx })
+        Ok(Self {
+            close_signal,
+            stats,
+            rx,
+        })
     }
 }
 
@@ -135,10 +258,16 @@ impl Socket {
 ///
 /// This server is used to send multicast packets to all members of a multicast
 /// group.
+///
+/// The recently sent fragments are kept, when a receiver reports lost
+/// fragments with a nack, the server multicasts the nack back to the group as
+/// a confirmation and then multicasts the lost fragments again.
 pub struct Server {
     target: SocketAddr,
     socket: UdpSocket,
     encoder: FragmentEncoder,
+    history: Arc<Mutex<History>>,
+    sent: u64,
 }
 
 impl Server {
@@ -147,26 +276,133 @@ impl Server {
     /// You need to specify the multicast group for the udp session to join to
     /// the specified multicast group.
     ///
-    /// Note that only IPV4 is supported.
+    /// Both IPV4 and IPV6 are supported, the multicast group and the bind
+    /// address must be of the same address family. For IPV6, the scope id of
+    /// the bind address is the index of the interface used to send multicast
+    /// packets, 0 lets the system choose the interface.
     ///
     /// MTU is used to specify the network unit size, this is used to limit the
     /// maximum size of packets sent.
-    pub fn new(multicast: Ipv4Addr, bind: SocketAddr, mtu: usize) -> Result<Self, Error> {
-        assert!(bind.is_ipv4());
+    ///
+    /// FEC is the number of fragments protected by one parity fragment, a lost
+    /// fragment in each group can be rebuilt by the receiver, 0 disables
+    /// forward error correction.
+    ///
+    /// When `keys` is set, the fragments are encrypted with the current key,
+    /// and only the nacks that can be authenticated with one of the keys are
+    /// accepted.
+    pub fn new(
+        multicast: IpAddr,
+        bind: SocketAddr,
+        mtu: usize,
+        fec: u8,
+        keys: Option<Keys>,
+    ) -> Result<Self, Error> {
+        let mut local = bind;
+        local.set_port(0);
 
-        let socket = UdpSocket::bind(SocketAddr::new(bind.ip(), 0))?;
-        if let IpAddr::V4(bind) = bind.ip() {
-            socket.join_multicast_v4(&multicast, &bind)?;
-            socket.set_multicast_loop_v4(false)?;
+        let socket = socket2::Socket::from(UdpSocket::bind(local)?);
+        join_multicast(&socket, multicast, bind)?;
+        match bind {
+            SocketAddr::V4(_) => {
+                socket.set_multicast_loop_v4(false)?;
+            }
+            SocketAddr::V6(bind) => {
+                socket.set_multicast_loop_v6(false)?;
+                socket.set_multicast_if_v6(bind.scope_id())?;
+            }
         }
 
+        // The target keeps the scope id of the bind address, which is required by the
+        // link-local IPV6 multicast groups.
+        let mut target = bind;
+        target.set_ip(multicast);
+
+        let socket: UdpSocket = socket.into();
+        let history: Arc<Mutex<History>> = Default::default();
+
+        // The receivers send the nacks to the socket used for sending, the read
+        // timeout is only used to check whether the server has been released.
+        let socket_ = socket.try_clone()?;
+        socket_.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let keys_ = keys.clone();
+        let history_ = Arc::downgrade(&history);
+        thread::Builder::new()
+            .name("MirrorMulticastRepairThread".to_string())
+            .spawn(move || {
+                let mut buf = [0u8; 2048];
+                let mut limiter = RepairLimiter::default();
+
+                loop {
+                    let (size, source) = match socket_.recv_from(&mut buf) {
+                        Ok(it) => it,
+                        Err(e)
+                            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                        {
+                            if history_.strong_count() == 0 {
+                                break;
+                            }
+
+                            continue;
+                        }
+                        Err(_) => break,
+                    };
+
+                    let history = if let Some(history) = history_.upgrade() {
+                        history
+                    } else {
+                        break;
+                    };
+
+                    if let Ok(Packet::Nack(nack)) = Packet::decode(&buf[..size], keys_.as_ref()) {
+                        // The nacks are not authenticated when the stream is not encrypted, so
+                        // each receiver only gets a limited number of repairs.
+                        let limit = limiter.remaining(source);
+                        let fragments = history.lock().unwrap().repair(&nack, limit);
+                        if fragments.is_empty() {
+                            continue;
+                        }
+
+                        limiter.consume(source, fragments.len());
+
+                        // Confirm the nack first, the other receivers that have lost the same
+                        // fragments will not send it again.
+                        if let Some(bytes) = nack.encode(keys_.as_ref()) {
+                            if socket_.send_to(&bytes, target).is_err() {
+                                break;
+                            }
+                        }
+
+                        for bytes in fragments {
+                            if socket_.send_to(&bytes, target).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            })?;
+
         Ok(Self {
-            target: SocketAddr::new(IpAddr::V4(multicast), bind.port()),
-            encoder: FragmentEncoder::new(mtu),
+            encoder: FragmentEncoder::new(mtu, fec, keys),
+            sent: 0,
+            history,
+            target,
             socket,
         })
     }
 
+    /// The total number of fragments repaired at the request of the
+    /// receivers.
+    pub fn repaired(&self) -> u64 {
+        self.history.lock().unwrap().repaired()
+    }
+
+    /// The total number of fragments sent, not including the repairs.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
     /// Sends data on the socket to the remote address to which it is connected.
     ///
     /// Sends the packet to all members of the multicast group.
@@ -177,14 +413,42 @@ impl Server {
             return Ok(());
         }
 
-        for chunk in self.encoder.encode(bytes) {
+        for chunk in self.encoder.encode(bytes)? {
             self.socket.send_to(chunk, self.target)?;
+            self.sent += 1;
+        }
+
+        // Keep the sent fragments for repair.
+        let mut history = self.history.lock().unwrap();
+        for (chunk_sequence, chunk) in self.encoder.fragments() {
+            history.push(chunk_sequence, Bytes::copy_from_slice(chunk));
         }
 
         Ok(())
     }
 }
 
+// For IPV4 the interface that joins the multicast group is specified by the bind
+// address, and for IPV6 by the scope id of the bind address.
+fn join_multicast(
+    socket: &socket2::Socket,
+    multicast: IpAddr,
+    bind: SocketAddr,
+) -> Result<(), Error> {
+    match (multicast, bind) {
+        (IpAddr::V4(multicast), SocketAddr::V4(bind)) => {
+            socket.join_multicast_v4(&multicast, bind.ip())
+        }
+        (IpAddr::V6(multicast), SocketAddr::V6(bind)) => {
+            socket.join_multicast_v6(&multicast, bind.scope_id())
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the multicast group and the bind address are not in the same address family",
+        )),
+    }
+}
+
 /// Picks a free port, that is unused on both TCP and UDP
 pub fn alloc_port() -> Result<u16, Error> {
     let socket = UdpSocket::bind("0.0.0.0:0")?;
@@ -0,0 +1,240 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use rand::Rng;
+
+use crate::fragments::Nack;
+
+/// The number of recently sent fragments kept by the publisher for repair.
+const HISTORY_CAPACITY: usize = 1024;
+
+/// The same fragment is repaired at most once in this interval, no matter how
+/// many receivers have requested it.
+const REPAIR_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The receiver waits a random time up to this value before sending a nack,
+/// if another receiver has requested the same fragments in the meantime, the
+/// nack is not sent.
+const BACKOFF: Duration = Duration::from_millis(5);
+
+/// How long the receiver waits for the repair after a nack has been sent or
+/// confirmed, before requesting it again.
+const REPAIR_TIMEOUT: Duration = Duration::from_millis(15);
+
+/// A gap larger than this is not requested, it is usually caused by the
+/// publisher restarting or the network being down for a while, and the
+/// receiver has to wait for the next key frame anyway. A nack never requests
+/// more fragments than this.
+pub const MAX_GAP: u64 = 256;
+
+/// The number of fragments repaired at the request of one receiver in each
+/// window, so that a single receiver cannot drive the repair traffic of the
+/// whole group.
+const REPAIR_BUDGET: usize = 512;
+
+const REPAIR_WINDOW: Duration = Duration::from_secs(1);
+
+/// Ring buffer of the fragments recently sent by the publisher.
+pub struct History {
+    fragments: Vec<Option<(u64, Bytes, Option<Instant>)>>,
+    repaired: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            fragments: vec![None; HISTORY_CAPACITY],
+            repaired: 0,
+        }
+    }
+}
+
+impl History {
+    /// The total number of fragments repaired.
+    pub fn repaired(&self) -> u64 {
+        self.repaired
+    }
+
+    pub fn push(&mut self, chunk_sequence: u64, bytes: Bytes) {
+        self.fragments[chunk_sequence as usize % HISTORY_CAPACITY] =
+            Some((chunk_sequence, bytes, None));
+    }
+
+    /// Get the fragments requested by the nack that still need to be
+    /// repaired, up to the limit, fragments that have been repaired recently
+    /// are skipped.
+    pub fn repair(&mut self, nack: &Nack, limit: usize) -> Vec<Bytes> {
+        let count = (nack.count as u64).min(MAX_GAP);
+        let mut fragments = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            if fragments.len() >= limit {
+                break;
+            }
+
+            let chunk_sequence = nack.chunk_sequence.wrapping_add(i);
+            if let Some((seq, bytes, repaired)) =
+                &mut self.fragments[chunk_sequence as usize % HISTORY_CAPACITY]
+            {
+                if *seq != chunk_sequence {
+                    continue;
+                }
+
+                if let Some(time) = repaired {
+                    if time.elapsed() < REPAIR_INTERVAL {
+                        continue;
+                    }
+                }
+
+                repaired.replace(Instant::now());
+                fragments.push(bytes.clone());
+            }
+        }
+
+        self.repaired += fragments.len() as u64;
+        fragments
+    }
+}
+
+/// The repair budgets of the receivers, by the address the nacks come from.
+#[derive(Default)]
+pub struct RepairLimiter {
+    sources: HashMap<SocketAddr, (Instant, usize)>,
+}
+
+impl RepairLimiter {
+    /// The number of fragments that can still be repaired for the receiver in
+    /// the current window.
+    pub fn remaining(&mut self, source: SocketAddr) -> usize {
+        let now = Instant::now();
+
+        // Forget the receivers whose window has passed.
+        self.sources
+            .retain(|_, (start, _)| now.duration_since(*start) < REPAIR_WINDOW);
+
+        REPAIR_BUDGET
+            - self
+                .sources
+                .get(&source)
+                .map(|(_, used)| *used)
+                .unwrap_or(0)
+    }
+
+    pub fn consume(&mut self, source: SocketAddr, count: usize) {
+        let (_, used) = self
+            .sources
+            .entry(source)
+            .or_insert_with(|| (Instant::now(), 0));
+
+        *used = (*used + count).min(REPAIR_BUDGET);
+    }
+}
+
+struct Pending {
+    deadline: Instant,
+    lost: Instant,
+}
+
+/// Detects the lost fragments on the receiver side and decides when to
+/// request them.
+///
+/// A lost fragment is not requested immediately, each receiver waits a random
+/// backoff first, and when the publisher confirms a nack from any receiver,
+/// all receivers waiting for the same fragments only wait for the repair.
+#[derive(Default)]
+pub struct LossDetector {
+    highest: Option<u64>,
+    pending: BTreeMap<u64, Pending>,
+}
+
+impl LossDetector {
+    /// Record a received fragment, the fragments skipped between it and the
+    /// previous highest fragment are considered lost.
+    pub fn push(&mut self, chunk_sequence: u64) {
+        self.pending.remove(&chunk_sequence);
+
+        let highest = if let Some(highest) = self.highest {
+            highest
+        } else {
+            self.highest = Some(chunk_sequence);
+            return;
+        };
+
+        // Duplicates, repairs and reordered fragments are not newer than the highest
+        // fragment.
+        let gap = chunk_sequence.wrapping_sub(highest);
+        if gap == 0 || gap > u64::MAX / 2 {
+            return;
+        }
+
+        self.highest = Some(chunk_sequence);
+        if gap > MAX_GAP {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut rng = rand::thread_rng();
+        for i in 1..gap {
+            self.pending.insert(
+                highest.wrapping_add(i),
+                Pending {
+                    deadline: now + rng.gen_range(Duration::ZERO..=BACKOFF),
+                    lost: now,
+                },
+            );
+        }
+    }
+
+    /// Another receiver has requested these fragments and the publisher has
+    /// confirmed it, so just wait for the repair.
+    pub fn confirm(&mut self, nack: &Nack) {
+        let deadline = Instant::now() + REPAIR_TIMEOUT;
+        for i in 0..nack.count as u64 {
+            if let Some(pending) = self.pending.get_mut(&nack.chunk_sequence.wrapping_add(i)) {
+                pending.deadline = deadline;
+            }
+        }
+    }
+
+    /// The time of the next nack to be sent, if any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|it| it.deadline).min()
+    }
+
+    /// Take the nacks that are due, consecutive lost fragments are merged into
+    /// one nack.
+    ///
+    /// Lost fragments are no longer requested after the delay of the
+    /// reordering queue, the queue has already skipped them.
+    pub fn poll(&mut self, delay: Duration) -> Vec<Nack> {
+        let now = Instant::now();
+        self.pending.retain(|_, it| it.lost.elapsed() < delay);
+
+        let mut nacks: Vec<Nack> = Vec::new();
+        for (seq, pending) in self.pending.iter_mut() {
+            if pending.deadline > now {
+                continue;
+            }
+
+            pending.deadline = now + REPAIR_TIMEOUT;
+            if let Some(nack) = nacks.last_mut() {
+                if nack.chunk_sequence.wrapping_add(nack.count as u64) == *seq
+                    && (nack.count as u64) < MAX_GAP
+                {
+                    nack.count += 1;
+                    continue;
+                }
+            }
+
+            nacks.push(Nack {
+                chunk_sequence: *seq,
+                count: 1,
+            });
+        }
+
+        nacks
+    }
+}
@@ -7,6 +7,7 @@ import android.media.MediaCodec
 import android.media.MediaCodecInfo
 import android.media.MediaFormat
 import android.os.Build
+import android.os.Bundle
 import android.os.Process
 import android.util.Log
 import android.view.Surface
@@ -112,6 +113,17 @@ class Video {
             return surface
         }
 
+        /**
+         * Request the encoder to emit a sync frame (IDR) as soon as possible.
+         */
+        fun requestKeyFrame() {
+            if (isRunning) {
+                val params = Bundle()
+                params.putInt(MediaCodec.PARAMETER_KEY_REQUEST_SYNC_FRAME, 0)
+                codec.setParameters(params)
+            }
+        }
+
         fun start() {
             if (!isRunning) {
                 isRunning = true
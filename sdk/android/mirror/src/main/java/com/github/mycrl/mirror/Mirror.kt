@@ -17,10 +17,94 @@ data class StreamBufferInfo(val kind: Int) {
     var timestamp: Long = 0;
 }
 
+class TransportMode {
+    companion object {
+        const val Srt = 0;
+        const val Multicast = 1;
+    }
+}
+
+/**
+ * The encryption state of the srt connection of a stream.
+ */
+class EncryptionState {
+    companion object {
+        const val Unsecured = 0;
+        const val Securing = 1;
+        const val Secured = 2;
+        const val NoSecret = 3;
+        const val BadSecret = 4;
+    }
+}
+
+/**
+ * Called when the sender switches the transport in auto mode, the mode is one
+ * of [TransportMode].
+ */
+abstract class SwitchHandler {
+    abstract fun switched(mode: Int)
+}
+
+/**
+ * Provides the token of a channel and role, issued by the application with the secret of the
+ * service, e.g. by its backend, `publisher` is false for a receiver. It is called every time a
+ * stream connects, and with the channel 0 as a receiver for the signal connection, null connects
+ * without a token.
+ */
+abstract class TokenProvider {
+    abstract fun token(id: Int, publisher: Boolean): String?
+}
+
+/**
+ * Network statistics of a stream.
+ *
+ * The bitrates are in Mbps and measured since the previous query, the
+ * packet counters are totals since the stream was created, `encryption` is one of
+ * [EncryptionState].
+ *
+ * `incompatibleDropped` counts the packets of a sender using a package version this
+ * receiver does not support, the sender and the receiver have to be upgraded to the same
+ * version.
+ */
+data class StreamStats(
+    val mode: Int,
+    val rtt: Double,
+    val sendBitrate: Double,
+    val recvBitrate: Double,
+    val lostPackets: Long,
+    val retransmittedPackets: Long,
+    val checksumDropped: Long,
+    val filteredFrames: Long,
+    val jitterDelay: Int,
+    val encryption: Int,
+    val incompatibleDropped: Long,
+)
+
+/**
+ * A published channel, the strings are null and the numbers are 0 when the
+ * publisher did not describe the channel, its video or its audio.
+ */
+data class Channel(
+    val id: Int,
+    val title: String?,
+    val videoCodec: String?,
+    val width: Int,
+    val height: Int,
+    val frameRate: Int,
+    val audioCodec: String?,
+    val sampleRate: Int,
+    val audioChannels: Int,
+)
+
 class SenderAdapterWrapper constructor(
     private val sendProc: (StreamBufferInfo, ByteArray) -> Unit,
     private val getMulticastProc: () -> Boolean,
     private val setMulticastProc: (Boolean) -> Unit,
+    private val getAutoSwitchProc: () -> Boolean,
+    private val setAutoSwitchProc: (Boolean) -> Unit,
+    private val setSwitchHandlerProc: (SwitchHandler) -> Unit,
+    private val takeKeyFrameRequestProc: () -> Boolean,
+    private val getStatsProc: () -> StreamStats?,
     private val releaseProc: () -> Unit,
 ) {
     fun send(info: StreamBufferInfo, buf: ByteArray) {
@@ -35,12 +119,53 @@ class SenderAdapterWrapper constructor(
         setMulticastProc(isMulticast)
     }
 
+    fun getAutoSwitch(): Boolean {
+        return getAutoSwitchProc()
+    }
+
+    /**
+     * Whether the sender switches between multicast and srt on its own, in
+     * auto mode the transport set by [setMulticast] may be overridden.
+     */
+    fun setAutoSwitch(enable: Boolean) {
+        setAutoSwitchProc(enable)
+    }
+
+    fun setSwitchHandler(handler: SwitchHandler) {
+        setSwitchHandlerProc(handler)
+    }
+
+    /**
+     * Whether a receiver has requested a key frame, if true, the encoder
+     * should emit a key frame immediately.
+     */
+    fun takeKeyFrameRequest(): Boolean {
+        return takeKeyFrameRequestProc()
+    }
+
+    /**
+     * Get the network statistics of this sender.
+     */
+    fun getStats(): StreamStats? {
+        return getStatsProc()
+    }
+
     fun release() {
         releaseProc()
     }
 }
 
-class ReceiverAdapterWrapper constructor(private val releaser: () -> Unit) {
+class ReceiverAdapterWrapper constructor(
+    private val getStatsProc: () -> StreamStats?,
+    private val releaser: () -> Unit,
+) {
+    /**
+     * Get the network statistics of this receiver.
+     */
+    fun getStats(): StreamStats? {
+        return getStatsProc()
+    }
+
     /**
      * Close and release this receiver.
      */
@@ -53,11 +178,35 @@ class Mirror constructor(
     private val server: String,
     private val multicast: String,
     private val mtu: Int,
+    private val fec: Int = 0,
+    private val interfaceIndex: Int = 0,
+    private val minJitterDelay: Int = 10,
+    private val maxJitterDelay: Int = 200,
+    private val tokens: TokenProvider? = null,
+    private val secret: String? = null,
+    private val passphrase: String? = null,
+    private val keyLength: Int = 0,
+    private val encryptMulticast: Boolean = false,
+    private val direct: Boolean = false,
 ) {
     private var mirror: Long = 0L
 
     init {
-        mirror = createMirror(server, multicast, mtu)
+        mirror = createMirror(
+            server,
+            multicast,
+            interfaceIndex,
+            minJitterDelay,
+            maxJitterDelay,
+            mtu,
+            fec,
+            tokens,
+            secret,
+            passphrase,
+            keyLength,
+            encryptMulticast,
+            direct
+        )
         if (mirror == 0L) {
             throw Exception("failed to create mirror!")
         }
@@ -90,6 +239,35 @@ class Mirror constructor(
                     }
                 }
             },
+            { ->
+                run {
+                    if (sender != 0L) senderGetAutoSwitch(sender) else false
+                }
+            },
+            { enable ->
+                run {
+                    if (sender != 0L) {
+                        senderSetAutoSwitch(sender, enable)
+                    }
+                }
+            },
+            { handler ->
+                run {
+                    if (sender != 0L) {
+                        senderSetSwitchHandler(sender, handler)
+                    }
+                }
+            },
+            { ->
+                run {
+                    if (sender != 0L) senderTakeKeyFrameRequest(sender) else false
+                }
+            },
+            { ->
+                run {
+                    if (sender != 0L) senderGetStats(sender) else null
+                }
+            },
             { ->
                 run {
                     if (sender != 0L) {
@@ -111,15 +289,30 @@ class Mirror constructor(
             throw Exception("failed to create mirror receiver adapter!")
         }
 
-        return ReceiverAdapterWrapper { ->
-            run {
-                if (receiver != 0L) {
-                    releaseStreamReceiverAdapter(receiver)
-                    adapter.close()
-                    receiver = 0L
+        return ReceiverAdapterWrapper(
+            { ->
+                run {
+                    if (receiver != 0L) receiverGetStats(receiver) else null
                 }
-            }
-        }
+            },
+            { ->
+                run {
+                    if (receiver != 0L) {
+                        releaseStreamReceiverAdapter(receiver)
+                        adapter.close()
+                        receiver = 0L
+                    }
+                }
+            },
+        )
+    }
+
+    /**
+     * Get the channels currently published, sorted by id, in direct mode these
+     * are the senders announced on the LAN.
+     */
+    fun getChannels(): List<Channel> {
+        return if (mirror != 0L) getChannels(mirror)?.toList() ?: listOf() else listOf()
     }
 
     fun release() {
@@ -147,6 +340,11 @@ class Mirror constructor(
      */
     private external fun releaseStreamReceiverAdapter(adapter: Long)
 
+    /**
+     * Get the network statistics of the receiver.
+     */
+    private external fun receiverGetStats(adapter: Long): StreamStats?
+
     /**
      * Creates a mirror instance, the return value is a pointer, and you
     need to
@@ -155,7 +353,17 @@ class Mirror constructor(
     private external fun createMirror(
         server: String,
         multicast: String,
+        interfaceIndex: Int,
+        minJitterDelay: Int,
+        maxJitterDelay: Int,
         mtu: Int,
+        fec: Int,
+        tokens: TokenProvider?,
+        secret: String?,
+        passphrase: String?,
+        keyLength: Int,
+        encryptMulticast: Boolean,
+        direct: Boolean,
     ): Long
 
     /**
@@ -163,6 +371,11 @@ class Mirror constructor(
      */
     private external fun releaseMirror(mirror: Long)
 
+    /**
+     * Get the channels currently published, sorted by id.
+     */
+    private external fun getChannels(mirror: Long): Array<Channel>?
+
     /**
      * Creates an instance of the stream sender adapter, the return value is
     a
@@ -180,6 +393,32 @@ class Mirror constructor(
      */
     private external fun senderSetMulticast(adapter: Long, isMulticast: Boolean)
 
+    /**
+     * Get whether the sender switches the transport on its own
+     */
+    private external fun senderGetAutoSwitch(adapter: Long): Boolean
+
+    /**
+     * Set whether the sender switches between multicast and srt on its own
+     */
+    private external fun senderSetAutoSwitch(adapter: Long, enable: Boolean)
+
+    /**
+     * Set the handler called when the sender switches the transport in auto
+     * mode.
+     */
+    private external fun senderSetSwitchHandler(adapter: Long, handler: SwitchHandler)
+
+    /**
+     * Takes the pending key frame request of the sender.
+     */
+    private external fun senderTakeKeyFrameRequest(adapter: Long): Boolean
+
+    /**
+     * Get the network statistics of the sender.
+     */
+    private external fun senderGetStats(adapter: Long): StreamStats?
+
     /**
      * Release the stream sender adapter.
      */
@@ -48,13 +48,66 @@ abstract class MirrorReceiver {
 /**
  * Create a mirror service, note that observer can be null, when observer is null, it will not
  * automatically respond to any sender push.
+ *
+ * `fec` is the number of multicast fragments protected by one parity fragment, 0 disables
+ * forward error correction.
+ *
+ * `multicast` can be either an IPv4 or an IPv6 multicast group, `interfaceIndex` is the index
+ * of the network interface used by the IPv6 multicast, 0 lets the system choose the interface.
+ *
+ * `minJitterDelay` and `maxJitterDelay` are the bounds of the multicast jitter buffer in
+ * milliseconds, the receiver adapts the time it waits for out of order packets between them.
+ *
+ * `tokens` provides the tokens of the streams, issued by the application, it is only needed when
+ * the service requires authentication, the secret of the service is never given to the clients.
+ *
+ * `secret` is the shared secret of the senders and receivers in direct mode, the senders only
+ * accept the receivers with a token signed with it and the receivers only accept the beacons
+ * signed with it, it is not used with the service.
+ *
+ * `passphrase` enables the srt encryption, it must match the passphrase of the service,
+ * `keyLength` is the length of the key in bytes, 16, 24 or 32, 0 uses the key length of the
+ * service.
+ *
+ * `encryptMulticast` encrypts and authenticates the multicast, the keys are distributed over srt,
+ * so the srt encryption should be enabled as well, all the senders and receivers must use the
+ * same setting.
+ *
+ * `direct` works without the service, the senders announce their streams with a broadcast on the
+ * LAN and the receivers connect to the senders directly, `server` is not used but must still be a
+ * valid address, all the senders and receivers must use the same setting.
  */
 class MirrorService constructor(
     private val server: String,
     private val multicast: String,
     private val mtu: Int,
+    private val fec: Int = 0,
+    private val interfaceIndex: Int = 0,
+    private val minJitterDelay: Int = 10,
+    private val maxJitterDelay: Int = 200,
+    private val tokens: TokenProvider? = null,
+    private val secret: String? = null,
+    private val passphrase: String? = null,
+    private val keyLength: Int = 0,
+    private val encryptMulticast: Boolean = false,
+    private val direct: Boolean = false,
 ) {
-    private val mirror: Mirror = Mirror(server, multicast, mtu)
+    private val mirror: Mirror =
+        Mirror(
+            server,
+            multicast,
+            mtu,
+            fec,
+            interfaceIndex,
+            minJitterDelay,
+            maxJitterDelay,
+            tokens,
+            secret,
+            passphrase,
+            keyLength,
+            encryptMulticast,
+            direct
+        )
 
     /**
      * Release this mirror instance.
@@ -114,7 +167,7 @@ class MirrorService constructor(
             init {
                 videoDecoder.start()
                 audioDecoder?.start()
-                observer.onStart(ReceiverAdapterWrapper { -> close() })
+                observer.onStart(ReceiverAdapterWrapper({ -> adapter?.getStats() }, { -> close() }))
             }
 
             override fun sink(kind: Int, flags: Int, timestamp: Long, buf: ByteArray): Boolean {
@@ -180,6 +233,11 @@ class MirrorSender constructor(
         Video.VideoEncoder(configure.video, object : ByteArraySinker() {
             override fun sink(info: StreamBufferInfo, buf: ByteArray) {
                 sender.send(info, buf)
+
+                // A receiver has lost packets and is waiting for a key frame.
+                if (sender.takeKeyFrameRequest()) {
+                    videoEncoder.requestKeyFrame()
+                }
             }
         })
 
@@ -209,6 +267,33 @@ class MirrorSender constructor(
         sender.setMulticast(isMulticast)
     }
 
+    /**
+     * Get whether the sender switches the transport on its own
+     */
+    fun getAutoSwitch(): Boolean {
+        return sender.getAutoSwitch()
+    }
+
+    /**
+     * Set whether the sender switches between multicast and srt on its own, based on the loss
+     * reported by the receivers, the handler is called on each switch.
+     */
+    fun setAutoSwitch(enable: Boolean, handler: SwitchHandler? = null) {
+        if (handler != null) {
+            sender.setSwitchHandler(handler)
+        }
+
+        sender.setAutoSwitch(enable)
+    }
+
+    /**
+     * Get the network statistics of this sender, such as the round trip time, the bitrate and
+     * the number of lost packets.
+     */
+    fun getStats(): StreamStats? {
+        return sender.getStats()
+    }
+
     /**
      * Get the surface inside the sender, you need to render the texture to this surface to pass the
      * screen to other receivers.
@@ -1,7 +1,7 @@
 use anyhow::anyhow;
 use bytes::Bytes;
-use jni::objects::{GlobalRef, JValue, JValueGen};
-use transport::adapter::StreamKind;
+use jni::objects::{GlobalRef, JString, JValue, JValueGen};
+use transport::{adapter::StreamKind, stats::TransportMode, SocketKind};
 
 use crate::command::{catcher, get_current_env};
 
@@ -63,3 +63,68 @@ impl AndroidStreamReceiverAdapter {
         });
     }
 }
+
+pub struct AndroidSwitchHandler {
+    pub callback: GlobalRef,
+}
+
+impl AndroidSwitchHandler {
+    // /**
+    //  * Called when the sender switches the transport in auto mode.
+    //  */
+    // abstract class SwitchHandler {
+    //     abstract fun switched(mode: Int)
+    // }
+    pub(crate) fn switched(&self, mode: TransportMode) {
+        let mut env = get_current_env();
+        catcher(&mut env, |env| {
+            env.call_method(
+                self.callback.as_obj(),
+                "switched",
+                "(I)V",
+                &[JValue::Int(mode as i32)],
+            )?;
+
+            Ok(())
+        });
+    }
+}
+
+pub struct AndroidTokenProvider {
+    pub callback: GlobalRef,
+}
+
+impl AndroidTokenProvider {
+    // /**
+    //  * Provides the token of a channel and role, issued by the application.
+    //  */
+    // abstract class TokenProvider {
+    //     abstract fun token(id: Int, publisher: Boolean): String?
+    // }
+    pub(crate) fn token(&self, id: u32, kind: SocketKind) -> Option<String> {
+        let mut env = get_current_env();
+        catcher(&mut env, |env| {
+            let token = env
+                .call_method(
+                    self.callback.as_obj(),
+                    "token",
+                    "(IZ)Ljava/lang/String;",
+                    &[
+                        JValue::Int(id as i32),
+                        JValue::Bool((kind == SocketKind::Publisher) as u8),
+                    ],
+                )?
+                .l()?;
+
+            if token.is_null() {
+                return Ok(None);
+            }
+
+            let token = JString::from(token);
+            let ret: String = env.get_string(&token)?.into();
+            let _ = env.delete_local_ref(token);
+            Ok(Some(ret))
+        })
+        .flatten()
+    }
+}
@@ -4,11 +4,11 @@ mod logger;
 
 use std::{ffi::c_void, ptr::null_mut, sync::Arc, thread};
 
-use adapter::AndroidStreamReceiverAdapter;
+use adapter::{AndroidStreamReceiverAdapter, AndroidSwitchHandler, AndroidTokenProvider};
 use command::{catcher, copy_from_byte_array, JVM};
 use jni::{
     objects::{JByteArray, JClass, JObject, JString},
-    sys::JNI_VERSION_1_6,
+    sys::{jobject, JNI_VERSION_1_6},
     JNIEnv, JavaVM,
 };
 
@@ -16,7 +16,7 @@ use jni_macro::jni_exports;
 use logger::AndroidLogger;
 use transport::{
     adapter::{StreamReceiverAdapter, StreamReceiverAdapterExt, StreamSenderAdapter},
-    Transport, TransportOptions,
+    TokenProvider, Transport, TransportOptions,
 };
 
 /// JNI_OnLoad
@@ -97,11 +97,15 @@ pub extern "system" fn JNI_OnUnload(_: JavaVM, _: *mut c_void) {
 mod objects {
     use anyhow::{anyhow, Ok};
     use jni::{
-        objects::{JObject, JValueGen},
+        objects::{JObject, JObjectArray, JValueGen},
         JNIEnv,
     };
 
-    use transport::adapter::{StreamBufferInfo, StreamKind};
+    use transport::{
+        adapter::{StreamBufferInfo, StreamKind},
+        stats::StreamStats,
+        ChannelInfo,
+    };
 
     /// /**
     ///  * Streaming data information.
@@ -138,6 +142,114 @@ mod objects {
             },
         )
     }
+
+    /// /**
+    ///  * Network statistics of a stream.
+    ///  */
+    /// data class StreamStats(
+    ///     val mode: Int,
+    ///     val rtt: Double,
+    ///     val sendBitrate: Double,
+    ///     val recvBitrate: Double,
+    ///     val lostPackets: Long,
+    ///     val retransmittedPackets: Long,
+    ///     val checksumDropped: Long,
+    ///     val filteredFrames: Long,
+    ///     val jitterDelay: Int,
+    ///     val encryption: Int,
+    ///     val incompatibleDropped: Long,
+    /// )
+    pub fn from_stream_stats<'a>(
+        env: &mut JNIEnv<'a>,
+        stats: &StreamStats,
+    ) -> anyhow::Result<JObject<'a>> {
+        Ok(env.new_object(
+            "com/github/mycrl/mirror/StreamStats",
+            "(IDDDJJJJIIJ)V",
+            &[
+                JValueGen::Int(stats.mode as i32),
+                JValueGen::Double(stats.rtt),
+                JValueGen::Double(stats.send_bitrate),
+                JValueGen::Double(stats.recv_bitrate),
+                JValueGen::Long(stats.lost_packets as i64),
+                JValueGen::Long(stats.retransmitted_packets as i64),
+                JValueGen::Long(stats.checksum_dropped as i64),
+                JValueGen::Long(stats.filtered_frames as i64),
+                JValueGen::Int(stats.jitter_delay as i32),
+                JValueGen::Int(stats.encryption as i32),
+                JValueGen::Long(stats.incompatible_dropped as i64),
+            ],
+        )?)
+    }
+
+    /// /**
+    ///  * A published channel, the strings are null and the numbers are 0 when
+    ///  * the publisher did not describe the channel, its video or its audio.
+    ///  */
+    /// data class Channel(
+    ///     val id: Int,
+    ///     val title: String?,
+    ///     val videoCodec: String?,
+    ///     val width: Int,
+    ///     val height: Int,
+    ///     val frameRate: Int,
+    ///     val audioCodec: String?,
+    ///     val sampleRate: Int,
+    ///     val audioChannels: Int,
+    /// )
+    pub fn from_channels<'a>(
+        env: &mut JNIEnv<'a>,
+        channels: &[ChannelInfo],
+    ) -> anyhow::Result<JObjectArray<'a>> {
+        let array = env.new_object_array(
+            channels.len() as i32,
+            "com/github/mycrl/mirror/Channel",
+            JObject::null(),
+        )?;
+
+        for (index, channel) in channels.iter().enumerate() {
+            let description = channel.description.clone().unwrap_or_default();
+            let video = description.video.unwrap_or_default();
+            let audio = description.audio.unwrap_or_default();
+
+            let title = from_string(env, description.title)?;
+            let video_codec = from_string(env, video.codec)?;
+            let audio_codec = from_string(env, audio.codec)?;
+            let object = env.new_object(
+                "com/github/mycrl/mirror/Channel",
+                "(ILjava/lang/String;Ljava/lang/String;IIILjava/lang/String;II)V",
+                &[
+                    JValueGen::Int(channel.id as i32),
+                    JValueGen::Object(&title),
+                    JValueGen::Object(&video_codec),
+                    JValueGen::Int(video.width as i32),
+                    JValueGen::Int(video.height as i32),
+                    JValueGen::Int(video.frame_rate as i32),
+                    JValueGen::Object(&audio_codec),
+                    JValueGen::Int(audio.sample_rate as i32),
+                    JValueGen::Int(audio.channels as i32),
+                ],
+            )?;
+
+            env.set_object_array_element(&array, index as i32, &object)?;
+
+            // The local references would pile up until the call returns.
+            for value in [object, title, video_codec, audio_codec] {
+                env.delete_local_ref(value)?;
+            }
+        }
+
+        Ok(array)
+    }
+
+    // An empty string is passed to java as null.
+    fn from_string<'a>(env: &mut JNIEnv<'a>, value: String) -> anyhow::Result<JObject<'a>> {
+        Ok(if value.is_empty() {
+            JObject::null()
+        } else {
+            env.new_string(value)?.into()
+        })
+    }
 }
 
 /// package mirror.java
@@ -369,35 +481,116 @@ impl Mirror {
         unsafe { Box::from_raw(ptr as *mut Arc<StreamReceiverAdapter>) }.close();
     }
 
+    /// /**
+    ///  * Get the network statistics of the receiver, the bitrate is measured
+    ///  * since the previous call.
+    ///  */
+    /// private external fun receiverGetStats(adapter: Long): StreamStats?
+    pub fn receiver_get_stats(
+        mut env: JNIEnv,
+        _this: JClass,
+        ptr: *const Arc<StreamReceiverAdapter>,
+    ) -> jobject {
+        let stats = unsafe { &*ptr }.get_stats();
+        catcher(&mut env, |env| {
+            Ok(objects::from_stream_stats(env, &stats)?.into_raw())
+        })
+        .unwrap_or_else(null_mut)
+    }
+
     /// /**
     ///  * Creates a mirror instance, the return value is a pointer, and you
     ///    need to
     ///  * check that the pointer is valid.
     ///  */
     /// private external fun createMirror(
-    ///     bind: String,
-    ///     adapterFactory: Long
+    ///     server: String,
+    ///     multicast: String,
+    ///     interfaceIndex: Int,
+    ///     minJitterDelay: Int,
+    ///     maxJitterDelay: Int,
+    ///     mtu: Int,
+    ///     fec: Int,
+    ///     tokens: TokenProvider?,
+    ///     secret: String?,
+    ///     passphrase: String?,
+    ///     keyLength: Int,
+    ///     encryptMulticast: Boolean,
+    ///     direct: Boolean,
     /// ): Long
     pub fn create_mirror(
         mut env: JNIEnv,
         _this: JClass,
         server: JString,
         multicast: JString,
+        interface_index: i32,
+        min_jitter_delay: i32,
+        max_jitter_delay: i32,
         mtu: i32,
+        fec: i32,
+        tokens: JObject,
+        secret: JString,
+        passphrase: JString,
+        key_length: i32,
+        encrypt_multicast: i32,
+        direct: i32,
     ) -> *const Transport {
         catcher(&mut env, |env| {
             let server: String = env.get_string(&server)?.into();
             let multicast: String = env.get_string(&multicast)?.into();
+            let tokens = if tokens.is_null() {
+                None
+            } else {
+                let provider = AndroidTokenProvider {
+                    callback: env.new_global_ref(tokens)?,
+                };
+
+                Some(TokenProvider::new(move |id, kind| provider.token(id, kind)))
+            };
+
+            let secret: Option<String> = if secret.is_null() {
+                None
+            } else {
+                Some(env.get_string(&secret)?.into())
+            };
+
+            let passphrase: Option<String> = if passphrase.is_null() {
+                None
+            } else {
+                Some(env.get_string(&passphrase)?.into())
+            };
 
             Ok(Box::into_raw(Box::new(Transport::new(TransportOptions {
                 server: server.parse()?,
                 multicast: multicast.parse()?,
+                interface: interface_index as u32,
+                min_jitter_delay: min_jitter_delay as u32,
+                max_jitter_delay: max_jitter_delay as u32,
                 mtu: mtu as usize,
+                fec: fec as u8,
+                tokens,
+                secret,
+                passphrase,
+                key_length: key_length as u32,
+                encrypt_multicast: encrypt_multicast != 0,
+                direct: direct != 0,
             })?)))
         })
         .unwrap_or_else(null_mut)
     }
 
+    /// /**
+    ///  * Get the channels currently published, sorted by id.
+    ///  */
+    /// private external fun getChannels(mirror: Long): Array<Channel>?
+    pub fn get_channels(mut env: JNIEnv, _this: JClass, ptr: *const Transport) -> jobject {
+        let channels = unsafe { &*ptr }.channels();
+        catcher(&mut env, |env| {
+            Ok(objects::from_channels(env, &channels)?.into_raw())
+        })
+        .unwrap_or_else(null_mut)
+    }
+
     /// /**
     ///  * Free the mirror instance pointer.
     ///  */
@@ -445,6 +638,83 @@ impl Mirror {
         unsafe { &*ptr }.set_multicast(is_multicast != 0)
     }
 
+    /// /**
+    ///  * Get whether the sender switches the transport on its own
+    ///  */
+    /// private external fun senderGetAutoSwitch(adapter: Long): Boolean
+    pub fn sender_get_auto_switch(
+        _env: JNIEnv,
+        _this: JClass,
+        ptr: *const Arc<StreamSenderAdapter>,
+    ) -> i32 {
+        unsafe { &*ptr }.get_auto_switch() as i32
+    }
+
+    /// /**
+    ///  * Set whether the sender switches between multicast and srt on its own
+    ///  */
+    /// private external fun senderSetAutoSwitch(adapter: Long, enable: Boolean)
+    pub fn sender_set_auto_switch(
+        _env: JNIEnv,
+        _this: JClass,
+        ptr: *const Arc<StreamSenderAdapter>,
+        enable: i32,
+    ) {
+        unsafe { &*ptr }.set_auto_switch(enable != 0)
+    }
+
+    /// /**
+    ///  * Set the handler called when the sender switches the transport in
+    ///  * auto mode.
+    ///  */
+    /// private external fun senderSetSwitchHandler(adapter: Long, handler:
+    /// SwitchHandler)
+    pub fn sender_set_switch_handler(
+        mut env: JNIEnv,
+        _this: JClass,
+        ptr: *const Arc<StreamSenderAdapter>,
+        handler: JObject,
+    ) {
+        catcher(&mut env, |env| {
+            let handler = AndroidSwitchHandler {
+                callback: env.new_global_ref(handler)?,
+            };
+
+            unsafe { &*ptr }.set_switch_handler(move |mode| handler.switched(mode));
+            Ok(())
+        });
+    }
+
+    /// /**
+    ///  * Takes the pending key frame request of the sender, if it returns
+    ///  * true, the encoder should emit a key frame immediately.
+    ///  */
+    /// private external fun senderTakeKeyFrameRequest(adapter: Long): Boolean
+    pub fn sender_take_key_frame_request(
+        _env: JNIEnv,
+        _this: JClass,
+        ptr: *const Arc<StreamSenderAdapter>,
+    ) -> i32 {
+        unsafe { &*ptr }.take_key_frame_request() as i32
+    }
+
+    /// /**
+    ///  * Get the network statistics of the sender, the bitrate is measured
+    ///  * since the previous call.
+    ///  */
+    /// private external fun senderGetStats(adapter: Long): StreamStats?
+    pub fn sender_get_stats(
+        mut env: JNIEnv,
+        _this: JClass,
+        ptr: *const Arc<StreamSenderAdapter>,
+    ) -> jobject {
+        let stats = unsafe { &*ptr }.get_stats();
+        catcher(&mut env, |env| {
+            Ok(objects::from_stream_stats(env, &stats)?.into_raw())
+        })
+        .unwrap_or_else(null_mut)
+    }
+
     /// /**
     ///  * Release the stream sender adapter.
     ///  */
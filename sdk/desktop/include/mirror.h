@@ -97,11 +97,37 @@ typedef struct
     AudioEncoderOptions encoder;
 } AudioOptions;
 
+/**
+ * An additional rendition of the video of the sender, encoded from the same
+ * capture at its own size and bit rate, and published as the channel of the
+ * id. It carries the same audio as the sender.
+ */
+typedef struct
+{
+    int id;
+    /**
+     * The size is not larger than the video of the sender.
+     */
+    VideoEncoderOptions encoder;
+} SimulcastLayer;
+
 typedef struct
 {
     VideoOptions* video;
     AudioOptions* audio;
     bool multicast;
+    /**
+     * The title of the channel shown to the receivers, can be null. The
+     * receivers also get the codecs and parameters of the video and audio.
+     */
+    const char* title;
+    /**
+     * The simulcast layers of the video, can be null. The descriptions of the
+     * channel and of its layers are tagged with `simulcast`, whose value is
+     * the id of the channel.
+     */
+    SimulcastLayer* layers;
+    size_t layers_size;
 } SenderOptions;
 
 typedef struct
@@ -111,21 +137,279 @@ typedef struct
      */
     const char* server;
     /**
-     * Multicast address, e.g. `239.0.0.1`.
+     * Multicast address, e.g. `239.0.0.1`, IPV6 multicast addresses are also
+     * supported, e.g. `ff02::1:239`.
      */
     const char* multicast;
+    /**
+     * The index of the network interface used by the IPV6 multicast, 0 lets
+     * the system choose the interface, it is ignored by IPV4.
+     */
+    uint32_t interface_index;
+    /**
+     * The bounds of the multicast jitter buffer in milliseconds, the receiver
+     * adapts the time it waits for out of order packets between them according
+     * to the network, e.g. 10 and 200.
+     */
+    uint32_t min_jitter_delay;
+    uint32_t max_jitter_delay;
     /**
      * The size of the maximum transmission unit of the network, which is
      * related to the settings of network devices such as routers or switches,
      * the recommended value is 1400.
      */
     size_t mtu;
+    /**
+     * Multicast forward error correction, the number of fragments protected
+     * by one parity fragment, e.g. 10 means 10% redundancy, 0 disables it.
+     */
+    uint8_t fec;
+    /**
+     * Provides the tokens of the senders and the receivers, issued by the
+     * application with the secret of the service, which is never given to
+     * the clients. It writes the token of the channel and role into the
+     * buffer of `size` bytes and returns its length, 0 for no token. It is
+     * called every time a stream connects, and with the channel 0 as a
+     * receiver for the signal connection. Can be null if the service does
+     * not require authentication.
+     */
+    size_t (*token)(void* ctx, uint32_t id, bool publisher, char* token, size_t size);
+    void* token_ctx;
+    /**
+     * The shared secret of the senders and the receivers in direct mode, the
+     * senders only accept the receivers with a token signed with it and the
+     * receivers only accept the beacons signed with it, it is not used with
+     * the service, can be null.
+     */
+    const char* secret;
+    /**
+     * The passphrase of the srt encryption, 10 to 79 characters, it must
+     * match the passphrase of the service, can be null to disable encryption.
+     */
+    const char* passphrase;
+    /**
+     * The length of the encryption key in bytes, 16, 24 or 32, 0 uses the key
+     * length of the service.
+     */
+    uint32_t key_length;
+    /**
+     * Encrypt and authenticate the multicast, the keys are distributed to the
+     * receivers over srt, so the srt encryption should be enabled as well. All
+     * the senders and receivers must use the same setting.
+     */
+    bool encrypt_multicast;
+    /**
+     * Work without the service, the senders announce their streams with a
+     * broadcast on the LAN and the receivers connect to the senders directly.
+     * The server address is not used but must still be valid. All the senders
+     * and receivers must use the same setting.
+     */
+    bool direct;
 } MirrorOptions;
 
+typedef struct
+{
+    /**
+     * The address of the service, e.g. `192.168.1.10:8080`, it can be used as
+     * `MirrorOptions.server`.
+     */
+    const char* server;
+    /**
+     * The multicast group suggested by the service, it can be used as
+     * `MirrorOptions.multicast`.
+     */
+    const char* multicast;
+    size_t mtu;
+    /**
+     * The port of the srt server of the service, on the host of `server`.
+     */
+    uint16_t srt_port;
+} DiscoveredServer;
+
+typedef struct
+{
+    DiscoveredServer* items;
+    size_t capacity;
+    size_t size;
+} DiscoveredServers;
+
+/**
+ * A published channel, the strings are null and the numbers are 0 when the
+ * publisher did not describe the channel, its video or its audio.
+ */
+typedef struct
+{
+    int id;
+    const char* title;
+    const char* video_codec;
+    uint32_t width;
+    uint32_t height;
+    uint8_t frame_rate;
+    const char* audio_codec;
+    uint32_t sample_rate;
+    uint8_t channels;
+    /**
+     * The channel whose simulcast layer this channel publishes, which is its
+     * own id for the channel itself, -1 if the channel has no layers.
+     */
+    int simulcast;
+} PublishedChannel;
+
+typedef struct
+{
+    PublishedChannel* items;
+    size_t capacity;
+    size_t size;
+} PublishedChannels;
+
 typedef const void* Mirror;
 typedef const void* Sender;
 typedef const void* Receiver;
 
+typedef enum
+{
+    Srt = 0,
+    Multicast = 1,
+} TransportMode;
+
+/**
+ * The connection whose state has changed, the id passed with it is the
+ * channel id of the sender or the receiver, it is 0 for the signal
+ * connection.
+ */
+typedef enum
+{
+    SignalConnection = 0,
+    SenderConnection = 1,
+    ReceiverConnection = 2,
+} ConnectionKind;
+
+/**
+ * The state of a connection, a lost connection is reestablished with
+ * exponential backoff. A receiver is disconnected when its channel is not
+ * published again in time, its close callback is called as well.
+ */
+typedef enum
+{
+    Connected = 0,
+    Reconnecting = 1,
+    Disconnected = 2,
+} ConnectionState;
+
+typedef enum
+{
+    /**
+     * The channel is published, it is reported again when the publisher
+     * describes the channel, or after the connection to the service is
+     * reestablished.
+     */
+    ChannelPublished = 0,
+    /**
+     * The channel is stopped by its publisher.
+     */
+    ChannelStopped = 1,
+    /**
+     * The receiver of the channel joined the multicast of the publisher.
+     */
+    MulticastStarted = 2,
+    /**
+     * The receiver of the channel lost packets, the stream is paused until
+     * the next key frame.
+     */
+    PacketLoss = 3,
+    /**
+     * The sender of the channel switched the transport in auto mode, the mode
+     * of the event is the new transport.
+     */
+    ModeSwitched = 4,
+} TransportEventKind;
+
+/**
+ * An event of the channels and the streams, the id is the channel id, the
+ * mode is only meaningful for ModeSwitched.
+ */
+typedef struct
+{
+    TransportEventKind kind;
+    int id;
+    TransportMode mode;
+} TransportEvent;
+
+typedef enum
+{
+    /**
+     * The connection is not encrypted.
+     */
+    Unsecured = 0,
+    /**
+     * The keys are being exchanged.
+     */
+    Securing = 1,
+    /**
+     * The connection is encrypted.
+     */
+    Secured = 2,
+    /**
+     * The service encrypts the connection but no passphrase is set.
+     */
+    NoSecret = 3,
+    /**
+     * The passphrase does not match the passphrase of the service.
+     */
+    BadSecret = 4,
+} EncryptionState;
+
+typedef struct
+{
+    /**
+     * The transport currently carrying the audio and video data.
+     */
+    TransportMode mode;
+    /**
+     * Round trip time of the srt connection in milliseconds.
+     */
+    double rtt;
+    /**
+     * Bitrate of the audio and video data sent and received since the
+     * previous query, in Mbps.
+     */
+    double send_bitrate;
+    double recv_bitrate;
+    /**
+     * The total number of packets lost, both in srt and multicast.
+     */
+    uint64_t lost_packets;
+    /**
+     * The total number of packets retransmitted by srt, repaired by the
+     * multicast sender or rebuilt by forward error correction.
+     */
+    uint64_t retransmitted_packets;
+    /**
+     * The total number of packets dropped because the checksum does not
+     * match.
+     */
+    uint64_t checksum_dropped;
+    /**
+     * The total number of frames dropped by the receiver while waiting for a
+     * key frame after packet loss.
+     */
+    uint64_t filtered_frames;
+    /**
+     * The current delay of the multicast jitter buffer in milliseconds.
+     */
+    uint32_t jitter_delay;
+    /**
+     * The encryption state of the srt connection.
+     */
+    EncryptionState encryption;
+    /**
+     * The total number of packets dropped because the sender packed them with
+     * a version this receiver does not support, the sender and the receiver
+     * have to be upgraded to the same version.
+     */
+    uint64_t incompatible_dropped;
+} StreamStats;
+
 typedef struct
 {
     /**
@@ -231,6 +515,9 @@ EXPORT bool mirror_load();
 
 /**
  * Create mirror.
+ *
+ * Returns null when it fails, such as when the service cannot be reached or
+ * does not support the protocol version of the SDK, the reason is logged.
  */
 EXPORT Mirror mirror_create(MirrorOptions options);
 
@@ -239,6 +526,53 @@ EXPORT Mirror mirror_create(MirrorOptions options);
  */
 EXPORT void mirror_destroy(Mirror mirror);
 
+/**
+ * Set the callback called when a connection of the mirror is lost or
+ * reestablished. The callback is called on the threads of the transport, be
+ * careful not to call blocking methods inside the callback.
+ */
+EXPORT void mirror_set_connection_state_callback(Mirror mirror, void (*callback)(void* ctx, ConnectionKind kind, int id, ConnectionState state), void* ctx);
+
+/**
+ * Set the handler called with the events of the channels and the streams,
+ * such as the channels published and stopped, so that the application can
+ * show the channel list and the state of the streams. The handler is called
+ * on the threads of the transport, be careful not to call blocking methods
+ * inside the handler.
+ */
+EXPORT void mirror_set_event_handler(Mirror mirror, void (*handler)(void* ctx, const TransportEvent* event), void* ctx);
+
+/**
+ * Find the services on the LAN, blocks for the timeout in milliseconds.
+ */
+EXPORT DiscoveredServers mirror_discover_servers(uint32_t timeout);
+
+/**
+ * Because `DiscoveredServers` are allocated internally, they also need to be
+ * released internally.
+ */
+EXPORT void mirror_discovered_servers_destroy(DiscoveredServers* servers);
+
+/**
+ * Get the channels currently published, sorted by id, in direct mode these
+ * are the senders announced on the LAN.
+ */
+EXPORT PublishedChannels mirror_get_channels(Mirror mirror);
+
+/**
+ * Because `PublishedChannels` are allocated internally, they also need to be
+ * released internally.
+ */
+EXPORT void mirror_channels_destroy(PublishedChannels* channels);
+
+/**
+ * Receive a simulcast layer of the channel, the id of the channel publishing
+ * the layer, or the id of the channel itself to receive it again. It applies
+ * to all the receivers of the channel, the receivers are switched at the
+ * next key frame of the layer.
+ */
+EXPORT bool mirror_select_layer(Mirror mirror, int id, int layer);
+
 #ifndef MACOS
 
 /**
@@ -269,6 +603,32 @@ EXPORT bool mirror_sender_get_multicast(Sender sender);
  */
 EXPORT void mirror_sender_set_multicast(Sender sender, bool is_multicast);
 
+/**
+ * Set whether the sender switches between multicast and srt on its own, based
+ * on the loss reported by the receivers and the srt statistics. In auto mode
+ * the transport set by `mirror_sender_set_multicast` may be overridden at any
+ * time.
+ */
+EXPORT void mirror_sender_set_auto_switch(Sender sender, bool enable);
+
+/**
+ * Get whether the sender switches the transport on its own.
+ */
+EXPORT bool mirror_sender_get_auto_switch(Sender sender);
+
+/**
+ * Set the callback called when the sender switches the transport in auto
+ * mode. The callback is called on the sending thread, be careful not to call
+ * blocking methods inside the callback.
+ */
+EXPORT void mirror_sender_set_switch_callback(Sender sender, void (*callback)(void* ctx, TransportMode mode), void* ctx);
+
+/**
+ * Get the network statistics of the sender, the bitrate is measured since the
+ * previous call.
+ */
+EXPORT StreamStats mirror_sender_get_stats(Sender sender);
+
 /**
  * Close sender.
  */
@@ -279,8 +639,23 @@ EXPORT void mirror_sender_destroy(Sender sender);
 /**
  * Create a receiver, specify a bound NIC address, you can pass callback to
  * get the sender's screen or sound callback, callback can not be null.
+ *
+ * The latency is the target delay between receiving and presenting the audio
+ * and video frames in milliseconds.
+ */
+EXPORT Receiver mirror_create_receiver(Mirror mirror, int id, const char* codec, uint32_t latency, FrameSink sink);
+
+/**
+ * Get the difference between the presented video and the played audio of the
+ * receiver in milliseconds, positive when the video is ahead of the audio.
+ */
+EXPORT int32_t mirror_receiver_get_av_offset(Receiver receiver);
+
+/**
+ * Get the network statistics of the receiver, the bitrate is measured since
+ * the previous call.
  */
-EXPORT Receiver mirror_create_receiver(Mirror mirror, int id, const char* codec, FrameSink sink);
+EXPORT StreamStats mirror_receiver_get_stats(Receiver receiver);
 
 /**
  * Close receiver.
@@ -0,0 +1,150 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A video frame later than this is dropped instead of being presented.
+const MAX_VIDEO_LATE: Duration = Duration::from_millis(40);
+
+/// An audio frame later than this drags the clock along, audio frames are
+/// never dropped, a gap in the sound is much more noticeable than a skipped
+/// video frame.
+const MAX_AUDIO_LATE: Duration = Duration::from_millis(40);
+
+/// Without audio, the clock is moved when the video is later than this, so
+/// that an increased network delay does not make all the frames late.
+const MAX_WALL_CLOCK_LATE: Duration = Duration::from_millis(200);
+
+/// A frame to be presented further in the future than the target latency plus
+/// this means the timestamps have jumped, usually because the sender has
+/// restarted, and the clock starts over.
+const MAX_AHEAD: Duration = Duration::from_secs(1);
+
+/// The audio is the master of the clock as long as it has been played within
+/// this time.
+const AUDIO_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub enum Schedule {
+    /// Present the frame after waiting for this long.
+    Wait(Duration),
+    /// The frame is too late and should be dropped.
+    Drop,
+}
+
+#[derive(Default)]
+struct State {
+    // A timestamp and the time when it was received, every other timestamp is
+    // presented relative to it.
+    anchor: Option<(u64, Instant)>,
+    // The last played audio timestamp and when it was played.
+    audio: Option<(u64, Instant)>,
+    // The difference between the last presented video frame and the audio
+    // played at the same time, in microseconds.
+    offset: i64,
+}
+
+impl State {
+    fn deadline(&mut self, timestamp: u64, latency: Duration, now: Instant) -> Instant {
+        let (base, time) = *self.anchor.get_or_insert((timestamp, now));
+
+        // The timestamps have gone backwards, start over.
+        if timestamp < base {
+            self.anchor = Some((timestamp, now));
+            return now + latency;
+        }
+
+        let deadline = time + Duration::from_micros(timestamp - base) + latency;
+        if deadline > now + latency + MAX_AHEAD {
+            self.anchor = Some((timestamp, now));
+            return now + latency;
+        }
+
+        deadline
+    }
+
+    // Move the clock so that the timestamp is presented now.
+    fn slip(&mut self, timestamp: u64, latency: Duration, now: Instant) {
+        self.anchor = Some((timestamp, now.checked_sub(latency).unwrap_or(now)));
+    }
+}
+
+/// Presentation clock of the receiver.
+///
+/// The timestamps of the audio and video packets are the capture time of the
+/// sender in microseconds, the clock maps them to the local time and holds
+/// each frame until its presentation time, which is the target latency after
+/// the first frame received.
+///
+/// The audio is the master, when the audio is late the clock follows it, and
+/// the video frames are scheduled against the audio playout. Without audio the
+/// video is scheduled against the wall clock.
+pub struct PresentationClock {
+    latency: Duration,
+    state: Mutex<State>,
+}
+
+impl PresentationClock {
+    /// `latency` is the target delay between receiving and presenting a
+    /// frame in milliseconds.
+    pub fn new(latency: u32) -> Self {
+        Self {
+            latency: Duration::from_millis(latency as u64),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Schedule an audio frame, audio frames are always played, the returned
+    /// duration is how long to wait before playing it.
+    pub fn audio(&self, timestamp: u64) -> Duration {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let deadline = state.deadline(timestamp, self.latency, now);
+        if now > deadline + MAX_AUDIO_LATE {
+            state.slip(timestamp, self.latency, now);
+        }
+
+        let wait = deadline.saturating_duration_since(now);
+        state.audio = Some((timestamp, now + wait));
+        wait
+    }
+
+    /// Schedule a video frame.
+    pub fn video(&self, timestamp: u64) -> Schedule {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let deadline = state.deadline(timestamp, self.latency, now);
+        let audio = state
+            .audio
+            .filter(|(_, time)| now.saturating_duration_since(*time) < AUDIO_TIMEOUT);
+
+        if audio.is_none() && now > deadline + MAX_WALL_CLOCK_LATE {
+            state.slip(timestamp, self.latency, now);
+            return Schedule::Wait(Duration::ZERO);
+        }
+
+        if now > deadline + MAX_VIDEO_LATE {
+            return Schedule::Drop;
+        }
+
+        // The audio being played when this frame is presented.
+        if let Some((audio, time)) = audio {
+            let position = if deadline > time {
+                audio + (deadline - time).as_micros() as u64
+            } else {
+                audio.saturating_sub((time - deadline).as_micros() as u64)
+            };
+
+            state.offset = timestamp as i64 - position as i64;
+        }
+
+        Schedule::Wait(deadline.saturating_duration_since(now))
+    }
+
+    /// The difference between the presented video and the played audio in
+    /// milliseconds, positive when the video is ahead of the audio.
+    pub fn offset(&self) -> i32 {
+        (self.state.lock().unwrap().offset / 1000) as i32
+    }
+}
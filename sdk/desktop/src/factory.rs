@@ -5,7 +5,7 @@ use crate::sender::{Sender, SenderOptions};
 
 use anyhow::Result;
 use frame::{AudioFrame, VideoFrame};
-use transport::{Transport, TransportOptions};
+use transport::{ChannelInfo, ConnectionState, Event, Link, Transport, TransportOptions};
 use utils::logger;
 
 #[cfg(target_os = "windows")]
@@ -141,6 +141,35 @@ impl Mirror {
         Ok(Self(Transport::new(options)?))
     }
 
+    /// Get the channels currently published.
+    pub fn channels(&self) -> Vec<ChannelInfo> {
+        self.0.channels()
+    }
+
+    /// Receive a simulcast layer of the channel, or the channel itself again.
+    pub fn select_layer(&self, id: u32, layer: u32) -> Result<()> {
+        self.0.select_layer(id, layer)?;
+        Ok(())
+    }
+
+    /// Set the handler called when a connection is lost, reestablished, or
+    /// given up.
+    pub fn set_state_handler<F>(&self, handler: F)
+    where
+        F: Fn(Link, ConnectionState) + Send + Sync + 'static,
+    {
+        self.0.set_state_handler(handler);
+    }
+
+    /// Set the handler called with the events of the channels and the
+    /// streams.
+    pub fn set_event_handler<F>(&self, handler: F)
+    where
+        F: Fn(Event) + Send + Sync + 'static,
+    {
+        self.0.set_event_handler(handler);
+    }
+
     /// Create a sender, specify a bound NIC address, you can pass callback to
     /// get the device screen or sound callback, callback can be null, if it is
     /// null then it means no callback data is needed.
@@ -153,8 +182,14 @@ impl Mirror {
     ) -> Result<Sender> {
         log::info!("create sender: id={}, options={:?}", id, options);
 
-        let sender = Sender::new(options, sink)?;
+        let sender = Sender::new(id, options, sink)?;
         self.0.create_sender(id, &sender.adapter)?;
+
+        // Every simulcast layer is published as its own channel.
+        for (id, adapter) in &sender.layers {
+            self.0.create_sender(*id, adapter)?;
+        }
+
         Ok(sender)
     }
 
@@ -1,3 +1,4 @@
+mod clock;
 mod factory;
 mod receiver;
 
@@ -5,16 +6,16 @@ mod receiver;
 mod sender;
 
 use std::{
-    ffi::{c_char, c_int},
+    ffi::{c_char, c_int, CString},
     fmt::Debug,
-    ptr::null_mut,
+    mem::ManuallyDrop,
+    ptr::{null, null_mut},
     sync::atomic::AtomicBool,
+    time::Duration,
 };
 
-#[cfg(not(target_os = "macos"))]
-use std::{ffi::CString, mem::ManuallyDrop};
-
 use frame::{AudioFrame, VideoFrame};
+use transport::{Event, Link, SIMULCAST_TAG};
 use utils::{atomic::EasyAtomic, strings::Strings};
 
 #[cfg(not(target_os = "macos"))]
@@ -71,12 +72,42 @@ pub extern "C" fn mirror_find_video_decoder() -> *const c_char {
     unsafe { codec::video::codec_find_video_decoder() }
 }
 
+/// The size of the buffer the token callback writes into.
+const TOKEN_BUFFER_SIZE: usize = 1024;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct MirrorOptions {
     pub server: *const c_char,
     pub multicast: *const c_char,
+    pub interface_index: u32,
+    pub min_jitter_delay: u32,
+    pub max_jitter_delay: u32,
     pub mtu: usize,
+    pub fec: u8,
+    /// Writes the token of a channel and role into the buffer and returns its
+    /// length, 0 for no token, can be null if the service does not require
+    /// authentication.
+    pub token: Option<
+        extern "C" fn(
+            ctx: usize,
+            id: u32,
+            publisher: bool,
+            token: *mut c_char,
+            size: usize,
+        ) -> usize,
+    >,
+    pub token_ctx: usize,
+    /// The shared secret of the peers in direct mode, can be null.
+    pub secret: *const c_char,
+    /// The passphrase of the srt encryption, can be null to disable
+    /// encryption.
+    pub passphrase: *const c_char,
+    pub key_length: u32,
+    /// Encrypt the multicast, the keys are distributed over srt.
+    pub encrypt_multicast: bool,
+    /// Work without the service, `server` is ignored.
+    pub direct: bool,
 }
 
 impl TryInto<transport::TransportOptions> for MirrorOptions {
@@ -86,7 +117,43 @@ impl TryInto<transport::TransportOptions> for MirrorOptions {
         Ok(transport::TransportOptions {
             multicast: Strings::from(self.multicast).to_string()?.parse()?,
             server: Strings::from(self.server).to_string()?.parse()?,
+            interface: self.interface_index,
+            min_jitter_delay: self.min_jitter_delay,
+            max_jitter_delay: self.max_jitter_delay,
             mtu: self.mtu,
+            fec: self.fec,
+            tokens: self.token.map(|token| {
+                let ctx = self.token_ctx;
+                transport::TokenProvider::new(move |id, kind| {
+                    let mut buf = [0u8; TOKEN_BUFFER_SIZE];
+                    let size = token(
+                        ctx,
+                        id,
+                        kind == transport::SocketKind::Publisher,
+                        buf.as_mut_ptr() as *mut c_char,
+                        buf.len(),
+                    );
+
+                    if size == 0 || size > buf.len() {
+                        None
+                    } else {
+                        std::str::from_utf8(&buf[..size]).ok().map(String::from)
+                    }
+                })
+            }),
+            secret: if self.secret.is_null() {
+                None
+            } else {
+                Some(Strings::from(self.secret).to_string()?)
+            },
+            passphrase: if self.passphrase.is_null() {
+                None
+            } else {
+                Some(Strings::from(self.passphrase).to_string()?)
+            },
+            key_length: self.key_length,
+            encrypt_multicast: self.encrypt_multicast,
+            direct: self.direct,
         })
     }
 }
@@ -115,6 +182,306 @@ pub extern "C" fn mirror_destroy(mirror: *const Mirror) {
     drop(unsafe { Box::from_raw(mirror as *mut Mirror) });
 }
 
+/// The connection whose state has changed, the id is the channel id of the
+/// sender or the receiver, it is 0 for the signal connection.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectionKind {
+    Signal = 0,
+    Sender = 1,
+    Receiver = 2,
+}
+
+/// The state of a connection, a lost connection is reestablished with
+/// exponential backoff. A receiver is disconnected when its channel is not
+/// published again in time, its close callback is called as well.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectionState {
+    Connected = 0,
+    Reconnecting = 1,
+    Disconnected = 2,
+}
+
+impl From<transport::ConnectionState> for ConnectionState {
+    fn from(value: transport::ConnectionState) -> Self {
+        match value {
+            transport::ConnectionState::Connected => Self::Connected,
+            transport::ConnectionState::Reconnecting => Self::Reconnecting,
+            transport::ConnectionState::Disconnected => Self::Disconnected,
+        }
+    }
+}
+
+/// Set the callback called when a connection of the mirror is lost or
+/// reestablished. The callback is called on the threads of the transport, be
+/// careful not to call blocking methods inside the callback.
+#[no_mangle]
+pub extern "C" fn mirror_set_connection_state_callback(
+    mirror: *const Mirror,
+    callback: Option<
+        extern "C" fn(ctx: usize, kind: ConnectionKind, id: c_int, state: ConnectionState),
+    >,
+    ctx: usize,
+) {
+    assert!(!mirror.is_null());
+
+    log::info!("extern api: mirror set connection state callback");
+    unsafe { &*mirror }.0.set_state_handler(move |link, state| {
+        let (kind, id) = match link {
+            Link::Signal => (ConnectionKind::Signal, 0),
+            Link::Sender(id) => (ConnectionKind::Sender, id),
+            Link::Receiver(id) => (ConnectionKind::Receiver, id),
+        };
+
+        if let Some(callback) = &callback {
+            callback(ctx, kind, id as c_int, state.into());
+        }
+    });
+}
+
+/// The kind of an event of the channels and the streams.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum TransportEventKind {
+    /// The channel is published, it is reported again when the publisher
+    /// describes the channel, or after the connection to the service is
+    /// reestablished.
+    ChannelPublished = 0,
+    /// The channel is stopped by its publisher.
+    ChannelStopped = 1,
+    /// The receiver of the channel joined the multicast of the publisher.
+    MulticastStarted = 2,
+    /// The receiver of the channel lost packets, the stream is paused until
+    /// the next key frame.
+    PacketLoss = 3,
+    /// The sender of the channel switched the transport in auto mode, the mode
+    /// of the event is the new transport.
+    ModeSwitched = 4,
+}
+
+/// An event of the channels and the streams, the id is the channel id, the
+/// mode is only meaningful for `ModeSwitched`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TransportEvent {
+    pub kind: TransportEventKind,
+    pub id: c_int,
+    pub mode: TransportMode,
+}
+
+impl From<Event> for TransportEvent {
+    fn from(value: Event) -> Self {
+        let (kind, id, mode) = match value {
+            Event::ChannelPublished { id } => (TransportEventKind::ChannelPublished, id, None),
+            Event::ChannelStopped { id } => (TransportEventKind::ChannelStopped, id, None),
+            Event::MulticastStarted { id, .. } => (TransportEventKind::MulticastStarted, id, None),
+            Event::PacketLoss { id } => (TransportEventKind::PacketLoss, id, None),
+            Event::ModeSwitched { id, mode } => {
+                (TransportEventKind::ModeSwitched, id, Some(mode.into()))
+            }
+        };
+
+        Self {
+            mode: mode.unwrap_or(TransportMode::Srt),
+            id: id as c_int,
+            kind,
+        }
+    }
+}
+
+/// Set the handler called with the events of the channels and the streams,
+/// such as the channels published and stopped, so that the application can
+/// show the channel list and the state of the streams. The handler is called
+/// on the threads of the transport, be careful not to call blocking methods
+/// inside the handler.
+#[no_mangle]
+pub extern "C" fn mirror_set_event_handler(
+    mirror: *const Mirror,
+    handler: Option<extern "C" fn(ctx: usize, event: *const TransportEvent)>,
+    ctx: usize,
+) {
+    assert!(!mirror.is_null());
+
+    log::info!("extern api: mirror set event handler");
+    unsafe { &*mirror }.0.set_event_handler(move |event| {
+        if let Some(handler) = &handler {
+            let event: TransportEvent = event.into();
+            handler(ctx, &event);
+        }
+    });
+}
+
+/// A published channel, the strings are null and the numbers are 0 when the
+/// publisher did not describe the channel, its video or its audio.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PublishedChannel {
+    id: c_int,
+    title: *const c_char,
+    video_codec: *const c_char,
+    width: u32,
+    height: u32,
+    frame_rate: u8,
+    audio_codec: *const c_char,
+    sample_rate: u32,
+    channels: u8,
+    /// The channel whose simulcast layer this channel publishes, which is its
+    /// own id for the channel itself, -1 if the channel has no layers.
+    simulcast: c_int,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PublishedChannels {
+    items: *mut PublishedChannel,
+    capacity: usize,
+    size: usize,
+}
+
+/// Get the channels currently published, sorted by id, in direct mode these
+/// are the senders announced on the LAN.
+#[no_mangle]
+pub extern "C" fn mirror_get_channels(mirror: *const Mirror) -> PublishedChannels {
+    assert!(!mirror.is_null());
+
+    log::info!("extern api: mirror get channels");
+
+    let string = |value: Option<String>| {
+        value
+            .and_then(|it| CString::new(it).ok())
+            .map(|it| it.into_raw() as *const c_char)
+            .unwrap_or_else(null)
+    };
+
+    let mut items = ManuallyDrop::new(
+        unsafe { &*mirror }
+            .0
+            .channels()
+            .into_iter()
+            .map(|item| {
+                let description = item.description.unwrap_or_default();
+                let simulcast = description
+                    .tags
+                    .get(SIMULCAST_TAG)
+                    .and_then(|it| it.parse::<c_int>().ok())
+                    .unwrap_or(-1);
+
+                let video = description.video.unwrap_or_default();
+                let audio = description.audio.unwrap_or_default();
+
+                PublishedChannel {
+                    id: item.id as c_int,
+                    title: string(Some(description.title).filter(|it| !it.is_empty())),
+                    video_codec: string(Some(video.codec).filter(|it| !it.is_empty())),
+                    width: video.width,
+                    height: video.height,
+                    frame_rate: video.frame_rate,
+                    audio_codec: string(Some(audio.codec).filter(|it| !it.is_empty())),
+                    sample_rate: audio.sample_rate,
+                    channels: audio.channels,
+                    simulcast,
+                }
+            })
+            .collect::<Vec<PublishedChannel>>(),
+    );
+
+    PublishedChannels {
+        items: items.as_mut_ptr(),
+        capacity: items.capacity(),
+        size: items.len(),
+    }
+}
+
+/// Because `PublishedChannels` are allocated internally, they also need to be
+/// released internally.
+#[no_mangle]
+pub extern "C" fn mirror_channels_destroy(channels: *const PublishedChannels) {
+    assert!(!channels.is_null());
+
+    let channels = unsafe { &*channels };
+    for item in unsafe { Vec::from_raw_parts(channels.items, channels.size, channels.capacity) } {
+        for value in [item.title, item.video_codec, item.audio_codec] {
+            if !value.is_null() {
+                drop(unsafe { CString::from_raw(value as *mut _) });
+            }
+        }
+    }
+}
+
+/// Receive a simulcast layer of the channel, the id of the channel publishing
+/// the layer, or the id of the channel itself to receive it again. It applies
+/// to all the receivers of the channel, the receivers are switched at the
+/// next key frame of the layer.
+#[no_mangle]
+pub extern "C" fn mirror_select_layer(mirror: *const Mirror, id: c_int, layer: c_int) -> bool {
+    assert!(!mirror.is_null());
+
+    log::info!(
+        "extern api: mirror select layer, id={}, layer={}",
+        id,
+        layer
+    );
+    checker(unsafe { &*mirror }.0.select_layer(id as u32, layer as u32)).is_ok()
+}
+
+/// A service found on the LAN, the server and the multicast can be used as
+/// the options of the mirror.
+#[repr(C)]
+#[derive(Debug)]
+pub struct DiscoveredServer {
+    server: *const c_char,
+    multicast: *const c_char,
+    mtu: usize,
+    srt_port: u16,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct DiscoveredServers {
+    items: *mut DiscoveredServer,
+    capacity: usize,
+    size: usize,
+}
+
+/// Find the services on the LAN, blocks for the timeout in milliseconds.
+#[no_mangle]
+pub extern "C" fn mirror_discover_servers(timeout: u32) -> DiscoveredServers {
+    log::info!("extern api: mirror discover servers: timeout={}", timeout);
+
+    let mut items = ManuallyDrop::new(
+        checker(transport::discover(Duration::from_millis(timeout as u64)))
+            .unwrap_or_else(|_| Vec::new())
+            .into_iter()
+            .map(|item| DiscoveredServer {
+                server: CString::new(item.server.to_string()).unwrap().into_raw(),
+                multicast: CString::new(item.multicast.to_string()).unwrap().into_raw(),
+                mtu: item.mtu,
+                srt_port: item.srt_port,
+            })
+            .collect::<Vec<DiscoveredServer>>(),
+    );
+
+    DiscoveredServers {
+        items: items.as_mut_ptr(),
+        capacity: items.capacity(),
+        size: items.len(),
+    }
+}
+
+/// Because `DiscoveredServers` are allocated internally, they also need to be
+/// released internally.
+#[no_mangle]
+pub extern "C" fn mirror_discovered_servers_destroy(servers: *const DiscoveredServers) {
+    assert!(!servers.is_null());
+
+    let servers = unsafe { &*servers };
+    for item in unsafe { Vec::from_raw_parts(servers.items, servers.size, servers.capacity) } {
+        drop(unsafe { CString::from_raw(item.server as *mut _) });
+        drop(unsafe { CString::from_raw(item.multicast as *mut _) });
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 #[cfg(not(target_os = "macos"))]
@@ -254,7 +621,7 @@ pub struct FrameSink {
     pub audio: Option<extern "C" fn(ctx: usize, frame: *const AudioFrame) -> bool>,
     /// Callback when the sender is closed. This may be because the external
     /// side actively calls the close, or the audio and video packets cannot be
-    /// sent (the network is disconnected), etc.
+    /// sent (the network is disconnected and cannot be reconnected), etc.
     pub close: Option<extern "C" fn(ctx: usize)>,
     pub ctx: usize,
 }
@@ -352,6 +719,16 @@ pub struct SenderSourceOptions<T> {
     options: T,
 }
 
+/// An additional rendition of the video of the sender, published as the
+/// channel of the id.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg(not(target_os = "macos"))]
+pub struct SimulcastLayer {
+    id: c_int,
+    options: VideoOptions,
+}
+
 #[repr(C)]
 #[derive(Debug)]
 #[cfg(not(target_os = "macos"))]
@@ -359,6 +736,21 @@ pub struct SenderOptions {
     video: *const SenderSourceOptions<VideoOptions>,
     audio: *const SenderSourceOptions<AudioOptions>,
     multicast: bool,
+    title: *const c_char,
+    layers: *const SimulcastLayer,
+    layers_size: usize,
+}
+
+// Check whether the external parameters are configured correctly to avoid some
+// clowns inserting some inexplicable parameters.
+#[cfg(not(target_os = "macos"))]
+#[rustfmt::skip]
+fn check_video_settings(settings: &codec::VideoEncoderSettings) -> anyhow::Result<()> {
+    anyhow::ensure!(settings.codec == "libx264" || settings.codec == "h264_qsv", "invalid video encoder");
+    anyhow::ensure!(settings.width % 4 == 0 && settings.width <= 4096, "invalid video width");
+    anyhow::ensure!(settings.height % 4 == 0 && settings.height <= 2560, "invalid video height");
+    anyhow::ensure!(settings.frame_rate <= 60, "invalid video frame rate");
+    Ok(())
 }
 
 #[cfg(not(target_os = "macos"))]
@@ -371,20 +763,20 @@ impl TryInto<sender::SenderOptions> for SenderOptions {
     fn try_into(self) -> Result<sender::SenderOptions, Self::Error> {
         let mut options = sender::SenderOptions {
             multicast: self.multicast,
+            layers: Vec::with_capacity(self.layers_size),
             audio: None,
             video: None,
+            title: if self.title.is_null() {
+                None
+            } else {
+                Some(Strings::from(self.title).to_string()?)
+            },
         };
 
         if !self.video.is_null() {
             let video = unsafe { &*self.video };
             let settings: codec::VideoEncoderSettings = video.options.try_into()?;
-
-            // Check whether the external parameters are configured correctly to 
-            // avoid some clowns inserting some inexplicable parameters.
-            anyhow::ensure!(settings.codec == "libx264" || settings.codec == "h264_qsv", "invalid video encoder");
-            anyhow::ensure!(settings.width % 4 == 0 && settings.width <= 4096, "invalid video width");
-            anyhow::ensure!(settings.height % 4 == 0 && settings.height <= 2560, "invalid video height");
-            anyhow::ensure!(settings.frame_rate <= 60, "invalid video frame rate");
+            check_video_settings(&settings)?;
 
             options.video = Some((
                 unsafe { &*video.source }.try_into()?,
@@ -400,10 +792,97 @@ impl TryInto<sender::SenderOptions> for SenderOptions {
             ));
         }
 
+        if !self.layers.is_null() {
+            for layer in unsafe { std::slice::from_raw_parts(self.layers, self.layers_size) } {
+                let settings: codec::VideoEncoderSettings = layer.options.try_into()?;
+                check_video_settings(&settings)?;
+
+                options.layers.push(sender::SimulcastLayer {
+                    id: layer.id as u32,
+                    settings,
+                });
+            }
+        }
+
         Ok(options)
     }
 }
 
+/// The transport currently carrying the audio and video data.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum TransportMode {
+    Srt = 0,
+    Multicast = 1,
+}
+
+/// The encryption state of the srt connection of a stream.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum EncryptionState {
+    Unsecured = 0,
+    Securing = 1,
+    Secured = 2,
+    NoSecret = 3,
+    BadSecret = 4,
+}
+
+/// A snapshot of the network statistics of a stream.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct StreamStats {
+    pub mode: TransportMode,
+    pub rtt: f64,
+    pub send_bitrate: f64,
+    pub recv_bitrate: f64,
+    pub lost_packets: u64,
+    pub retransmitted_packets: u64,
+    pub checksum_dropped: u64,
+    pub filtered_frames: u64,
+    pub jitter_delay: u32,
+    pub encryption: EncryptionState,
+    pub incompatible_dropped: u64,
+}
+
+impl From<transport::stats::TransportMode> for TransportMode {
+    fn from(value: transport::stats::TransportMode) -> Self {
+        match value {
+            transport::stats::TransportMode::Srt => Self::Srt,
+            transport::stats::TransportMode::Multicast => Self::Multicast,
+        }
+    }
+}
+
+impl From<transport::stats::EncryptionState> for EncryptionState {
+    fn from(value: transport::stats::EncryptionState) -> Self {
+        match value {
+            transport::stats::EncryptionState::Unsecured => Self::Unsecured,
+            transport::stats::EncryptionState::Securing => Self::Securing,
+            transport::stats::EncryptionState::Secured => Self::Secured,
+            transport::stats::EncryptionState::NoSecret => Self::NoSecret,
+            transport::stats::EncryptionState::BadSecret => Self::BadSecret,
+        }
+    }
+}
+
+impl From<transport::stats::StreamStats> for StreamStats {
+    fn from(value: transport::stats::StreamStats) -> Self {
+        Self {
+            mode: value.mode.into(),
+            rtt: value.rtt,
+            send_bitrate: value.send_bitrate,
+            recv_bitrate: value.recv_bitrate,
+            lost_packets: value.lost_packets,
+            retransmitted_packets: value.retransmitted_packets,
+            checksum_dropped: value.checksum_dropped,
+            filtered_frames: value.filtered_frames,
+            jitter_delay: value.jitter_delay,
+            encryption: value.encryption.into(),
+            incompatible_dropped: value.incompatible_dropped,
+        }
+    }
+}
+
 #[repr(C)]
 #[cfg(not(target_os = "macos"))]
 pub struct Sender(sender::Sender);
@@ -458,6 +937,59 @@ pub extern "C" fn mirror_sender_get_multicast(sender: *const Sender) -> bool {
     unsafe { &*sender }.0.get_multicast()
 }
 
+/// Set whether the sender switches between multicast and srt on its own,
+/// based on the loss reported by the receivers and the srt statistics. In
+/// auto mode the transport set by `mirror_sender_set_multicast` may be
+/// overridden at any time.
+#[no_mangle]
+#[cfg(not(target_os = "macos"))]
+pub extern "C" fn mirror_sender_set_auto_switch(sender: *const Sender, enable: bool) {
+    assert!(!sender.is_null());
+
+    log::info!("extern api: mirror set sender auto switch={}", enable);
+    unsafe { &*sender }.0.set_auto_switch(enable);
+}
+
+/// Get whether the sender switches the transport on its own.
+#[no_mangle]
+#[cfg(not(target_os = "macos"))]
+pub extern "C" fn mirror_sender_get_auto_switch(sender: *const Sender) -> bool {
+    assert!(!sender.is_null());
+
+    log::info!("extern api: mirror get sender auto switch");
+    unsafe { &*sender }.0.get_auto_switch()
+}
+
+/// Set the callback called when the sender switches the transport in auto
+/// mode. The callback is called on the sending thread, be careful not to call
+/// blocking methods inside the callback.
+#[no_mangle]
+#[cfg(not(target_os = "macos"))]
+pub extern "C" fn mirror_sender_set_switch_callback(
+    sender: *const Sender,
+    callback: Option<extern "C" fn(ctx: usize, mode: TransportMode)>,
+    ctx: usize,
+) {
+    assert!(!sender.is_null());
+
+    log::info!("extern api: mirror set sender switch callback");
+    unsafe { &*sender }.0.set_switch_handler(move |mode| {
+        if let Some(callback) = &callback {
+            callback(ctx, mode.into());
+        }
+    });
+}
+
+/// Get the network statistics of the sender, the bitrate is measured since
+/// the previous call.
+#[no_mangle]
+#[cfg(not(target_os = "macos"))]
+pub extern "C" fn mirror_sender_get_stats(sender: *const Sender) -> StreamStats {
+    assert!(!sender.is_null());
+
+    unsafe { &*sender }.0.get_stats().into()
+}
+
 /// Close sender.
 #[no_mangle]
 #[cfg(not(target_os = "macos"))]
@@ -473,11 +1005,15 @@ pub struct Receiver(receiver::Receiver);
 
 /// Create a receiver, specify a bound NIC address, you can pass callback to
 /// get the sender's screen or sound callback, callback can not be null.
+///
+/// The latency is the target delay between receiving and presenting the audio
+/// and video frames in milliseconds.
 #[no_mangle]
 pub extern "C" fn mirror_create_receiver(
     mirror: *const Mirror,
     id: c_int,
     codec: *const c_char,
+    latency: u32,
     sink: FrameSink,
 ) -> *const Receiver {
     assert!(!mirror.is_null() && !codec.is_null());
@@ -490,6 +1026,7 @@ pub extern "C" fn mirror_create_receiver(
             receiver::ReceiverOptions {
                 video: Strings::from(codec).to_string()?,
                 audio: "libopus".to_string(),
+                latency,
             },
             sink.into(),
         )
@@ -500,6 +1037,24 @@ pub extern "C" fn mirror_create_receiver(
         .unwrap_or_else(|_| null_mut())
 }
 
+/// Get the difference between the presented video and the played audio of the
+/// receiver in milliseconds, positive when the video is ahead of the audio.
+#[no_mangle]
+pub extern "C" fn mirror_receiver_get_av_offset(receiver: *const Receiver) -> i32 {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.0.get_av_offset()
+}
+
+/// Get the network statistics of the receiver, the bitrate is measured since
+/// the previous call.
+#[no_mangle]
+pub extern "C" fn mirror_receiver_get_stats(receiver: *const Receiver) -> StreamStats {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.0.get_stats().into()
+}
+
 /// Close receiver.
 #[no_mangle]
 pub extern "C" fn mirror_receiver_destroy(receiver: *const Receiver) {
@@ -1,10 +1,16 @@
-use crate::factory::FrameSink;
+use crate::{
+    clock::{PresentationClock, Schedule},
+    factory::FrameSink,
+};
 
 use std::{sync::Arc, thread};
 
 use anyhow::Result;
 use codec::{AudioDecoder, VideoDecoder};
-use transport::adapter::{StreamKind, StreamMultiReceiverAdapter, StreamReceiverAdapterExt};
+use transport::{
+    adapter::{StreamKind, StreamMultiReceiverAdapter, StreamReceiverAdapterExt},
+    stats::StreamStats,
+};
 
 #[cfg(target_os = "windows")]
 use utils::win32::MediaThreadClass;
@@ -13,13 +19,19 @@ use utils::win32::MediaThreadClass;
 pub struct ReceiverOptions {
     pub video: String,
     pub audio: String,
+    /// The target delay between receiving and presenting the audio and video
+    /// frames in milliseconds, a larger latency gives the audio and video more
+    /// time to line up.
+    pub latency: u32,
 }
 
 fn create_video_decoder(
     adapter: &Arc<StreamMultiReceiverAdapter>,
+    clock: &Arc<PresentationClock>,
     sink: &Arc<FrameSink>,
     codec: &str,
 ) -> Result<()> {
+    let clock = clock.clone();
     let sink_ = Arc::downgrade(sink);
     let adapter_ = Arc::downgrade(adapter);
     let mut codec = VideoDecoder::new(codec)?;
@@ -34,6 +46,14 @@ fn create_video_decoder(
                 if let Some((packet, flags, timestamp)) = adapter.next(StreamKind::Video) {
                     if codec.decode(&packet, flags, timestamp) {
                         while let Some(frame) = codec.read() {
+                            // Hold the frame until its presentation time, the frames that
+                            // are too late are not presented at all. The decoder may return
+                            // the frames of earlier packets, each frame carries its own pts.
+                            match clock.video(frame.timestamp) {
+                                Schedule::Wait(delay) => thread::sleep(delay),
+                                Schedule::Drop => continue,
+                            }
+
                             if !(sink.video)(frame) {
                                 break 'a;
                             }
@@ -62,9 +82,11 @@ fn create_video_decoder(
 
 fn create_audio_decoder(
     adapter: &Arc<StreamMultiReceiverAdapter>,
+    clock: &Arc<PresentationClock>,
     sink: &Arc<FrameSink>,
     codec: &str,
 ) -> Result<()> {
+    let clock = clock.clone();
     let sink_ = Arc::downgrade(sink);
     let adapter_ = Arc::downgrade(adapter);
     let mut codec = AudioDecoder::new(codec)?;
@@ -79,6 +101,8 @@ fn create_audio_decoder(
                 if let Some((packet, flags, timestamp)) = adapter.next(StreamKind::Audio) {
                     if codec.decode(&packet, flags, timestamp) {
                         while let Some(frame) = codec.read() {
+                            thread::sleep(clock.audio(timestamp));
+
                             if !(sink.audio)(frame) {
                                 break 'a;
                             }
@@ -107,6 +131,7 @@ fn create_audio_decoder(
 
 pub struct Receiver {
     pub(crate) adapter: Arc<StreamMultiReceiverAdapter>,
+    clock: Arc<PresentationClock>,
     sink: Arc<FrameSink>,
 }
 
@@ -114,15 +139,34 @@ impl Receiver {
     /// Create a receiving end. The receiving end is much simpler to implement.
     /// You only need to decode the data in the queue and call it back to the
     /// sink.
+    ///
+    /// The decoded frames are not called back immediately, they are scheduled
+    /// by a presentation clock shared by the audio and video, so that they
+    /// stay in sync.
     pub fn new(options: ReceiverOptions, sink: FrameSink) -> Result<Self> {
         log::info!("create receiver");
 
         let adapter = StreamMultiReceiverAdapter::new();
+        let clock = Arc::new(PresentationClock::new(options.latency));
         let sink = Arc::new(sink);
 
-        create_video_decoder(&adapter, &sink, &options.video)?;
-        create_audio_decoder(&adapter, &sink, &options.audio)?;
-        Ok(Self { adapter, sink })
+        create_video_decoder(&adapter, &clock, &sink, &options.video)?;
+        create_audio_decoder(&adapter, &clock, &sink, &options.audio)?;
+        Ok(Self {
+            adapter,
+            clock,
+            sink,
+        })
+    }
+
+    pub fn get_stats(&self) -> StreamStats {
+        self.adapter.get_stats()
+    }
+
+    /// The difference between the presented video and the played audio in
+    /// milliseconds, positive when the video is ahead of the audio.
+    pub fn get_av_offset(&self) -> i32 {
+        self.clock.offset()
     }
 }
 
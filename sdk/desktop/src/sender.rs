@@ -1,12 +1,14 @@
 use crate::factory::FrameSink;
 
 use std::{
+    collections::BTreeMap,
     mem::size_of,
-    sync::{Arc, Mutex, Weak},
+    sync::{atomic::AtomicU64, Arc, Mutex, Weak},
     thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, ensure, Result};
 use bytes::BytesMut;
 use capture::{
     AudioCaptureSourceDescription, Capture, CaptureOptions, FrameArrived, Size, Source,
@@ -23,18 +25,27 @@ use frame::{AudioFrame, VideoFrame};
 use transport::{
     adapter::{BufferFlag, StreamBufferInfo, StreamSenderAdapter},
     package,
+    stats::{StreamStats, TransportMode},
+    AudioDescription, StreamDescription, VideoDescription, SIMULCAST_TAG,
 };
 
+use utils::atomic::EasyAtomic;
+
 #[cfg(target_os = "windows")]
 use utils::win32::MediaThreadClass;
 
-struct VideoSender {
+// One encoder of the captured video and the channel it is published on.
+struct VideoLayer {
     encoder: Arc<Mutex<VideoEncoder>>,
-    sink: Weak<FrameSink>,
+    // The capture time of the frame in the encoder, it is only accessed with the
+    // encoder locked.
+    timestamp: Arc<AtomicU64>,
+    adapter: Weak<StreamSenderAdapter>,
     unparker: Unparker,
+    epoch: Instant,
 }
 
-impl VideoSender {
+impl VideoLayer {
     // Encoding is a relatively complex task. If you add encoding tasks to the
     // pipeline that pushes frames, it will slow down the entire pipeline.
     //
@@ -45,14 +56,17 @@ impl VideoSender {
         adapter: &Arc<StreamSenderAdapter>,
         settings: &VideoEncoderSettings,
         sink: &Arc<FrameSink>,
+        epoch: Instant,
     ) -> Result<Self> {
         let parker = Parker::new();
         let unparker = parker.unparker().clone();
         let encoder = Arc::new(Mutex::new(VideoEncoder::new(settings)?));
+        let timestamp = Arc::new(AtomicU64::new(0));
 
         let sink_ = Arc::downgrade(sink);
         let adapter_ = Arc::downgrade(adapter);
         let encoder_ = Arc::downgrade(&encoder);
+        let timestamp_ = timestamp.clone();
         thread::Builder::new()
             .name("VideoEncoderThread".to_string())
             .spawn(move || {
@@ -69,10 +83,13 @@ impl VideoSender {
                         // correspond to the data packets one by one, so you need to try to get
                         // multiple packets until they are empty.
                         if encoder.encode() {
+                            // The encoder works with a low delay, so the packets belong to
+                            // the frame just encoded.
+                            let timestamp = timestamp_.get();
                             while let Some(packet) = encoder.read() {
                                 adapter.send(
                                     package::copy_from_slice(packet.buffer),
-                                    StreamBufferInfo::Video(packet.flags, packet.timestamp),
+                                    StreamBufferInfo::Video(packet.flags, timestamp),
                                 );
                             }
                         } else {
@@ -94,22 +111,57 @@ impl VideoSender {
             })?;
 
         Ok(Self {
-            sink: Arc::downgrade(sink),
+            adapter: Arc::downgrade(adapter),
+            timestamp,
             unparker,
             encoder,
+            epoch,
         })
     }
+
+    fn send(&self, frame: &VideoFrame) -> bool {
+        // The frame is stamped when it is captured, not when the encoder gets to it.
+        let timestamp = self.epoch.elapsed().as_micros() as u64;
+
+        {
+            let mut encoder = self.encoder.lock().unwrap();
+
+            // A receiver has lost packets and is waiting for a key frame, so the current
+            // frame is forced to be encoded as a key frame.
+            if let Some(adapter) = self.adapter.upgrade() {
+                if adapter.take_key_frame_request() {
+                    encoder.request_key_frame();
+                }
+            }
+
+            // Push the audio and video frames into the encoder.
+            if !encoder.send_frame(frame) {
+                return false;
+            }
+
+            self.timestamp.update(timestamp);
+        }
+
+        self.unparker.unpark();
+        true
+    }
+}
+
+// Passes the captured video to the encoders of the main channel and of the
+// simulcast layers, each of them encodes the frame at its own size and bit rate.
+struct VideoSender {
+    layers: Vec<VideoLayer>,
+    sink: Weak<FrameSink>,
 }
 
 impl FrameArrived for VideoSender {
     type Frame = VideoFrame;
 
     fn sink(&mut self, frame: &Self::Frame) -> bool {
-        // // Push the audio and video frames into the encoder.
-        if self.encoder.lock().unwrap().send_frame(frame) {
-            self.unparker.unpark();
-        } else {
-            return false;
+        for layer in &self.layers {
+            if !layer.send(frame) {
+                return false;
+            }
         }
 
         if let Some(sink) = self.sink.upgrade() {
@@ -134,45 +186,65 @@ impl AudioSender {
     // Here, the tasks are separated, and the encoding tasks are separated into
     // independent threads. The encoding thread is notified of task updates through
     // the optional lock.
+    //
+    // The audio is encoded once and sent to the main channel and to every simulcast
+    // layer.
     fn new(
-        adapter: &Arc<StreamSenderAdapter>,
+        adapters: &[Arc<StreamSenderAdapter>],
         settings: &AudioEncoderSettings,
         sink: &Arc<FrameSink>,
+        epoch: Instant,
     ) -> Result<Self> {
         // Create an opus header data. The opus decoder needs this data to obtain audio
         // information. Here, actively add an opus header information to the queue, and
         // the transport layer will automatically cache it.
-        adapter.send(
-            package::copy_from_slice(&create_opus_identification_header(
-                1,
-                settings.sample_rate as u32,
-            )),
-            StreamBufferInfo::Audio(BufferFlag::Config as i32, 0),
-        );
+        let header = create_opus_identification_header(1, settings.sample_rate as u32);
+        for adapter in adapters {
+            adapter.send(
+                package::copy_from_slice(&header),
+                StreamBufferInfo::Audio(BufferFlag::Config as i32, 0),
+            );
+        }
 
         let parker = Parker::new();
         let unparker = parker.unparker().clone();
         let mut encoder = AudioEncoder::new(settings)?;
         let buffer = Arc::new(Mutex::new(BytesMut::with_capacity(48000)));
         let chunk_count = settings.sample_rate as usize / 1000 * 100;
+        let sample_rate = settings.sample_rate;
 
         let sink_ = Arc::downgrade(sink);
         let buffer_ = Arc::downgrade(&buffer);
-        let adapter_ = Arc::downgrade(adapter);
+        let adapters_ = adapters.iter().map(Arc::downgrade).collect::<Vec<_>>();
         thread::Builder::new()
             .name("AudioEncoderThread".to_string())
             .spawn(move || {
                 #[cfg(target_os = "windows")]
                 let thread_class_guard = MediaThreadClass::ProAudio.join().ok();
 
+                // The capture time of the first sample, the timestamps of the encoded
+                // packets are counted in samples from it.
+                let mut first_sample = None;
+
                 loop {
                     parker.park();
 
-                    if let (Some(adapter), Some(buffer)) = (adapter_.upgrade(), buffer_.upgrade()) {
+                    let adapters = adapters_
+                        .iter()
+                        .filter_map(Weak::upgrade)
+                        .collect::<Vec<_>>();
+
+                    if let Some(buffer) = buffer_.upgrade().filter(|_| !adapters.is_empty()) {
                         let payload = buffer
                             .lock()
                             .unwrap()
                             .split_to(chunk_count * size_of::<i16>());
+                        let first_sample = *first_sample.get_or_insert_with(|| {
+                            epoch.elapsed().saturating_sub(Duration::from_micros(
+                                chunk_count as u64 * 1_000_000 / sample_rate,
+                            ))
+                        });
+
                         let frame = AudioFrame {
                             data: payload.as_ptr() as *const _,
                             frames: chunk_count as u32,
@@ -187,10 +259,18 @@ impl AudioSender {
                                 // packets one by one, so you need to try to get
                                 // multiple packets until they are empty.
                                 while let Some(packet) = encoder.read() {
-                                    adapter.send(
-                                        package::copy_from_slice(packet.buffer),
-                                        StreamBufferInfo::Audio(packet.flags, packet.timestamp),
-                                    );
+                                    // The encoder may report a negative timestamp for the
+                                    // priming samples.
+                                    let samples = (packet.timestamp as i64).max(0) as u64;
+                                    let timestamp = first_sample.as_micros() as u64
+                                        + samples * 1_000_000 / sample_rate;
+
+                                    for adapter in &adapters {
+                                        adapter.send(
+                                            package::copy_from_slice(packet.buffer),
+                                            StreamBufferInfo::Audio(packet.flags, timestamp),
+                                        );
+                                    }
                                 }
                             } else {
                                 break;
@@ -246,33 +326,124 @@ impl FrameArrived for AudioSender {
     }
 }
 
+/// An additional rendition of the captured video, encoded at its own size and
+/// bit rate and published as its own channel, so that the receivers can pick
+/// the rendition that suits them. It is not larger than the video of the
+/// sender, and carries the same audio.
+///
+/// The descriptions of the main channel and of its layers are tagged with
+/// [`SIMULCAST_TAG`], whose value is the id of the main channel, the
+/// receivers of the main channel can then select a layer, see
+/// [`transport::Transport::select_layer`]. The layers are relayed by the
+/// service, so they are always sent over srt.
+#[derive(Debug, Clone)]
+pub struct SimulcastLayer {
+    pub id: u32,
+    pub settings: VideoEncoderSettings,
+}
+
 #[derive(Debug)]
 pub struct SenderOptions {
     pub video: Option<(Source, VideoEncoderSettings)>,
     pub audio: Option<(Source, AudioEncoderSettings)>,
     pub multicast: bool,
+    pub title: Option<String>,
+    pub layers: Vec<SimulcastLayer>,
 }
 
 pub struct Sender {
     pub(crate) adapter: Arc<StreamSenderAdapter>,
+    pub(crate) layers: Vec<(u32, Arc<StreamSenderAdapter>)>,
     sink: Arc<FrameSink>,
     capture: Capture,
 }
 
+fn describe(
+    title: &Option<String>,
+    video: Option<&VideoEncoderSettings>,
+    audio: Option<&AudioEncoderSettings>,
+) -> StreamDescription {
+    StreamDescription {
+        title: title.clone().unwrap_or_default(),
+        video: video.map(|video| VideoDescription {
+            codec: "h264".to_string(),
+            frame_rate: video.frame_rate,
+            bit_rate: video.bit_rate,
+            height: video.height,
+            width: video.width,
+        }),
+        audio: audio.map(|it| AudioDescription {
+            codec: "opus".to_string(),
+            sample_rate: it.sample_rate as u32,
+            bit_rate: it.bit_rate,
+            channels: 1,
+        }),
+        ..Default::default()
+    }
+}
+
 impl Sender {
     // Create a sender. The capture of the sender is started following the sender,
     // but both video capture and audio capture can be empty, which means you can
     // create a sender that captures nothing.
-    pub fn new(options: SenderOptions, sink: FrameSink) -> Result<Self> {
+    pub fn new(id: u32, options: SenderOptions, sink: FrameSink) -> Result<Self> {
         log::info!("create sender");
 
         let mut capture_options = CaptureOptions::default();
         let adapter = StreamSenderAdapter::new(options.multicast);
         let sink = Arc::new(sink);
 
+        // The receivers get the description with the signal of the channel, before any
+        // audio and video data arrives.
+        let audio = options.audio.as_ref().map(|(_, it)| it);
+        let video = options.video.as_ref().map(|(_, it)| it);
+        let mut description = describe(&options.title, video, audio);
+
+        let mut layers = Vec::with_capacity(options.layers.len());
+        if !options.layers.is_empty() {
+            let video = video.ok_or_else(|| anyhow!("simulcast layers require a video source"))?;
+
+            let tags = BTreeMap::from([(SIMULCAST_TAG.to_string(), id.to_string())]);
+            description.tags = tags.clone();
+
+            for layer in &options.layers {
+                ensure!(
+                    layer.id != id && layers.iter().all(|(it, _)| *it != layer.id),
+                    "duplicate simulcast layer id={}",
+                    layer.id
+                );
+
+                ensure!(
+                    layer.settings.width <= video.width && layer.settings.height <= video.height,
+                    "simulcast layer id={} is larger than the video",
+                    layer.id
+                );
+
+                let adapter = StreamSenderAdapter::new(false);
+                adapter.set_description(StreamDescription {
+                    tags: tags.clone(),
+                    ..describe(&options.title, Some(&layer.settings), audio)
+                });
+
+                layers.push((layer.id, adapter));
+            }
+        }
+
+        adapter.set_description(description);
+
+        // The audio and video timestamps are both the capture time in microseconds
+        // since the sender was created, the receiver uses them to keep the audio and
+        // video in sync.
+        let epoch = Instant::now();
+
+        let adapters = [adapter.clone()]
+            .into_iter()
+            .chain(layers.iter().map(|(_, it)| it.clone()))
+            .collect::<Vec<_>>();
+
         if let Some((source, options)) = options.audio {
             capture_options.audio = Some(SourceCaptureOptions {
-                arrived: AudioSender::new(&adapter, &options, &sink)?,
+                arrived: AudioSender::new(&adapters, &options, &sink, epoch)?,
                 description: AudioCaptureSourceDescription {
                     sample_rate: options.sample_rate as u32,
                     source,
@@ -280,15 +451,23 @@ impl Sender {
             });
         }
 
-        if let Some((source, options)) = options.video {
+        if let Some((source, settings)) = options.video {
+            let mut encoders = vec![VideoLayer::new(&adapter, &settings, &sink, epoch)?];
+            for ((_, adapter), layer) in layers.iter().zip(&options.layers) {
+                encoders.push(VideoLayer::new(adapter, &layer.settings, &sink, epoch)?);
+            }
+
             capture_options.video = Some(SourceCaptureOptions {
-                arrived: VideoSender::new(&adapter, &options, &sink)?,
+                arrived: VideoSender {
+                    sink: Arc::downgrade(&sink),
+                    layers: encoders,
+                },
                 description: VideoCaptureSourceDescription {
-                    fps: options.frame_rate,
+                    fps: settings.frame_rate,
                     source,
                     size: Size {
-                        width: options.width,
-                        height: options.height,
+                        width: settings.width,
+                        height: settings.height,
                     },
                 },
             });
@@ -297,6 +476,7 @@ impl Sender {
         Ok(Self {
             capture: Capture::new(capture_options)?,
             adapter,
+            layers,
             sink,
         })
     }
@@ -308,6 +488,26 @@ impl Sender {
     pub fn set_multicast(&self, multicast: bool) {
         self.adapter.set_multicast(multicast)
     }
+
+    pub fn get_auto_switch(&self) -> bool {
+        self.adapter.get_auto_switch()
+    }
+
+    pub fn set_auto_switch(&self, enable: bool) {
+        self.adapter.set_auto_switch(enable)
+    }
+
+    pub fn set_switch_handler<F>(&self, handler: F)
+    where
+        F: Fn(TransportMode) + Send + Sync + 'static,
+    {
+        self.adapter.set_switch_handler(handler)
+    }
+
+    /// The statistics of the main channel.
+    pub fn get_stats(&self) -> StreamStats {
+        self.adapter.get_stats()
+    }
 }
 
 impl Drop for Sender {
@@ -324,6 +524,10 @@ impl Drop for Sender {
         }
 
         self.adapter.close();
+        for (_, adapter) in &self.layers {
+            adapter.close();
+        }
+
         (self.sink.close)()
     }
 }
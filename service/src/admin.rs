@@ -0,0 +1,220 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use service::{auth::Secret, description::StreamDescription, route::Route};
+use sha2::Sha256;
+use srt::Socket;
+use tokio::net::TcpListener;
+
+use crate::proxy::Relay;
+
+#[derive(Debug, Serialize)]
+struct ConnectionStats {
+    /// Round trip time in milliseconds.
+    rtt: f64,
+    /// Sending and receiving rate in Mbps.
+    send_rate: f64,
+    recv_rate: f64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_sent: i64,
+    packets_received: i64,
+    packets_send_lost: i32,
+    packets_recv_lost: i32,
+    packets_retransmitted: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct Connection {
+    addr: SocketAddr,
+    stats: Option<ConnectionStats>,
+}
+
+impl Connection {
+    fn new(addr: SocketAddr, socket: &Socket) -> Self {
+        Self {
+            stats: socket.get_stats().ok().map(|trace| ConnectionStats {
+                rtt: trace.ms_rtt,
+                send_rate: trace.mbps_send_rate,
+                recv_rate: trace.mbps_recv_rate,
+                bytes_sent: trace.byte_sent_total,
+                bytes_received: trace.byte_recv_total,
+                packets_sent: trace.pkt_sent_total,
+                packets_received: trace.pkt_recv_total,
+                packets_send_lost: trace.pkt_snd_loss_total,
+                packets_recv_lost: trace.pkt_rcv_loss_total,
+                packets_retransmitted: trace.pkt_retrans_total,
+            }),
+            addr,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Channel {
+    id: u32,
+    port: u16,
+    multicast: Option<IpAddr>,
+    description: Option<StreamDescription>,
+    publisher: Option<Connection>,
+    subscribers: Vec<Connection>,
+}
+
+struct Context {
+    route: Arc<Route>,
+    relay: Arc<Relay>,
+    token: Arc<Secret>,
+    // Whether the api is only reachable from the host of the service.
+    loopback: bool,
+}
+
+impl Context {
+    fn get_channels(&self) -> Vec<Channel> {
+        self.route
+            .get_channels()
+            .into_iter()
+            .map(|(id, port, multicast, description)| Channel {
+                publisher: self
+                    .relay
+                    .get_publisher(id)
+                    .map(|(addr, socket)| Connection::new(addr, &socket)),
+                subscribers: self
+                    .relay
+                    .get_subscribers(id)
+                    .into_iter()
+                    .map(|(addr, socket)| Connection::new(addr, &socket))
+                    .collect(),
+                description,
+                multicast,
+                port,
+                id,
+            })
+            .collect()
+    }
+}
+
+// Whether the bearer token is the admin token. The macs of both are compared in
+// constant time, so the time taken does not tell how much of the token matches.
+fn is_token(token: &str, bearer: &str) -> bool {
+    let mac = |it: &str| {
+        Hmac::<Sha256>::new_from_slice(token.as_bytes())
+            .expect("hmac can take a key of any size")
+            .chain_update(it)
+    };
+
+    mac(bearer)
+        .verify_slice(&mac(token).finalize().into_bytes())
+        .is_ok()
+}
+
+// When the service is configured with an admin token, every request has to
+// carry it as a bearer token. Without the token the api is only served on a
+// loopback address, the token may have been removed by a reload since the start.
+async fn authorize(State(ctx): State<Arc<Context>>, req: Request, next: Next) -> Response {
+    if let Some(token) = ctx.token.get() {
+        let authorized = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|it| it.to_str().ok())
+            .and_then(|it| it.strip_prefix("Bearer "))
+            .map(|it| is_token(&token, it))
+            .unwrap_or(false);
+
+        if !authorized {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    } else if !ctx.loopback {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    next.run(req).await
+}
+
+async fn get_channels(State(ctx): State<Arc<Context>>) -> Json<Vec<Channel>> {
+    Json(ctx.get_channels())
+}
+
+async fn get_channel(State(ctx): State<Arc<Context>>, Path(id): Path<u32>) -> Response {
+    if let Some(channel) = ctx.get_channels().into_iter().find(|it| it.id == id) {
+        Json(channel).into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+async fn stop_channel(State(ctx): State<Arc<Context>>, Path(id): Path<u32>) -> StatusCode {
+    if ctx.relay.stop(id) {
+        log::info!("admin stops channel, id={}", id);
+
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn kick_subscriber(
+    State(ctx): State<Arc<Context>>,
+    Path((id, addr)): Path<(u32, SocketAddr)>,
+) -> StatusCode {
+    if ctx.relay.kick(id, addr) {
+        log::info!("admin kicks subscriber, id={}, addr={}", id, addr);
+
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Serve the admin api over http.
+///
+/// * `GET /channels` lists the published channels with their publisher and
+///   subscribers and the srt statistics of their connections.
+/// * `GET /channels/{id}` gets one channel.
+/// * `DELETE /channels/{id}` stops the channel by closing its publisher.
+/// * `DELETE /channels/{id}/subscribers/{addr}` closes the subscriber.
+///
+/// The api can stop channels and kick subscribers, it is not started on an
+/// address other than a loopback address without the admin token.
+pub async fn start_server(
+    bind: SocketAddr,
+    token: Arc<Secret>,
+    route: Arc<Route>,
+    relay: Arc<Relay>,
+) -> Result<(), Error> {
+    let loopback = bind.ip().is_loopback();
+    if !loopback && token.get().is_none() {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "the admin api requires the admin token on a non-loopback address",
+        ));
+    }
+
+    let ctx = Arc::new(Context {
+        route,
+        relay,
+        token,
+        loopback,
+    });
+
+    let app = Router::new()
+        .route("/channels", get(get_channels))
+        .route("/channels/:id", get(get_channel).delete(stop_channel))
+        .route("/channels/:id/subscribers/:addr", delete(kick_subscriber))
+        .route_layer(middleware::from_fn_with_state(ctx.clone(), authorize))
+        .with_state(ctx);
+
+    axum::serve(TcpListener::bind(bind).await?, app).await
+}
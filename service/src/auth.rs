@@ -0,0 +1,182 @@
+use std::{
+    fmt,
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{SocketKind, StreamInfo};
+
+/// The validity of the tokens created by the clients themselves.
+pub const TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// The stream information does not carry a token.
+    Missing,
+    /// The token cannot be parsed.
+    Malformed,
+    /// The token has expired.
+    Expired,
+    /// The signature does not match, the token was not created with the
+    /// shared secret or was created for another channel or role.
+    Invalid,
+}
+
+impl std::error::Error for AuthError {}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuthError: {:?}", self)
+    }
+}
+
+/// A secret of the service that can be replaced while the service is
+/// running, the clients that are already authenticated are kept.
+#[derive(Default)]
+pub struct Secret(RwLock<Option<String>>);
+
+impl Secret {
+    pub fn new(secret: Option<String>) -> Self {
+        Self(RwLock::new(secret))
+    }
+
+    pub fn get(&self) -> Option<String> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, secret: Option<String>) {
+        *self.0.write().unwrap() = secret;
+    }
+}
+
+fn mac(secret: &str, id: u32, kind: SocketKind, expiry: u64) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("hmac can take a key of any size")
+        .chain_update(format!("{}:{}:{}", id, kind as u8, expiry))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or(0)
+}
+
+/// Create a token for the channel and role, which is valid until the expiry,
+/// in seconds since the unix epoch.
+///
+/// The token is the expiry and the HMAC-SHA256 signature of the channel id,
+/// the role and the expiry with the shared secret, separated by a dot.
+pub fn sign(secret: &str, id: u32, kind: SocketKind, expiry: u64) -> String {
+    format!(
+        "{}.{}",
+        expiry,
+        hex::encode(mac(secret, id, kind, expiry).finalize().into_bytes())
+    )
+}
+
+/// Create a token for the channel and role, which is valid for the
+/// [`TOKEN_TTL`] from now.
+pub fn sign_with_ttl(secret: &str, id: u32, kind: SocketKind) -> String {
+    sign(secret, id, kind, now() + TOKEN_TTL.as_secs())
+}
+
+/// Verify the token carried in the stream information against the channel id
+/// and role of the stream information.
+pub fn verify(secret: &str, info: &StreamInfo) -> Result<(), AuthError> {
+    let token = info.token.as_ref().ok_or(AuthError::Missing)?;
+    let (expiry, signature) = token.split_once('.').ok_or(AuthError::Malformed)?;
+    let expiry = expiry.parse::<u64>().map_err(|_| AuthError::Malformed)?;
+    let signature = hex::decode(signature).map_err(|_| AuthError::Malformed)?;
+
+    if expiry < now() {
+        return Err(AuthError::Expired);
+    }
+
+    // The comparison takes constant time.
+    mac(secret, info.id, info.kind, expiry)
+        .verify_slice(&signature)
+        .map_err(|_| AuthError::Invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: u32, kind: SocketKind, token: Option<String>) -> StreamInfo {
+        StreamInfo {
+            id,
+            kind,
+            token,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn verify_a_signed_token() {
+        let token = sign_with_ttl("secret", 1, SocketKind::Publisher);
+        assert_eq!(
+            verify("secret", &info(1, SocketKind::Publisher, Some(token))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reject_another_secret_channel_or_role() {
+        let token = Some(sign_with_ttl("secret", 1, SocketKind::Publisher));
+
+        assert_eq!(
+            verify("other", &info(1, SocketKind::Publisher, token.clone())),
+            Err(AuthError::Invalid)
+        );
+        assert_eq!(
+            verify("secret", &info(2, SocketKind::Publisher, token.clone())),
+            Err(AuthError::Invalid)
+        );
+        assert_eq!(
+            verify("secret", &info(1, SocketKind::Subscriber, token)),
+            Err(AuthError::Invalid)
+        );
+    }
+
+    #[test]
+    fn reject_an_expired_token() {
+        let token = sign("secret", 1, SocketKind::Subscriber, now() - 1);
+        assert_eq!(
+            verify("secret", &info(1, SocketKind::Subscriber, Some(token))),
+            Err(AuthError::Expired)
+        );
+    }
+
+    #[test]
+    fn reject_a_tampered_expiry() {
+        let token = sign("secret", 1, SocketKind::Subscriber, now() + 10);
+        let (_, signature) = token.split_once('.').unwrap();
+        let token = format!("{}.{}", now() + 1000, signature);
+        assert_eq!(
+            verify("secret", &info(1, SocketKind::Subscriber, Some(token))),
+            Err(AuthError::Invalid)
+        );
+    }
+
+    #[test]
+    fn reject_a_missing_or_malformed_token() {
+        assert_eq!(
+            verify("secret", &info(1, SocketKind::Subscriber, None)),
+            Err(AuthError::Missing)
+        );
+
+        for token in ["", "123", "abc.00", "123.not hex"] {
+            assert_eq!(
+                verify(
+                    "secret",
+                    &info(1, SocketKind::Subscriber, Some(token.to_string()))
+                ),
+                Err(AuthError::Malformed)
+            );
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use log::LevelFilter;
+use serde::Deserialize;
+use service::discovery::DISCOVERY_PORT;
+
+/// The command line of the service, the options given here override the
+/// ones of the configuration file.
+#[derive(Parser, Clone, Debug)]
+#[command(
+    about = env!("CARGO_PKG_DESCRIPTION"),
+    version = env!("CARGO_PKG_VERSION"),
+    author = env!("CARGO_PKG_AUTHORS"),
+)]
+pub struct Cli {
+    /// The path of the toml configuration file. The file is read again when
+    /// the service receives SIGHUP.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+    #[arg(long)]
+    pub srt_bind: Option<SocketAddr>,
+    #[arg(long)]
+    pub multicast: Option<IpAddr>,
+    #[arg(long)]
+    pub discovery_port: Option<u16>,
+    #[arg(long)]
+    pub admin: Option<SocketAddr>,
+    #[arg(long)]
+    pub metrics: Option<SocketAddr>,
+    #[arg(long)]
+    pub mtu: Option<u32>,
+    #[arg(long)]
+    pub latency: Option<u32>,
+    #[arg(long)]
+    pub fc: Option<u32>,
+    #[arg(long)]
+    pub fec: Option<String>,
+    #[arg(long)]
+    pub max_bandwidth: Option<i64>,
+    #[arg(long)]
+    pub backlog: Option<u32>,
+    #[arg(long)]
+    pub log_level: Option<LevelFilter>,
+    #[arg(long)]
+    pub log_file: Option<String>,
+    #[arg(long)]
+    pub secret: Option<String>,
+    #[arg(long)]
+    pub passphrase: Option<String>,
+    #[arg(long)]
+    pub key_length: Option<u32>,
+    #[arg(long)]
+    pub admin_token: Option<String>,
+    #[arg(long)]
+    pub max_publishers: Option<usize>,
+    #[arg(long)]
+    pub max_subscribers: Option<usize>,
+    #[arg(long)]
+    pub max_connections: Option<usize>,
+    #[arg(long)]
+    pub publisher_max_bandwidth: Option<i64>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Server {
+    /// The address of the signal server.
+    pub bind: SocketAddr,
+    /// The address of the srt server, the signal address if it is not set.
+    /// The clients are told the port on the signal connection and connect to
+    /// it on the host of the signal server.
+    pub srt_bind: Option<SocketAddr>,
+    /// The multicast group suggested to the clients that discover the
+    /// service.
+    pub multicast: IpAddr,
+    /// The udp port on which the discovery queries of the clients are
+    /// answered, 0 disables discovery.
+    pub discovery_port: u16,
+    /// The address of the http admin api, it is disabled if not set. The api
+    /// can stop channels and kick subscribers, so it is only served on a
+    /// loopback address unless the admin token is set.
+    pub admin: Option<SocketAddr>,
+    /// The address of the http server exporting the prometheus metrics at
+    /// `/metrics`, it is disabled if not set.
+    pub metrics: Option<SocketAddr>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8080),
+            multicast: IpAddr::V4(Ipv4Addr::new(239, 0, 0, 1)),
+            discovery_port: DISCOVERY_PORT,
+            srt_bind: None,
+            metrics: None,
+            admin: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Srt {
+    pub mtu: u32,
+    /// The receiving latency in milliseconds. Since this suite only works
+    /// within the LAN, it is set to the minimum delay without considering
+    /// network factors.
+    pub latency: u32,
+    /// The flow control window in packets.
+    pub fc: u32,
+    /// The packet filter configuration of the forward error correction.
+    pub fec: String,
+    /// The maximum bandwidth of a connection in bytes per second, -1 is
+    /// unlimited.
+    pub max_bandwidth: i64,
+    /// The length of the queue of the connections waiting to be accepted.
+    pub backlog: u32,
+}
+
+impl Default for Srt {
+    fn default() -> Self {
+        let opt = srt::Options::default();
+
+        Self {
+            max_bandwidth: opt.max_bandwidth,
+            fec: opt.fec,
+            mtu: opt.mtu,
+            latency: 40,
+            backlog: 100,
+            fc: 32,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Log {
+    pub level: LevelFilter,
+    /// The log is also written to this file if it is set.
+    pub file: Option<String>,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            file: Some("mirror-service.log".to_string()),
+            level: LevelFilter::Info,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Auth {
+    /// The shared secret used to sign the tokens of the clients, if it is not
+    /// set, the service accepts all clients without authentication.
+    pub secret: Option<String>,
+    /// The passphrase of the srt encryption, 10 to 79 characters, if it is
+    /// set, the clients must connect with the same passphrase.
+    pub passphrase: Option<String>,
+    /// The length of the srt encryption key in bytes, 16, 24 or 32.
+    pub key_length: u32,
+    /// The bearer token required by the admin api, if it is set.
+    pub admin_token: Option<String>,
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self {
+            admin_token: None,
+            passphrase: None,
+            secret: None,
+            key_length: 16,
+        }
+    }
+}
+
+/// The limits of the resources used by the clients, nothing is limited if
+/// they are not set. The clients over the limits are rejected when they
+/// connect.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Limits {
+    /// The maximum number of publishers, which is also the number of
+    /// channels.
+    pub max_publishers: Option<usize>,
+    /// The maximum number of subscribers of each channel.
+    pub max_subscribers: Option<usize>,
+    /// The maximum number of srt connections, publishers and subscribers
+    /// together.
+    pub max_connections: Option<usize>,
+    /// The maximum bandwidth of each publisher in bytes per second, the
+    /// publishers are told it, and closed when they exceed it by half over a
+    /// few seconds.
+    pub publisher_max_bandwidth: Option<i64>,
+}
+
+/// The configuration of the service, read from the configuration file and
+/// overridden by the command line.
+///
+/// The log level, the `auth` secret and admin token and the limits are
+/// applied again when the configuration is reloaded, the other settings only
+/// take effect after a restart. The new limits only apply to the clients
+/// connecting afterwards.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Configure {
+    pub server: Server,
+    pub srt: Srt,
+    pub log: Log,
+    pub auth: Auth,
+    pub limits: Limits,
+}
+
+impl Configure {
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let mut config: Self = if let Some(path) = cli.config.as_ref() {
+            toml::from_str(&fs::read_to_string(path)?)?
+        } else {
+            Self::default()
+        };
+
+        // The command line overrides the configuration file.
+        macro_rules! apply {
+            ($($arg:ident => $($field:ident).+),* $(,)?) => {
+                $(
+                    if let Some(value) = cli.$arg.clone() {
+                        config.$($field).+ = value.into();
+                    }
+                )*
+            };
+        }
+
+        apply!(
+            bind => server.bind,
+            srt_bind => server.srt_bind,
+            multicast => server.multicast,
+            discovery_port => server.discovery_port,
+            admin => server.admin,
+            metrics => server.metrics,
+            mtu => srt.mtu,
+            latency => srt.latency,
+            fc => srt.fc,
+            fec => srt.fec,
+            max_bandwidth => srt.max_bandwidth,
+            backlog => srt.backlog,
+            log_level => log.level,
+            log_file => log.file,
+            secret => auth.secret,
+            passphrase => auth.passphrase,
+            key_length => auth.key_length,
+            admin_token => auth.admin_token,
+            max_publishers => limits.max_publishers,
+            max_subscribers => limits.max_subscribers,
+            max_connections => limits.max_connections,
+            publisher_max_bandwidth => limits.publisher_max_bandwidth,
+        );
+
+        Ok(config)
+    }
+
+    /// The address of the srt server.
+    pub fn srt_bind(&self) -> SocketAddr {
+        self.server.srt_bind.unwrap_or(self.server.bind)
+    }
+
+    /// Whether the settings that cannot change at runtime differ, the
+    /// service has to be restarted to apply them.
+    pub fn requires_restart(&self, other: &Self) -> bool {
+        self.server != other.server
+            || self.srt != other.srt
+            || self.log.file != other.log.file
+            || self.auth.passphrase != other.auth.passphrase
+            || self.auth.key_length != other.auth.key_length
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+/// The description is sent by the publisher over srt among the fragments,
+/// which start with their check digit, it starts with this instead.
+const MAGIC: &[u8] = b"MIRRDESC";
+
+/// The tag of the channels publishing the simulcast layers of a channel, the
+/// value is the id of that channel. The subscribers of the channel can ask the
+/// service for one of its layers instead, see [`crate::StreamInfo::layer`].
+pub const SIMULCAST_TAG: &str = "simulcast";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VideoDescription {
+    /// The name of the codec, such as `h264`.
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: u8,
+    pub bit_rate: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AudioDescription {
+    /// The name of the codec, such as `opus`.
+    pub codec: String,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bit_rate: u64,
+}
+
+/// The description of a channel supplied by the publisher, the receivers get
+/// it with the signal of the channel, before any audio and video data
+/// arrives, to list the channels and choose the decoders.
+///
+/// The whole description is sent in one srt message, so it is limited to the
+/// srt payload size, keep the tags short.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StreamDescription {
+    /// The human-readable title of the channel, such as the name of the
+    /// publisher.
+    pub title: String,
+    pub video: Option<VideoDescription>,
+    pub audio: Option<AudioDescription>,
+    /// Arbitrary key/value pairs defined by the application.
+    pub tags: BTreeMap<String, String>,
+}
+
+impl StreamDescription {
+    pub fn encode(&self) -> Bytes {
+        let payload = rmp_serde::to_vec(&self).unwrap();
+        let mut buf = BytesMut::with_capacity(payload.len() + MAGIC.len());
+        buf.put(MAGIC);
+        buf.put(&payload[..]);
+        buf.freeze()
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        rmp_serde::from_slice(buf.strip_prefix(MAGIC)?).ok()
+    }
+}
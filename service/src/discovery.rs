@@ -0,0 +1,71 @@
+use std::{
+    io::Error,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+
+/// The udp port on which the services answer the discovery queries.
+pub const DISCOVERY_PORT: u16 = 43165;
+
+/// Every discovery message starts with it, other traffic on the port is
+/// ignored.
+const MAGIC: &[u8] = b"MIRROR";
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum Discovery {
+    /// Broadcast by the clients looking for services on the LAN.
+    Query,
+    /// The answer of a service to the query. The server is the address of
+    /// the signaling, it is unspecified when the service listens on all
+    /// interfaces, and the client then uses the source address of the answer.
+    /// The srt server is on the same host, at the srt port, or at the port of
+    /// the signaling if it is not sent. The multicast group is the group
+    /// suggested to the clients, and the mtu is the mtu of the service.
+    Announcement {
+        server: SocketAddr,
+        multicast: IpAddr,
+        mtu: u32,
+        #[serde(default)]
+        srt_port: Option<u16>,
+    },
+}
+
+impl Discovery {
+    pub fn encode(&self) -> Bytes {
+        let payload = rmp_serde::to_vec(&self).unwrap();
+        let mut buf = BytesMut::with_capacity(payload.len() + MAGIC.len());
+        buf.put(MAGIC);
+        buf.put(&payload[..]);
+        buf.freeze()
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        rmp_serde::from_slice(buf.strip_prefix(MAGIC)?).ok()
+    }
+}
+
+/// Answer the discovery queries of the clients with the announcement, the
+/// queries are broadcast to the port on all interfaces.
+pub async fn start_server(port: u16, announcement: Discovery) -> Result<(), Error> {
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)).await?;
+    let reply = announcement.encode();
+
+    let mut buf = [0u8; 1024];
+    loop {
+        let (size, addr) = socket.recv_from(&mut buf).await?;
+        if let Some(Discovery::Query) = Discovery::decode(&buf[..size]) {
+            log::info!("answer a discovery query, addr={}", addr);
+
+            if let Err(e) = socket.send_to(&reply, addr).await {
+                log::warn!(
+                    "not send a discovery announcement, addr={}, err={:?}",
+                    addr,
+                    e
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Why the service does not accept a client, see [`Feedback::Rejected`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RejectReason {
+    TooManyConnections,
+    TooManyPublishers,
+    TooManySubscribers,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::TooManyConnections => "too many connections",
+                Self::TooManyPublishers => "too many publishers",
+                Self::TooManySubscribers => "too many subscribers of the channel",
+            }
+        )
+    }
+}
+
+/// Messages sent by the subscriber back to the publisher of the same channel.
+///
+/// The subscriber writes the feedback to its srt socket, the service forwards
+/// it to the publisher of the channel, and the publisher handles it on its own
+/// srt socket. The service also sends its own feedback to the publisher, and
+/// handles the choice of the simulcast layer with the subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Feedback {
+    /// The subscriber has lost packets and is waiting for a key frame, the
+    /// publisher should emit an IDR frame as soon as possible.
+    KeyFrameRequest,
+    /// Sent by the subscriber periodically, the number of multicast fragments
+    /// received and finally lost in the last period. The publisher uses it to
+    /// decide whether the multicast is reliable enough.
+    LossReport { received: u32, lost: u32 },
+    /// Sent by the subscriber after connecting when the multicast is
+    /// encrypted, the publisher answers with its multicast keys over srt.
+    KeyRequest,
+    /// Sent by the service after accepting the publisher when the bandwidth
+    /// of the publishers is limited, in bytes per second. The publisher caps
+    /// its srt sending bandwidth, the service closes the publishers that
+    /// exceed the limit.
+    BandwidthLimit { max_bandwidth: i64 },
+    /// Sent by the subscriber to get another simulcast layer of the channel,
+    /// the id of the channel publishing the layer, or the id of the channel
+    /// itself. It is handled by the service, which switches the subscriber at
+    /// the next key frame of the layer.
+    SelectLayer { id: u32 },
+    /// Sent by the service to the subscriber right before the first packet of
+    /// the layer it switched to, the packets of the layer have their own
+    /// sequence and video configuration.
+    LayerSwitched { id: u32 },
+    /// Sent by the service to a client it does not accept within its limits,
+    /// right before closing the connection.
+    Rejected { reason: RejectReason },
+}
+
+impl Feedback {
+    pub fn encode(&self) -> Bytes {
+        Bytes::from(rmp_serde::to_vec(&self).unwrap())
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        rmp_serde::from_slice(buf).ok()
+    }
+}
@@ -1,19 +1,65 @@
+pub mod auth;
+pub mod description;
+pub mod discovery;
+pub mod feedback;
+pub mod metrics;
+pub mod package;
 pub mod route;
 pub mod signal;
 
+use std::net::IpAddr;
+
+/// The version of the protocol between the clients and the service, it is
+/// negotiated on the signal connection and carried in the stream information.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// The oldest version of the protocol that is still supported.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// Whether a client that only speaks this version is supported.
+pub fn is_supported(version: u8) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// Choose the version used between two sides, the highest version supported
+/// by both, if any, each side supports the versions from its minimum to its
+/// current version.
+pub fn negotiate(version: u8, min_version: u8) -> Option<u8> {
+    let version = version.min(PROTOCOL_VERSION);
+    if version >= min_version.max(MIN_PROTOCOL_VERSION) {
+        Some(version)
+    } else {
+        None
+    }
+}
+
 #[repr(u8)]
-#[derive(Default, PartialEq, Eq, Debug)]
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
 pub enum SocketKind {
     #[default]
     Subscriber = 0,
     Publisher = 1,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct StreamInfo {
     pub id: u32,
     pub port: Option<u16>,
+    /// The multicast group used by the publisher, which can be either an IPV4
+    /// or an IPV6 address.
+    pub multicast: Option<IpAddr>,
     pub kind: SocketKind,
+    /// The token proving that the client is allowed to publish or subscribe
+    /// to the channel, it is required when the service is configured with a
+    /// secret, see [`auth::sign`].
+    pub token: Option<String>,
+    /// The protocol version of the client, 0 when the client does not carry
+    /// it, which is a client older than the versioned protocol.
+    pub version: u8,
+    /// The simulcast layer a subscriber asks for, the id of the channel
+    /// publishing the layer, see [`description::SIMULCAST_TAG`]. The subscriber
+    /// gets the channel itself if it is not set.
+    pub layer: Option<u32>,
 }
 
 impl StreamInfo {
@@ -46,6 +92,24 @@ impl StreamInfo {
                                 info.port = Some(port);
                             }
                         }
+                        "m" => {
+                            if let Ok(multicast) = v.parse::<IpAddr>() {
+                                info.multicast = Some(multicast);
+                            }
+                        }
+                        "t" => {
+                            info.token = Some(v.to_string());
+                        }
+                        "v" => {
+                            if let Ok(version) = v.parse::<u8>() {
+                                info.version = version;
+                            }
+                        }
+                        "l" => {
+                            if let Ok(layer) = v.parse::<u32>() {
+                                info.layer = Some(layer);
+                            }
+                        }
                         _ => (),
                     }
                 }
@@ -64,6 +128,12 @@ impl StreamInfo {
                 format!("i={}", self.id),
                 format!("k={}", self.kind as u8),
                 self.port.map(|p| format!("p={}", p)).unwrap_or_default(),
+                self.multicast
+                    .map(|m| format!("m={}", m))
+                    .unwrap_or_default(),
+                self.token.map(|t| format!("t={}", t)).unwrap_or_default(),
+                format!("v={}", self.version),
+                self.layer.map(|l| format!("l={}", l)).unwrap_or_default(),
             ]
             .join(",")
         )
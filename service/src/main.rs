@@ -1,47 +1,107 @@
+mod admin;
+mod config;
+mod prometheus;
 mod proxy;
 
-use std::{net::SocketAddr, process::exit, sync::Arc, thread};
+use std::{
+    process::exit,
+    sync::{Arc, RwLock},
+    thread,
+};
 
 use anyhow::Result;
 use clap::Parser;
+use config::{Cli, Configure, Limits};
 use log::LevelFilter;
-use service::route::Route;
+use proxy::Relay;
+use service::{
+    auth::Secret,
+    discovery::{self, Discovery},
+    metrics::Metrics,
+    route::Route,
+};
 use tokio::runtime::Runtime;
 use utils::logger;
 
 // #[global_allocator]
 // static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-#[derive(Parser, Clone, Debug)]
-#[command(
-    about = env!("CARGO_PKG_DESCRIPTION"),
-    version = env!("CARGO_PKG_VERSION"),
-    author = env!("CARGO_PKG_AUTHORS"),
-)]
-pub struct Configure {
-    #[arg(long)]
-    pub bind: SocketAddr,
-    #[arg(long)]
-    pub mtu: usize,
+// Read the configuration again and apply the settings that can change while the
+// service is running, the others are kept until the service is restarted.
+#[cfg(unix)]
+async fn reload(
+    cli: Cli,
+    mut config: Configure,
+    secret: Arc<Secret>,
+    admin_token: Arc<Secret>,
+    limits: Arc<RwLock<Limits>>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(it) => it,
+        Err(e) => {
+            log::error!("not listen for SIGHUP, err={:?}", e);
+
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        let new_config = match Configure::load(&cli) {
+            Ok(it) => it,
+            Err(e) => {
+                log::error!("not reload configure, err={:?}", e);
+
+                continue;
+            }
+        };
+
+        if new_config.requires_restart(&config) {
+            log::warn!("some changes of the configure only take effect after a restart");
+        }
+
+        log::set_max_level(new_config.log.level);
+        secret.set(new_config.auth.secret.clone());
+        admin_token.set(new_config.auth.admin_token.clone());
+        *limits.write().unwrap() = new_config.limits.clone();
+
+        config = new_config;
+        log::info!("reload configure: {:?}", config);
+    }
 }
 
 fn main() -> Result<()> {
-    // Initialize srt and logger
+    // Parse command line parameters and read the configuration file. Note that if
+    // the command line parameters are incorrect, panic will occur.
+    let cli = Cli::parse();
+    let config = Configure::load(&cli)?;
+
+    // Initialize srt and logger. The loggers accept all levels, the level is
+    // filtered with the maximum level of the log crate, which can be changed when
+    // the configuration is reloaded.
     srt::startup();
-    logger::init(LevelFilter::Info, Some("mirror-service.log"))?;
+    logger::init(LevelFilter::Trace, config.log.file.as_deref())?;
+    log::set_max_level(config.log.level);
 
-    // Parse command line parameters. Note that if the command line parameters are
-    // incorrect, panic will occur.
-    let config = Configure::parse();
+    let secret = Arc::new(Secret::new(config.auth.secret.clone()));
+    let admin_token = Arc::new(Secret::new(config.auth.admin_token.clone()));
+    let limits = Arc::new(RwLock::new(config.limits.clone()));
     let route = Arc::new(Route::default());
+    let relay = Arc::new(Relay::default());
+    let metrics = Arc::new(Metrics::default());
 
     log::info!("configure: {:?}", config);
 
     // Start the forwarding server
     let route_ = route.clone();
+    let relay_ = relay.clone();
+    let metrics_ = metrics.clone();
+    let secret_ = secret.clone();
+    let limits_ = limits.clone();
     let config_ = config.clone();
     thread::spawn(move || {
-        if proxy::start_server(config_, route_).is_err() {
+        if proxy::start_server(config_, route_, relay_, metrics_, secret_, limits_).is_err() {
             exit(-11);
         }
     });
@@ -49,7 +109,66 @@ fn main() -> Result<()> {
     // Start the signaling server. If the signaling server exits, the entire process
     // will exit. This is because if the signaling exits, it is meaningless to
     // continue running.
-    Runtime::new()?.block_on(service::signal::start_server(config.bind, route))?;
+    Runtime::new()?.block_on(async {
+        // The clients on the LAN can find the service without being configured, the
+        // discovery is not essential and its failure does not stop the service.
+        if config.server.discovery_port != 0 {
+            let port = config.server.discovery_port;
+            let announcement = Discovery::Announcement {
+                server: config.server.bind,
+                multicast: config.server.multicast,
+                mtu: config.srt.mtu,
+                srt_port: Some(config.srt_bind().port()),
+            };
+
+            tokio::spawn(async move {
+                if let Err(e) = discovery::start_server(port, announcement).await {
+                    log::error!("discovery server is closed, err={:?}", e);
+                }
+            });
+        }
+
+        // The metrics and the admin api are only for monitoring and management, their
+        // failure does not stop the service either.
+        if let Some(bind) = config.server.metrics {
+            let route = route.clone();
+            let relay = relay.clone();
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                if let Err(e) = prometheus::start_server(bind, route, relay, metrics).await {
+                    log::error!("metrics server is closed, err={:?}", e);
+                }
+            });
+        }
+
+        if let Some(bind) = config.server.admin {
+            let token = admin_token.clone();
+            let route = route.clone();
+            tokio::spawn(async move {
+                if let Err(e) = admin::start_server(bind, token, route, relay).await {
+                    log::error!("admin server is closed, err={:?}", e);
+                }
+            });
+        }
+
+        #[cfg(unix)]
+        tokio::spawn(reload(
+            cli,
+            config.clone(),
+            secret.clone(),
+            admin_token,
+            limits,
+        ));
+
+        service::signal::start_server(
+            config.server.bind,
+            config.srt_bind().port(),
+            route,
+            metrics,
+            secret,
+        )
+        .await
+    })?;
     srt::cleanup();
 
     Ok(())
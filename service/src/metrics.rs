@@ -0,0 +1,70 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// The counters of the service that are exported as metrics, shared by the
+/// signaling server and the forwarding server.
+#[derive(Default)]
+pub struct Metrics {
+    signal_connections: AtomicU64,
+    signal_connections_total: AtomicU64,
+    forwarded_bytes: AtomicU64,
+    forwarded_packets: AtomicU64,
+    dropped_subscribers: AtomicU64,
+}
+
+impl Metrics {
+    /// Count a new signal connection, the connection is counted as open until
+    /// the returned guard is dropped.
+    pub fn signal_connected(self: &Arc<Self>) -> SignalConnection {
+        self.signal_connections.fetch_add(1, Ordering::Relaxed);
+        self.signal_connections_total
+            .fetch_add(1, Ordering::Relaxed);
+
+        SignalConnection(self.clone())
+    }
+
+    /// A packet of this size has been forwarded to a subscriber.
+    pub fn forwarded(&self, size: usize) {
+        self.forwarded_bytes
+            .fetch_add(size as u64, Ordering::Relaxed);
+        self.forwarded_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A subscriber has been dropped because sending to it failed.
+    pub fn dropped(&self) {
+        self.dropped_subscribers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of currently open signal connections.
+    pub fn get_signal_connections(&self) -> u64 {
+        self.signal_connections.load(Ordering::Relaxed)
+    }
+
+    /// The number of signal connections accepted since the service started.
+    pub fn get_signal_connections_total(&self) -> u64 {
+        self.signal_connections_total.load(Ordering::Relaxed)
+    }
+
+    pub fn get_forwarded_bytes(&self) -> u64 {
+        self.forwarded_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn get_forwarded_packets(&self) -> u64 {
+        self.forwarded_packets.load(Ordering::Relaxed)
+    }
+
+    pub fn get_dropped_subscribers(&self) -> u64 {
+        self.dropped_subscribers.load(Ordering::Relaxed)
+    }
+}
+
+/// An open signal connection, see [`Metrics::signal_connected`].
+pub struct SignalConnection(Arc<Metrics>);
+
+impl Drop for SignalConnection {
+    fn drop(&mut self) {
+        self.0.signal_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,47 @@
+// The layout of the packages carried in the srt fragments, shared by the
+// clients that pack them and the service that only inspects them:
+//
+// ```text
+// check digit (8) | length (8) | version (1) | kind (1) | flags (1) | timestamp (8)
+// ```
+//
+// The length is the length of the whole package, the header included.
+
+/// The size of the package header.
+pub const HEAD_SIZE: usize = 27;
+
+/// The version of the package layout.
+pub const VERSION: u8 = 1;
+
+pub const VIDEO: u8 = 0;
+pub const AUDIO: u8 = 1;
+
+pub const KEY_FRAME: u8 = 1;
+pub const CONFIG: u8 = 2;
+pub const END_OF_STREAM: u8 = 4;
+pub const PARTIAL: u8 = 8;
+
+const LENGTH_OFFSET: usize = 8;
+const VERSION_OFFSET: usize = 16;
+
+/// Whether the srt fragment is the first fragment of a video configuration
+/// package, the point where a receiver can start decoding a stream.
+pub fn is_config_start(fragment: &[u8]) -> bool {
+    let Some((size, package)) = srt::split_fragment(fragment) else {
+        return false;
+    };
+
+    // The first fragment of a payload carries the package header, and its
+    // length is the size of the whole payload.
+    if package.len() < HEAD_SIZE {
+        return false;
+    }
+
+    let length = u64::from_be_bytes(
+        package[LENGTH_OFFSET..VERSION_OFFSET]
+            .try_into()
+            .expect("the length is 8 bytes"),
+    );
+
+    length == size as u64 && package[VERSION_OFFSET..VERSION_OFFSET + 3] == [VERSION, VIDEO, CONFIG]
+}
@@ -0,0 +1,248 @@
+use std::{fmt::Write, io::Error, net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::State, http::header::CONTENT_TYPE, response::IntoResponse, routing::get, Router,
+};
+use service::{metrics::Metrics, route::Route};
+use srt::{Socket, TraceStats};
+use tokio::net::TcpListener;
+
+use crate::proxy::Relay;
+
+struct Context {
+    route: Arc<Route>,
+    relay: Arc<Relay>,
+    metrics: Arc<Metrics>,
+}
+
+// Write the help and type lines that precede the samples of a metric.
+fn family(buf: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(buf, "# HELP {} {}", name, help);
+    let _ = writeln!(buf, "# TYPE {} {}", name, kind);
+}
+
+// A metric taken from the srt statistics of each connection.
+struct TraceFamily {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    value: fn(&TraceStats) -> f64,
+}
+
+// The srt statistics of one connection of a channel.
+struct Connection {
+    channel: u32,
+    role: &'static str,
+    addr: SocketAddr,
+    socket: Arc<Socket>,
+}
+
+impl Context {
+    fn encode(&self) -> String {
+        let mut buf = String::with_capacity(4096);
+        let mut connections = Vec::with_capacity(100);
+
+        let channels = self.route.get_channels();
+        family(
+            &mut buf,
+            "mirror_channels",
+            "gauge",
+            "The number of published channels.",
+        );
+        let _ = writeln!(buf, "mirror_channels {}", channels.len());
+
+        family(
+            &mut buf,
+            "mirror_channel_publishers",
+            "gauge",
+            "The number of publishers connected to the channel.",
+        );
+
+        for (id, _, _, _) in &channels {
+            let publisher = self.relay.get_publisher(*id);
+            let _ = writeln!(
+                buf,
+                "mirror_channel_publishers{{channel=\"{}\"}} {}",
+                id,
+                publisher.is_some() as u8
+            );
+
+            if let Some((addr, socket)) = publisher {
+                connections.push(Connection {
+                    channel: *id,
+                    role: "publisher",
+                    addr,
+                    socket,
+                });
+            }
+        }
+
+        family(
+            &mut buf,
+            "mirror_channel_subscribers",
+            "gauge",
+            "The number of subscribers connected to the channel.",
+        );
+
+        for (id, _, _, _) in &channels {
+            let subscribers = self.relay.get_subscribers(*id);
+            let _ = writeln!(
+                buf,
+                "mirror_channel_subscribers{{channel=\"{}\"}} {}",
+                id,
+                subscribers.len()
+            );
+
+            for (addr, socket) in subscribers {
+                connections.push(Connection {
+                    channel: *id,
+                    role: "subscriber",
+                    addr,
+                    socket,
+                });
+            }
+        }
+
+        family(
+            &mut buf,
+            "mirror_forwarded_bytes_total",
+            "counter",
+            "The bytes forwarded from the publishers to the subscribers.",
+        );
+
+        let _ = writeln!(
+            buf,
+            "mirror_forwarded_bytes_total {}",
+            self.metrics.get_forwarded_bytes()
+        );
+
+        family(
+            &mut buf,
+            "mirror_forwarded_packets_total",
+            "counter",
+            "The packets forwarded from the publishers to the subscribers.",
+        );
+
+        let _ = writeln!(
+            buf,
+            "mirror_forwarded_packets_total {}",
+            self.metrics.get_forwarded_packets()
+        );
+
+        family(
+            &mut buf,
+            "mirror_dropped_subscribers_total",
+            "counter",
+            "The subscribers dropped because a srt send to them failed.",
+        );
+
+        let _ = writeln!(
+            buf,
+            "mirror_dropped_subscribers_total {}",
+            self.metrics.get_dropped_subscribers()
+        );
+
+        family(
+            &mut buf,
+            "mirror_signal_connections",
+            "gauge",
+            "The number of open signal connections.",
+        );
+
+        let _ = writeln!(
+            buf,
+            "mirror_signal_connections {}",
+            self.metrics.get_signal_connections()
+        );
+
+        family(
+            &mut buf,
+            "mirror_signal_connections_total",
+            "counter",
+            "The signal connections accepted since the service started.",
+        );
+
+        let _ = writeln!(
+            buf,
+            "mirror_signal_connections_total {}",
+            self.metrics.get_signal_connections_total()
+        );
+
+        // The statistics are read once per connection and written under each family,
+        // the connections that are already closed are skipped.
+        let stats = connections
+            .iter()
+            .filter_map(|it| it.socket.get_stats().ok().map(|trace| (it, trace)))
+            .collect::<Vec<_>>();
+
+        let families = [
+            TraceFamily {
+                name: "mirror_srt_rtt_milliseconds",
+                kind: "gauge",
+                help: "The round trip time of the srt connection.",
+                value: |it| it.ms_rtt,
+            },
+            TraceFamily {
+                name: "mirror_srt_send_lost_packets_total",
+                kind: "counter",
+                help: "The packets reported lost by the receiver of the srt connection.",
+                value: |it| it.pkt_snd_loss_total as f64,
+            },
+            TraceFamily {
+                name: "mirror_srt_recv_lost_packets_total",
+                kind: "counter",
+                help: "The packets detected lost on the srt connection.",
+                value: |it| it.pkt_rcv_loss_total as f64,
+            },
+            TraceFamily {
+                name: "mirror_srt_retransmitted_packets_total",
+                kind: "counter",
+                help: "The packets retransmitted on the srt connection.",
+                value: |it| it.pkt_retrans_total as f64,
+            },
+        ];
+
+        for metric in families {
+            family(&mut buf, metric.name, metric.kind, metric.help);
+
+            for (connection, trace) in &stats {
+                let _ = writeln!(
+                    buf,
+                    "{}{{channel=\"{}\",role=\"{}\",addr=\"{}\"}} {}",
+                    metric.name,
+                    connection.channel,
+                    connection.role,
+                    connection.addr,
+                    (metric.value)(trace)
+                );
+            }
+        }
+
+        buf
+    }
+}
+
+async fn get_metrics(State(ctx): State<Arc<Context>>) -> impl IntoResponse {
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], ctx.encode())
+}
+
+/// Serve the metrics of the service in the prometheus text format at
+/// `GET /metrics`.
+pub async fn start_server(
+    bind: SocketAddr,
+    route: Arc<Route>,
+    relay: Arc<Relay>,
+    metrics: Arc<Metrics>,
+) -> Result<(), Error> {
+    let ctx = Arc::new(Context {
+        route,
+        relay,
+        metrics,
+    });
+
+    let app = Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(ctx);
+
+    axum::serve(TcpListener::bind(bind).await?, app).await
+}
@@ -1,177 +1,881 @@
 use std::{
-    collections::{HashMap, HashSet},
-    sync::{Arc, RwLock},
-    thread,
+    collections::{HashMap, HashSet, VecDeque},
+    io::{Error, ErrorKind},
+    mem,
+    net::SocketAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
-use service::{route::Route, SocketKind, StreamInfo};
-use srt::{Options, Server};
+use bytes::Bytes;
+use service::{
+    auth::{self, Secret},
+    description::{StreamDescription, SIMULCAST_TAG},
+    feedback::{Feedback, RejectReason},
+    is_supported,
+    metrics::Metrics,
+    package,
+    route::Route,
+    SocketKind, StreamInfo,
+};
+use srt::{Epoll, Event, Options, Server, Socket};
 
-use crate::Configure;
+use crate::config::{Configure, Limits};
 
-pub fn start_server(config: Configure, route: Arc<Route>) -> Result<()> {
-    // Configuration of the srt server. Since this suite only works within the LAN,
-    // the delay is set to the minimum delay without considering network factors.
-    let mut opt = Options::default();
-    opt.mtu = config.mtu as u32;
-    opt.latency = 40;
-    opt.fc = 32;
+/// The number of packets waiting to be sent to each subscriber. A subscriber
+/// which cannot keep up with the publisher fills its queue and is dropped,
+/// instead of holding up the other subscribers of the channel.
+const QUEUE_SIZE: usize = 1024;
 
-    // Start the srt server
-    let server = Server::bind(config.bind, opt, 100)?;
-    log::info!("starting srt server...");
+/// How long the event loop waits for the sockets at most, before closing the
+/// connections requested by the admin api.
+const WAIT_TIMEOUT: Duration = Duration::from_millis(100);
 
-    let sockets = Arc::new(RwLock::new(HashMap::with_capacity(200)));
-    let subscribers = Arc::new(RwLock::new(HashMap::with_capacity(200)));
+/// How long the connections rejected by the limits are kept open after
+/// telling the clients why, the srt sockets drop the unsent data when closed.
+const REJECT_LINGER: Duration = Duration::from_secs(1);
 
-    loop {
-        match server.accept() {
-            Ok((socket, addr)) => {
-                let stream_id = socket.get_stream_id();
-                log::info!("new srt socket, addr={:?}, stream_id={:?}", addr, stream_id);
-
-                let route = route.clone();
-                let socket = Arc::new(socket);
-
-                // Get the stream information carried in the srt link. If the stream information
-                // does not exist or is invalid, the current connection is rejected. Skipping
-                // this step directly will trigger the release of the link and close it.
-                let stream_info =
-                    if let Some(info) = stream_id.as_ref().and_then(|it| StreamInfo::decode(it)) {
-                        info
-                    } else {
-                        log::error!("invalid stream id, addr={:?}", addr);
+/// The publishers are allowed this much over their bandwidth limit, the srt
+/// sending bandwidth is not exact.
+const BANDWIDTH_HEADROOM: f64 = 1.5;
 
-                        continue;
-                    };
+/// The bandwidth of the publishers is measured over this period, so that the
+/// packets held back by a stall and arriving bunched afterwards are tolerated.
+const BANDWIDTH_PERIOD: Duration = Duration::from_secs(5);
+
+/// The srt connections of the relay, shared with the admin api.
+///
+/// The connections are only added and removed by the event loop of the
+/// relay, the admin api asks the event loop to close them.
+pub struct Relay {
+    sockets: RwLock<HashMap<SocketAddr, Arc<Socket>>>,
+    publishers: RwLock<HashMap<u32, (SocketAddr, Arc<Socket>)>>,
+    subscribers: RwLock<HashMap<u32, HashSet<SocketAddr>>>,
+    closing: Mutex<Vec<SocketAddr>>,
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Self {
+            sockets: RwLock::new(HashMap::with_capacity(200)),
+            publishers: RwLock::new(HashMap::with_capacity(200)),
+            subscribers: RwLock::new(HashMap::with_capacity(200)),
+            closing: Mutex::new(Vec::with_capacity(10)),
+        }
+    }
+}
+
+impl Relay {
+    /// Get the publisher of the channel.
+    pub fn get_publisher(&self, id: u32) -> Option<(SocketAddr, Arc<Socket>)> {
+        self.publishers.read().unwrap().get(&id).cloned()
+    }
+
+    /// Get the subscribers of the channel.
+    pub fn get_subscribers(&self, id: u32) -> Vec<(SocketAddr, Arc<Socket>)> {
+        let sockets = self.sockets.read().unwrap();
+        self.subscribers
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|addr| sockets.get(addr).map(|it| (*addr, it.clone())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Close the connection of a subscriber of the channel, returns false if
+    /// it is not subscribed to the channel.
+    pub fn kick(&self, id: u32, addr: SocketAddr) -> bool {
+        let subscribed = self
+            .subscribers
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(|items| items.contains(&addr))
+            .unwrap_or(false);
+
+        if subscribed {
+            self.closing.lock().unwrap().push(addr);
+        }
+
+        subscribed
+    }
+
+    /// Check whether the client can be accepted within the limits, returns the
+    /// reason of the rejection otherwise.
+    fn admit(&self, info: &StreamInfo, limits: &Limits) -> Result<(), RejectReason> {
+        let publishers = self.publishers.read().unwrap().len();
+        let connections = self.sockets.read().unwrap().len() + publishers;
+        if limits.max_connections.is_some_and(|max| connections >= max) {
+            return Err(RejectReason::TooManyConnections);
+        }
+
+        if info.kind == SocketKind::Publisher {
+            if limits.max_publishers.is_some_and(|max| publishers >= max) {
+                return Err(RejectReason::TooManyPublishers);
+            }
+        } else if let Some(max) = limits.max_subscribers {
+            let subscribers = self
+                .subscribers
+                .read()
+                .unwrap()
+                .get(&info.id)
+                .map(|items| items.len())
+                .unwrap_or(0);
+
+            if subscribers >= max {
+                return Err(RejectReason::TooManySubscribers);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close the connection of the publisher of the channel, the channel is
+    /// then stopped and its subscribers are closed, returns false if the
+    /// channel is not published.
+    pub fn stop(&self, id: u32) -> bool {
+        if let Some((addr, _)) = self.get_publisher(id) {
+            self.closing.lock().unwrap().push(addr);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+struct Connection {
+    addr: SocketAddr,
+    info: StreamInfo,
+    socket: Arc<Socket>,
+    // The channel whose packets a subscriber gets, its own channel or one of the
+    // simulcast layers of it, and the layer it is switching to.
+    source: u32,
+    pending: Option<u32>,
+    // The packets waiting to be sent to a subscriber, the socket is watched for
+    // writing while there are any.
+    queue: VecDeque<Bytes>,
+    writing: bool,
+    // The bandwidth a publisher is allowed to use.
+    bandwidth: Option<Bucket>,
+}
+
+// A token bucket of the bytes a publisher can send, it is filled at the bandwidth
+// limit with headroom and holds the bytes of the whole period.
+struct Bucket {
+    max_bandwidth: i64,
+    rate: f64,
+    tokens: f64,
+    time: Instant,
+}
+
+impl Bucket {
+    fn new(max_bandwidth: i64) -> Self {
+        let rate = max_bandwidth as f64 * BANDWIDTH_HEADROOM;
+        Self {
+            tokens: rate * BANDWIDTH_PERIOD.as_secs_f64(),
+            time: Instant::now(),
+            max_bandwidth,
+            rate,
+        }
+    }
+
+    // Take the bytes from the bucket, returns false if the bucket is empty.
+    fn take(&mut self, size: usize) -> bool {
+        let now = Instant::now();
+        let capacity = self.rate * BANDWIDTH_PERIOD.as_secs_f64();
+        let elapsed = now.duration_since(self.time).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(capacity) - size as f64;
+        self.time = now;
+        self.tokens >= 0.0
+    }
+}
+
+impl Connection {
+    // Send the queued packets until the socket cannot take more without blocking,
+    // the rest is sent when the socket is writable again.
+    fn flush(&mut self, epoll: &Epoll, metrics: &Metrics) -> Result<(), Error> {
+        while let Some(packet) = self.queue.front() {
+            match self.socket.send(packet) {
+                Ok(_) => {
+                    metrics.forwarded(packet.len());
+                    self.queue.pop_front();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let writing = !self.queue.is_empty();
+        if self.writing != writing {
+            self.writing = writing;
+            epoll.update(
+                self.socket.as_raw(),
+                if writing {
+                    Event::IN | Event::OUT | Event::ERR
+                } else {
+                    Event::IN | Event::ERR
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+}
 
-                log::info!(
-                    "accept a srt socket, addr={:?}, info={:?}",
+struct Proxy {
+    route: Arc<Route>,
+    relay: Arc<Relay>,
+    metrics: Arc<Metrics>,
+    secret: Arc<Secret>,
+    limits: Arc<RwLock<Limits>>,
+    epoll: Epoll,
+    connections: HashMap<i32, Connection>,
+    // The raw handles of the connections by address, of the subscribers by the
+    // channel whose packets they get, and of the subscribers waiting for a key frame
+    // of the layer they switch to.
+    handles: HashMap<SocketAddr, i32>,
+    subscribers: HashMap<u32, HashSet<i32>>,
+    switching: HashMap<u32, HashSet<i32>>,
+    // The channels publishing the simulcast layers of a channel, and that channel.
+    layers: HashMap<u32, u32>,
+    // The connections rejected by the limits, closed after REJECT_LINGER.
+    rejected: VecDeque<(Instant, Socket)>,
+}
+
+impl Proxy {
+    fn accept(&mut self, socket: Socket, addr: SocketAddr) {
+        let stream_id = socket.get_stream_id();
+        log::info!(
+            "new srt socket, addr={:?}, stream_id={:?}, km_state={:?}",
+            addr,
+            stream_id,
+            socket.get_km_state()
+        );
+
+        // Get the stream information carried in the srt link. If the stream information
+        // does not exist or is invalid, the current connection is rejected. Returning
+        // directly will trigger the release of the link and close it.
+        let stream_info =
+            if let Some(info) = stream_id.as_ref().and_then(|it| StreamInfo::decode(it)) {
+                info
+            } else {
+                log::error!("invalid stream id, addr={:?}", addr);
+
+                return;
+            };
+
+        // The clients speaking a protocol version the service does not support are
+        // rejected, they would not understand the forwarded data either.
+        if !is_supported(stream_info.version) {
+            log::error!(
+                "reject a srt socket, addr={:?}, id={}, kind={:?}, version={}",
+                addr,
+                stream_info.id,
+                stream_info.kind,
+                stream_info.version
+            );
+
+            return;
+        }
+
+        // When the service is configured with a secret, only the clients carrying a
+        // valid token for the channel and role are accepted, the rejected connection is
+        // released and closed like the invalid ones.
+        if let Some(secret) = self.secret.get() {
+            if let Err(e) = auth::verify(&secret, &stream_info) {
+                log::error!(
+                    "reject a srt socket, addr={:?}, id={}, kind={:?}, err={}",
                     addr,
-                    stream_info
+                    stream_info.id,
+                    stream_info.kind,
+                    e
                 );
 
-                // The multicast port number exists only for publishers
-                if let Some(port) = stream_info.port {
-                    route.add(stream_info.id, port)
-                }
+                return;
+            }
+        }
 
-                {
-                    // If it is a subscriber, add the current connection to the subscription
-                    // connection pool
-                    if stream_info.kind == SocketKind::Subscriber {
-                        sockets.write().unwrap().insert(addr, socket.clone());
-                        subscribers
-                            .write()
-                            .unwrap()
-                            .entry(stream_info.id)
-                            .or_insert_with(|| HashSet::with_capacity(200))
-                            .insert(addr);
-                    }
+        // The limits are checked after the authentication, so that the clients without
+        // a valid token cannot take the place of the others.
+        let limits = self.limits.read().unwrap().clone();
+        if let Err(reason) = self.relay.admit(&stream_info, &limits) {
+            log::warn!(
+                "reject a srt socket, addr={:?}, id={}, kind={:?}, reason={}",
+                addr,
+                stream_info.id,
+                stream_info.kind,
+                reason
+            );
+
+            // The client is told why before the connection is closed, the socket is kept
+            // for a moment so that the feedback is delivered.
+            if let Err(e) = socket.send(&Feedback::Rejected { reason }.encode()) {
+                log::warn!("not send the rejection, addr={:?}, err={:?}", addr, e);
+            }
+
+            self.rejected.push_back((Instant::now(), socket));
+            return;
+        }
+
+        let handle = socket.as_raw();
+        if let Err(e) = socket
+            .set_nonblocking()
+            .and_then(|_| self.epoll.add(handle, Event::IN | Event::ERR))
+        {
+            log::error!("not watch a srt socket, addr={:?}, err={:?}", addr, e);
+
+            return;
+        }
+
+        log::info!(
+            "accept a srt socket, addr={:?}, info={:?}",
+            addr,
+            stream_info
+        );
+
+        // The multicast port number exists only for publishers
+        if let Some(port) = stream_info.port {
+            self.route.add(stream_info.id, port, stream_info.multicast)
+        }
+
+        let socket = Arc::new(socket);
+
+        // The subscriber asking for a simulcast layer gets the packets of the layer
+        // from the start, the layers that are not published fall back to the channel.
+        let source = match stream_info.layer {
+            Some(layer) if self.is_layer_of(layer, stream_info.id) => layer,
+            Some(layer) => {
+                log::warn!(
+                    "subscribe to a missing layer, addr={:?}, id={}, layer={}",
+                    addr,
+                    stream_info.id,
+                    layer
+                );
+
+                stream_info.id
+            }
+            None => stream_info.id,
+        };
+
+        // If it is a subscriber, add the current connection to the subscription
+        // connection pool
+        if stream_info.kind == SocketKind::Subscriber {
+            self.relay
+                .sockets
+                .write()
+                .unwrap()
+                .insert(addr, socket.clone());
+            self.relay
+                .subscribers
+                .write()
+                .unwrap()
+                .entry(stream_info.id)
+                .or_insert_with(|| HashSet::with_capacity(200))
+                .insert(addr);
+
+            self.subscribers
+                .entry(source)
+                .or_insert_with(|| HashSet::with_capacity(200))
+                .insert(handle);
+        } else {
+            // The publisher connection is recorded so that the feedback from the
+            // subscribers can be forwarded to it
+            self.relay
+                .publishers
+                .write()
+                .unwrap()
+                .insert(stream_info.id, (addr, socket.clone()));
+
+            // The publisher caps its own sending bandwidth when it is told the limit.
+            if let Some(max_bandwidth) = limits.publisher_max_bandwidth {
+                if let Err(e) = socket.send(&Feedback::BandwidthLimit { max_bandwidth }.encode()) {
+                    log::warn!(
+                        "not send the bandwidth limit to publisher, id={}, err={:?}",
+                        stream_info.id,
+                        e
+                    );
                 }
+            }
+        }
 
-                let socket = socket.clone();
-                let sockets = sockets.clone();
-                let subscribers = subscribers.clone();
-                thread::spawn(move || {
-                    let mut buf = [0u8; 2000];
-                    let mut closed = Vec::with_capacity(100);
-
-                    loop {
-                        match socket.read(&mut buf) {
-                            Ok(size) => {
-                                if size == 0 {
-                                    break;
-                                }
-
-                                // Subscribers are not allowed to write any information to the
-                                // server!
-                                if stream_info.kind == SocketKind::Subscriber {
-                                    break;
-                                }
-
-                                closed.clear();
-
-                                {
-                                    let sockets = sockets.read().unwrap();
-                                    let subscribers = subscribers.read().unwrap();
-
-                                    // Forwards all packets sent by the publisher to all subscribers
-                                    // of the same channel
-                                    if let Some(items) = subscribers.get(&stream_info.id) {
-                                        for addr in items.iter() {
-                                            if let Some(socket) = sockets.get(addr) {
-                                                if let Err(e) = socket.send(&buf[..size]) {
-                                                    closed.push(*addr);
-
-                                                    log::warn!(
-                                                        "not send a buf to srt socket, addr={:?}, err={:?}",
-                                                        addr,
-                                                        e
-                                                    );
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                // Some subscribers have expired, clean up all expired subscribers
-                                if !closed.is_empty() {
-                                    let mut sockets = sockets.write().unwrap();
-                                    for addr in &closed {
-                                        if let Some(socket) = sockets.remove(addr) {
-                                            socket.close()
-                                        }
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                log::warn!(
-                                    "not recv a buf to srt socket, addr={:?}, err={:?}",
-                                    addr,
-                                    e
-                                );
-
-                                break;
-                            }
-                        }
-                    }
+        self.handles.insert(addr, handle);
+        self.connections.insert(
+            handle,
+            Connection {
+                queue: VecDeque::with_capacity(QUEUE_SIZE),
+                bandwidth: limits.publisher_max_bandwidth.map(Bucket::new),
+                info: stream_info,
+                writing: false,
+                pending: None,
+                socket,
+                source,
+                addr,
+            },
+        );
+
+        // The subscriber asking for a layer is told which one it gets.
+        if let Some(connection) = self
+            .connections
+            .get_mut(&handle)
+            .filter(|it| it.info.layer.is_some())
+        {
+            connection
+                .queue
+                .push_back(Feedback::LayerSwitched { id: source }.encode());
+        }
+    }
 
-                    log::info!("srt socket closed, addr={:?}, info={:?}", addr, stream_info);
+    // Whether the channel is published and publishes a simulcast layer of the other
+    // channel, a channel is also a layer of itself.
+    fn is_layer_of(&self, layer: u32, id: u32) -> bool {
+        (layer == id || self.layers.get(&layer) == Some(&id))
+            && self.relay.publishers.read().unwrap().contains_key(&layer)
+    }
 
-                    let mut sockets = sockets.write().unwrap();
-                    let mut subscribers = subscribers.write().unwrap();
+    fn read(&mut self, handle: i32, buf: &mut [u8]) {
+        let connection = if let Some(it) = self.connections.get_mut(&handle) {
+            it
+        } else {
+            return;
+        };
 
-                    // If the publisher has exited, it is necessary to close all subscribers of the
-                    // current channel and inform the client that the publisher has exited.
-                    if stream_info.kind == SocketKind::Publisher {
-                        if let Some(items) = subscribers.remove(&stream_info.id) {
-                            for addr in items.iter() {
-                                if let Some(socket) = sockets.remove(addr) {
-                                    socket.close()
-                                }
-                            }
-                        }
+        let size = match connection.socket.read(buf) {
+            Ok(size) if size > 0 => size,
+            Ok(_) => return self.close(handle),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+            Err(e) => {
+                log::warn!(
+                    "not recv a buf to srt socket, addr={:?}, err={:?}",
+                    connection.addr,
+                    e
+                );
+
+                return self.close(handle);
+            }
+        };
+
+        let id = connection.info.id;
+
+        // Subscribers are not allowed to write any information to the server other than
+        // feedback, which is forwarded to the publisher of the same channel.
+        if connection.info.kind == SocketKind::Subscriber {
+            match Feedback::decode(&buf[..size]) {
+                // Only the service limits the publishers, switches the layers and rejects
+                // the clients.
+                Some(
+                    Feedback::BandwidthLimit { .. }
+                    | Feedback::LayerSwitched { .. }
+                    | Feedback::Rejected { .. },
+                ) => (),
+                Some(Feedback::SelectLayer { id: layer }) => self.select(handle, layer),
+                Some(feedback) => {
+                    // The key frames are requested from the layer the subscriber gets, the
+                    // multicast is only sent by the publisher of the channel.
+                    let id = if feedback == Feedback::KeyFrameRequest {
+                        connection.source
                     } else {
-                        // Subscriber exits, deletes subscription group record
-                        if let Some(items) = subscribers.get_mut(&stream_info.id) {
-                            items.remove(&addr);
+                        id
+                    };
+
+                    log::debug!(
+                        "forward feedback to publisher, addr={:?}, id={}, feedback={:?}",
+                        connection.addr,
+                        id,
+                        feedback
+                    );
+
+                    if let Some((_, publisher)) = self.relay.get_publisher(id) {
+                        if let Err(e) = publisher.send(&buf[..size]) {
+                            log::warn!("not send a feedback to publisher, id={}, err={:?}", id, e);
                         }
                     }
+                }
+                None => self.close(handle),
+            }
+
+            return;
+        }
 
-                    // If the publisher exits, inform the router that the publisher has exited and
-                    // start cleaning up
-                    if stream_info.kind == SocketKind::Publisher {
-                        route.remove(stream_info.id)
+        // The publisher describes the channel after connecting, the description goes to
+        // the route instead of the subscribers.
+        if let Some(description) = StreamDescription::decode(&buf[..size]) {
+            log::info!(
+                "publisher describes channel, id={}, description={:?}",
+                id,
+                description
+            );
+
+            // The channel publishing a simulcast layer of another channel is tagged with
+            // the id of that channel.
+            match description
+                .tags
+                .get(SIMULCAST_TAG)
+                .and_then(|it| it.parse::<u32>().ok())
+            {
+                Some(main) if main != id => {
+                    self.layers.insert(id, main);
+                }
+                _ => {
+                    self.layers.remove(&id);
+                }
+            }
+
+            return self.route.describe(id, description);
+        }
+
+        // The publisher that exceeds the bandwidth limit is closed, a publisher which
+        // caps its sending bandwidth as told never does.
+        if let Some(bandwidth) = connection.bandwidth.as_mut() {
+            if !bandwidth.take(size) {
+                log::warn!(
+                    "close a publisher over the bandwidth limit, addr={:?}, id={}, max_bandwidth={}",
+                    connection.addr,
+                    id,
+                    bandwidth.max_bandwidth
+                );
+
+                return self.close(handle);
+            }
+        }
+
+        // The publishers send the configuration of the video before every key
+        // frame, a subscriber switched to another layer there can decode the
+        // layer from its first packet.
+        if self.switching.contains_key(&id) && package::is_config_start(&buf[..size]) {
+            self.switch(id);
+        }
+
+        self.forward(id, Bytes::copy_from_slice(&buf[..size]));
+    }
+
+    // The subscriber asks for another layer of its channel, it keeps getting the
+    // current layer until the next key frame of the new one, which is requested from
+    // the publisher of the layer.
+    fn select(&mut self, handle: i32, layer: u32) {
+        let (id, source, pending) = if let Some(it) = self.connections.get(&handle) {
+            (it.info.id, it.source, it.pending)
+        } else {
+            return;
+        };
+
+        if !self.is_layer_of(layer, id) {
+            log::warn!("select a missing layer, id={}, layer={}", id, layer);
+
+            return;
+        }
+
+        log::info!("subscriber selects layer, id={}, layer={}", id, layer);
+
+        if let Some(pending) = pending {
+            if let Some(items) = self.switching.get_mut(&pending) {
+                items.remove(&handle);
+                if items.is_empty() {
+                    self.switching.remove(&pending);
+                }
+            }
+        }
+
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            connection.pending = (layer != source).then_some(layer);
+        }
+
+        if layer != source {
+            self.switching
+                .entry(layer)
+                .or_insert_with(|| HashSet::with_capacity(10))
+                .insert(handle);
+
+            if let Some((_, publisher)) = self.relay.get_publisher(layer) {
+                if let Err(e) = publisher.send(&Feedback::KeyFrameRequest.encode()) {
+                    log::warn!(
+                        "not send a feedback to publisher, id={}, err={:?}",
+                        layer,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    // The layer reaches a key frame, the subscribers waiting for it get the packets
+    // of the layer from now on, they are told before the first packet.
+    fn switch(&mut self, layer: u32) {
+        for handle in self.switching.remove(&layer).unwrap_or_default() {
+            let connection = if let Some(it) = self.connections.get_mut(&handle) {
+                it
+            } else {
+                continue;
+            };
+
+            if let Some(items) = self.subscribers.get_mut(&connection.source) {
+                items.remove(&handle);
+            }
+
+            connection.source = layer;
+            connection.pending = None;
+            connection
+                .queue
+                .push_back(Feedback::LayerSwitched { id: layer }.encode());
+
+            self.subscribers
+                .entry(layer)
+                .or_insert_with(|| HashSet::with_capacity(200))
+                .insert(handle);
+
+            log::info!(
+                "subscriber switched layer, addr={:?}, id={}, layer={}",
+                connection.addr,
+                connection.info.id,
+                layer
+            );
+        }
+    }
+
+    // Queue the packet of the publisher for all subscribers of the same channel, the
+    // sockets do not block, so a slow subscriber does not delay the others.
+    fn forward(&mut self, id: u32, packet: Bytes) {
+        let mut closed = Vec::new();
+
+        if let Some(handles) = self.subscribers.get(&id) {
+            for handle in handles {
+                let connection = if let Some(it) = self.connections.get_mut(handle) {
+                    it
+                } else {
+                    continue;
+                };
+
+                if connection.queue.len() >= QUEUE_SIZE {
+                    log::warn!(
+                        "drop a subscriber which does not keep up, addr={:?}, id={}",
+                        connection.addr,
+                        id
+                    );
+
+                    closed.push(*handle);
+                    continue;
+                }
+
+                connection.queue.push_back(packet.clone());
+
+                // A subscriber which is already waiting for its socket to be writable gets
+                // the packet when it is.
+                if !connection.writing {
+                    if let Err(e) = connection.flush(&self.epoll, &self.metrics) {
+                        log::warn!(
+                            "not send a buf to srt socket, addr={:?}, err={:?}",
+                            connection.addr,
+                            e
+                        );
+
+                        closed.push(*handle);
                     }
-                });
+                }
             }
+        }
+
+        // Some subscribers have expired, clean up all expired subscribers
+        for handle in closed {
+            self.metrics.dropped();
+            self.close(handle);
+        }
+    }
+
+    fn flush(&mut self, handle: i32) {
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            if let Err(e) = connection.flush(&self.epoll, &self.metrics) {
+                log::warn!(
+                    "not send a buf to srt socket, addr={:?}, err={:?}",
+                    connection.addr,
+                    e
+                );
+
+                self.metrics.dropped();
+                self.close(handle);
+            }
+        }
+    }
+
+    fn close(&mut self, handle: i32) {
+        let connection = if let Some(it) = self.connections.remove(&handle) {
+            it
+        } else {
+            return;
+        };
+
+        let _ = self.epoll.remove(handle);
+        connection.socket.close();
+        self.handles.remove(&connection.addr);
+
+        log::info!(
+            "srt socket closed, addr={:?}, info={:?}",
+            connection.addr,
+            connection.info
+        );
+
+        let id = connection.info.id;
+        if connection.info.kind == SocketKind::Publisher {
+            // If the publisher has exited, it is necessary to close all subscribers of the
+            // current channel and inform the router that the publisher has exited.
+            self.relay.publishers.write().unwrap().remove(&id);
+            self.relay.subscribers.write().unwrap().remove(&id);
+            self.route.remove(id);
+            self.layers.remove(&id);
+
+            let subscribers = self
+                .connections
+                .iter()
+                .filter(|(_, it)| it.info.kind == SocketKind::Subscriber)
+                .filter(|(_, it)| it.info.id == id || it.source == id || it.pending == Some(id))
+                .map(|(handle, it)| (*handle, it.info.id))
+                .collect::<Vec<_>>();
+
+            self.subscribers.remove(&id);
+            self.switching.remove(&id);
+
+            for (handle, channel) in subscribers {
+                if channel == id {
+                    self.close(handle);
+                    continue;
+                }
+
+                // The subscribers of the other channels which get this layer, or are
+                // switching to it, go back to their own channel.
+                let source = if let Some(connection) = self.connections.get_mut(&handle) {
+                    connection.pending = None;
+                    connection.source
+                } else {
+                    continue;
+                };
+
+                if source == id {
+                    self.select(handle, channel);
+                }
+            }
+        } else {
+            // Subscriber exits, deletes subscription group record
+            self.relay.sockets.write().unwrap().remove(&connection.addr);
+
+            if let Some(items) = self.relay.subscribers.write().unwrap().get_mut(&id) {
+                items.remove(&connection.addr);
+            }
+
+            if let Some(items) = self.subscribers.get_mut(&connection.source) {
+                items.remove(&handle);
+            }
+
+            if let Some(items) = connection
+                .pending
+                .and_then(|it| self.switching.get_mut(&it))
+            {
+                items.remove(&handle);
+            }
+        }
+    }
+}
+
+/// Run the relay, all srt connections are handled by one event loop on the
+/// calling thread.
+pub fn start_server(
+    config: Configure,
+    route: Arc<Route>,
+    relay: Arc<Relay>,
+    metrics: Arc<Metrics>,
+    secret: Arc<Secret>,
+    limits: Arc<RwLock<Limits>>,
+) -> Result<()> {
+    // Configuration of the srt server.
+    let mut opt = Options::default();
+    opt.mtu = config.srt.mtu;
+    opt.latency = config.srt.latency;
+    opt.fc = config.srt.fc;
+    opt.fec = config.srt.fec.clone();
+    opt.max_bandwidth = config.srt.max_bandwidth;
+    opt.passphrase = config.auth.passphrase.clone();
+    opt.key_length = config.auth.key_length;
+
+    // Start the srt server
+    let server = Server::bind(config.srt_bind(), opt, config.srt.backlog)?;
+    log::info!("starting srt server...");
+
+    let epoll = Epoll::new()?;
+    epoll.add(server.as_raw(), Event::IN | Event::ERR)?;
+
+    let mut proxy = Proxy {
+        connections: HashMap::with_capacity(200),
+        handles: HashMap::with_capacity(200),
+        subscribers: HashMap::with_capacity(200),
+        switching: HashMap::with_capacity(10),
+        layers: HashMap::with_capacity(10),
+        rejected: VecDeque::with_capacity(10),
+        metrics,
+        secret,
+        limits,
+        route,
+        relay,
+        epoll,
+    };
+
+    let mut events = vec![Event::default(); 1024];
+    let mut buf = [0u8; 2000];
+
+    loop {
+        // Close the connections stopped or kicked by the admin api.
+        let closing = mem::take(&mut *proxy.relay.closing.lock().unwrap());
+        for addr in closing {
+            if let Some(handle) = proxy.handles.get(&addr).copied() {
+                proxy.close(handle);
+            }
+        }
+
+        while proxy
+            .rejected
+            .front()
+            .is_some_and(|(time, _)| time.elapsed() >= REJECT_LINGER)
+        {
+            proxy.rejected.pop_front();
+        }
+
+        let size = match proxy.epoll.wait(&mut events, WAIT_TIMEOUT) {
+            Ok(size) => size,
             Err(e) => {
                 log::error!("{:?}", e);
 
                 break;
             }
+        };
+
+        for event in &events[..size] {
+            if event.fd == server.as_raw() {
+                match server.accept() {
+                    Ok((socket, addr)) => proxy.accept(socket, addr),
+                    Err(e) => {
+                        log::error!("{:?}", e);
+
+                        return Ok(());
+                    }
+                }
+
+                continue;
+            }
+
+            if event.is_error() {
+                proxy.close(event.fd);
+                continue;
+            }
+
+            if event.is_writable() {
+                proxy.flush(event.fd);
+            }
+
+            if event.is_readable() {
+                proxy.read(event.fd, &mut buf);
+            }
         }
     }
 
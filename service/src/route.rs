@@ -1,11 +1,17 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{collections::HashMap, net::IpAddr, sync::RwLock};
 
 use tokio::sync::broadcast::{channel, Receiver, Sender};
 
-use crate::signal::Signal;
+use crate::{description::StreamDescription, signal::Signal};
+
+struct Node {
+    port: u16,
+    multicast: Option<IpAddr>,
+    description: Option<StreamDescription>,
+}
 
 pub struct Route {
-    nodes: RwLock<HashMap<u32, u16>>,
+    nodes: RwLock<HashMap<u32, Node>>,
     tx: Sender<Signal>,
     rx: Receiver<Signal>,
 }
@@ -23,13 +29,50 @@ impl Default for Route {
 
 impl Route {
     /// Add a channel to the route, where the port number is the multicast port
-    /// on the sender side
+    /// on the sender side, and the multicast group is the group used by the
+    /// sender
     ///
     /// This will trigger an event update, which will broadcast a channel
     /// release event
-    pub fn add(&self, id: u32, port: u16) {
-        self.nodes.write().unwrap().insert(id, port);
-        self.tx.send(Signal::Start { id, port }).unwrap();
+    pub fn add(&self, id: u32, port: u16, multicast: Option<IpAddr>) {
+        self.nodes.write().unwrap().insert(
+            id,
+            Node {
+                description: None,
+                multicast,
+                port,
+            },
+        );
+
+        self.tx
+            .send(Signal::Start {
+                description: None,
+                multicast,
+                port,
+                id,
+            })
+            .unwrap();
+    }
+
+    /// Set the description of a published channel
+    ///
+    /// This will trigger an event update, which will broadcast the channel
+    /// release event again with the description
+    pub fn describe(&self, id: u32, description: StreamDescription) {
+        let signal = if let Some(node) = self.nodes.write().unwrap().get_mut(&id) {
+            node.description = Some(description.clone());
+
+            Signal::Start {
+                description: Some(description),
+                multicast: node.multicast,
+                port: node.port,
+                id,
+            }
+        } else {
+            return;
+        };
+
+        self.tx.send(signal).unwrap();
     }
 
     /// Delete a published channel
@@ -43,12 +86,12 @@ impl Route {
     }
 
     /// Get all channels that are publishing
-    pub fn get_channels(&self) -> Vec<(u32, u16)> {
+    pub fn get_channels(&self) -> Vec<(u32, u16, Option<IpAddr>, Option<StreamDescription>)> {
         self.nodes
             .read()
             .unwrap()
             .iter()
-            .map(|(k, v)| (*k, *v))
+            .map(|(k, node)| (*k, node.port, node.multicast, node.description.clone()))
             .collect()
     }
 
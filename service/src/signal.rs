@@ -1,19 +1,98 @@
-use std::{io::Error, net::SocketAddr, sync::Arc};
+use std::{
+    io::Error,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
 use bytes::{BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpListener,
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    auth::{self, Secret},
+    description::StreamDescription,
+    metrics::Metrics,
+    negotiate,
+    route::Route,
+    StreamInfo, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
 };
 
-use crate::route::Route;
+/// How long each side waits for the handshake and the credential of a new
+/// signal connection.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The messages of the signal connection are prefixed with their size, the prefix
+// included. Returns the size and the payload of the first message, if the whole
+// message has arrived, the messages with a size shorter than the prefix are never
+// complete.
+fn frame(buf: &[u8]) -> Option<(usize, &[u8])> {
+    let size = u16::from_be_bytes([*buf.first()?, *buf.get(1)?]) as usize;
+    if size < 2 {
+        return None;
+    }
+
+    buf.get(2..size).map(|payload| (size, payload))
+}
+
+/// The first messages of the signal connection. The client sends the versions
+/// it supports, and the service answers with the version used on the
+/// connection, or rejects the client when they have no version in common,
+/// the client can then report the versions of the service.
+///
+/// The service also tells the accepted clients the port of its srt server,
+/// which is on the host of the signal server, the port of the signal server
+/// is used if it is not sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Handshake {
+    Hello {
+        version: u8,
+        min_version: u8,
+    },
+    Accept {
+        version: u8,
+        #[serde(default)]
+        srt_port: Option<u16>,
+    },
+    Reject {
+        version: u8,
+        min_version: u8,
+    },
+}
+
+impl Handshake {
+    pub fn encode(&self) -> Bytes {
+        let payload = rmp_serde::to_vec(&self).unwrap();
+        let mut buf = BytesMut::with_capacity(payload.len() + 2);
+        buf.put_u16(buf.capacity() as u16);
+        buf.extend_from_slice(&payload);
+        buf.freeze()
+    }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+    pub fn decode(buf: &[u8]) -> Option<(usize, Self)> {
+        let (size, payload) = frame(buf)?;
+        rmp_serde::from_slice(payload).ok().map(|it| (size, it))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum Signal {
     /// Start publishing a channel. The port number is the publisher's multicast
-    /// port.
-    Start { id: u32, port: u16 },
+    /// port, and the multicast group is the group used by the publisher, if
+    /// the publisher does not specify it, the receiver uses its own group.
+    ///
+    /// The description is sent by the publisher after connecting, the signal
+    /// is sent again with the description when it arrives.
+    Start {
+        id: u32,
+        port: u16,
+        multicast: Option<IpAddr>,
+        description: Option<StreamDescription>,
+    },
     /// Stop publishing to a channel
     Stop { id: u32 },
 }
@@ -27,24 +106,123 @@ impl Signal {
         buf.freeze()
     }
 
-    #[rustfmt::skip]
     pub fn decode(buf: &[u8]) -> Option<(usize, Self)> {
-        if buf.len() > 2 {
-            let size = u16::from_be_bytes([
-                buf[0],
-                buf[1],
-            ]) as usize;
-
-            if size <= buf.len() {
-                return rmp_serde::from_slice(&buf[2..size]).ok().map(|it| (size, it))
-            }
+        let (size, payload) = frame(buf)?;
+        rmp_serde::from_slice(payload).ok().map(|it| (size, it))
+    }
+}
+
+/// The credential sent by the client after the handshake, it is an encoded
+/// [`StreamInfo`] carrying a token, the same as the srt stream id. Any valid
+/// token is accepted, the channel is not checked.
+#[derive(Debug, Clone)]
+pub struct Credential(pub String);
+
+impl Credential {
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(self.0.len() + 2);
+        buf.put_u16(buf.capacity() as u16);
+        buf.extend_from_slice(self.0.as_bytes());
+        buf.freeze()
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<(usize, Self)> {
+        let (size, payload) = frame(buf)?;
+        std::str::from_utf8(payload)
+            .ok()
+            .map(|it| (size, Self(it.to_string())))
+    }
+}
+
+// Read a message of the client, the client does not send anything else before
+// the service answers, so the messages are read one by one.
+async fn read_message<T>(
+    socket: &mut TcpStream,
+    bytes: &mut BytesMut,
+    decode: impl Fn(&[u8]) -> Option<(usize, T)>,
+) -> Result<T, anyhow::Error> {
+    let mut buf = [0u8; 1024];
+
+    loop {
+        if let Some((size, message)) = decode(bytes) {
+            let _ = bytes.split_to(size);
+            return Ok(message);
         }
 
-        None
+        let size = timeout(HANDSHAKE_TIMEOUT, socket.read(&mut buf)).await??;
+        if size == 0 || bytes.len() + size > buf.len() {
+            return Err(anyhow::anyhow!("message not received"));
+        }
+
+        bytes.extend_from_slice(&buf[..size]);
     }
 }
 
-pub async fn start_server(bind: SocketAddr, route: Arc<Route>) -> Result<(), Error> {
+// Agree on the protocol version with the client, the clients without a version
+// in common with the service are told so before being closed.
+async fn handshake(
+    socket: &mut TcpStream,
+    bytes: &mut BytesMut,
+    srt_port: u16,
+) -> Result<u8, anyhow::Error> {
+    let (version, min_version) = match read_message(socket, bytes, Handshake::decode).await? {
+        Handshake::Hello {
+            version,
+            min_version,
+        } => (version, min_version),
+        _ => return Err(anyhow::anyhow!("unexpected handshake")),
+    };
+
+    if let Some(version) = negotiate(version, min_version) {
+        socket
+            .write_all(
+                &Handshake::Accept {
+                    version,
+                    srt_port: Some(srt_port),
+                }
+                .encode(),
+            )
+            .await?;
+
+        Ok(version)
+    } else {
+        socket
+            .write_all(
+                &Handshake::Reject {
+                    version: PROTOCOL_VERSION,
+                    min_version: MIN_PROTOCOL_VERSION,
+                }
+                .encode(),
+            )
+            .await?;
+
+        Err(anyhow::anyhow!(
+            "incompatible protocol version, version={}, min_version={}",
+            version,
+            min_version
+        ))
+    }
+}
+
+// Read the credential of the client and verify it.
+async fn authenticate(
+    socket: &mut TcpStream,
+    bytes: &mut BytesMut,
+    secret: &str,
+) -> Result<(), anyhow::Error> {
+    let credential = read_message(socket, bytes, Credential::decode).await?;
+    let info =
+        StreamInfo::decode(&credential.0).ok_or_else(|| anyhow::anyhow!("invalid credential"))?;
+    Ok(auth::verify(secret, &info)?)
+}
+
+pub async fn start_server(
+    bind: SocketAddr,
+    srt_port: u16,
+    route: Arc<Route>,
+    metrics: Arc<Metrics>,
+    secret: Arc<Secret>,
+) -> Result<(), Error> {
     let listener = TcpListener::bind(bind).await?;
     loop {
         match listener.accept().await {
@@ -52,17 +230,54 @@ pub async fn start_server(bind: SocketAddr, route: Arc<Route>) -> Result<(), Err
                 log::info!("new signal socket, addr={}", addr);
 
                 let route = route.clone();
+                let secret = secret.get();
+                let connection = metrics.signal_connected();
                 tokio::spawn(async move {
+                    let _connection = connection;
                     if socket.set_nodelay(true).is_err() {
                         return;
                     }
 
+                    let mut bytes = BytesMut::with_capacity(1024);
+                    match handshake(&mut socket, &mut bytes, srt_port).await {
+                        Ok(version) => {
+                            log::info!(
+                                "signal socket handshake, addr={}, version={}",
+                                addr,
+                                version
+                            );
+                        }
+                        Err(e) => {
+                            log::error!("reject a signal socket, addr={}, err={}", addr, e);
+
+                            return;
+                        }
+                    }
+
+                    // The channel list is only revealed to the clients with a valid credential
+                    // when the service is configured with a secret.
+                    if let Some(secret) = secret {
+                        if let Err(e) = authenticate(&mut socket, &mut bytes, &secret).await {
+                            log::error!("reject a signal socket, addr={}, err={}", addr, e);
+
+                            return;
+                        }
+                    }
+
                     // Every time a new connection comes online, notify the current link of all
                     // published channels.
                     {
-                        for (id, port) in route.get_channels() {
+                        for (id, port, multicast, description) in route.get_channels() {
                             if socket
-                                .write_all(&Signal::Start { id, port }.encode())
+                                .write_all(
+                                    &Signal::Start {
+                                        id,
+                                        port,
+                                        multicast,
+                                        description,
+                                    }
+                                    .encode(),
+                                )
                                 .await
                                 .is_err()
                             {
@@ -103,3 +318,55 @@ pub async fn start_server(bind: SocketAddr, route: Arc<Route>) -> Result<(), Err
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_an_encoded_handshake() {
+        let handshake = Handshake::Accept {
+            version: 1,
+            srt_port: Some(8080),
+        };
+
+        let mut buf = handshake.encode().to_vec();
+        let size = buf.len();
+        buf.extend_from_slice(&[0, 5]);
+        assert_eq!(Handshake::decode(&buf), Some((size, handshake)));
+    }
+
+    #[test]
+    fn not_decode_an_invalid_size() {
+        for buf in [
+            &[0, 0, 0x41][..],
+            &[0, 1, 0x41],
+            &[0, 2],
+            &[0, 10, 0x41],
+            &[0],
+            &[],
+        ] {
+            assert_eq!(Handshake::decode(buf), None);
+        }
+    }
+
+    #[test]
+    fn decode_an_encoded_credential() {
+        let buf = Credential("#!::i=1".to_string()).encode();
+        let (size, credential) = Credential::decode(&buf).unwrap();
+        assert_eq!(size, buf.len());
+        assert_eq!(credential.0, "#!::i=1");
+    }
+
+    #[test]
+    fn not_decode_an_invalid_credential() {
+        for buf in [
+            &[0, 0, 0x41][..],
+            &[0, 1, 0x41],
+            &[0, 10, 0x41],
+            &[0, 4, 0xff, 0xfe],
+        ] {
+            assert!(Credential::decode(buf).is_none());
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    ffi::c_int,
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use super::{
+    error, srt_epoll_add_usock, srt_epoll_create, srt_epoll_release, srt_epoll_remove_usock,
+    srt_epoll_set, srt_epoll_update_usock, srt_epoll_uwait, SRT_EPOLL_EVENT,
+};
+
+// Waiting on an empty container is allowed instead of an error.
+const SRT_EPOLL_ENABLE_EMPTY: i32 = 1;
+
+impl SRT_EPOLL_EVENT {
+    /// The socket is ready to read, or a listener has a connection to
+    /// accept.
+    pub const IN: c_int = 0x1;
+    /// The socket is ready to send.
+    pub const OUT: c_int = 0x4;
+    /// The socket is broken or closed.
+    pub const ERR: c_int = 0x8;
+
+    pub fn is_readable(&self) -> bool {
+        self.events & Self::IN != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.events & Self::OUT != 0
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.events & Self::ERR != 0
+    }
+}
+
+/// A container of srt sockets waiting for them to be ready, which lets one
+/// thread handle many sockets, see the `srt_epoll_*` functions.
+///
+/// The sockets are identified by their raw handle, see `Socket::as_raw` and
+/// `Server::as_raw`, and are watched level-triggered.
+pub struct Epoll {
+    id: c_int,
+}
+
+unsafe impl Send for Epoll {}
+unsafe impl Sync for Epoll {}
+
+impl Epoll {
+    pub fn new() -> Result<Self, Error> {
+        let id = unsafe { srt_epoll_create() };
+        if id < 0 {
+            return Err(error());
+        }
+
+        let epoll = Self { id };
+        if unsafe { srt_epoll_set(id, SRT_EPOLL_ENABLE_EMPTY) } < 0 {
+            return Err(error());
+        }
+
+        Ok(epoll)
+    }
+
+    /// Watch the socket for the events, a combination of `Event::IN`,
+    /// `Event::OUT` and `Event::ERR`.
+    pub fn add(&self, socket: i32, events: c_int) -> Result<(), Error> {
+        if unsafe { srt_epoll_add_usock(self.id, socket, &events) } != 0 {
+            return Err(error());
+        }
+
+        Ok(())
+    }
+
+    /// Change the events watched for the socket.
+    pub fn update(&self, socket: i32, events: c_int) -> Result<(), Error> {
+        if unsafe { srt_epoll_update_usock(self.id, socket, &events) } != 0 {
+            return Err(error());
+        }
+
+        Ok(())
+    }
+
+    /// Stop watching the socket, a closed socket is removed automatically.
+    pub fn remove(&self, socket: i32) -> Result<(), Error> {
+        if unsafe { srt_epoll_remove_usock(self.id, socket) } != 0 {
+            return Err(error());
+        }
+
+        Ok(())
+    }
+
+    /// Wait up to the timeout for the sockets to be ready, the ready sockets
+    /// and their events are written to `events`, returns the number of them,
+    /// 0 if the timeout has passed.
+    pub fn wait(&self, events: &mut [SRT_EPOLL_EVENT], timeout: Duration) -> Result<usize, Error> {
+        let size = unsafe {
+            srt_epoll_uwait(
+                self.id,
+                events.as_mut_ptr(),
+                events.len() as c_int,
+                timeout.as_millis() as i64,
+            )
+        };
+
+        if size < 0 {
+            let e = error();
+            return match e.kind() {
+                ErrorKind::TimedOut => Ok(0),
+                _ => Err(e),
+            };
+        }
+
+        Ok(size as usize)
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe {
+            srt_epoll_release(self.id);
+        }
+    }
+}
@@ -1,6 +1,22 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use xxhash_rust::xxh3::xxh3_64;
 
+/// The header of a fragment, the check digit (8), the sequence of the payload
+/// (8) and the size of the whole payload (4).
+pub const FRAGMENT_HEAD_SIZE: usize = 20;
+
+/// The size of the whole payload and the part of it carried by the fragment,
+/// the check digit is not verified.
+pub fn split_fragment(mut bytes: &[u8]) -> Option<(usize, &[u8])> {
+    if bytes.len() < FRAGMENT_HEAD_SIZE {
+        return None;
+    }
+
+    bytes.advance(16);
+    let size = bytes.get_u32() as usize;
+    Some((size, bytes))
+}
+
 pub struct FragmentEncoder {
     packets: Vec<BytesMut>,
     sequence: u64,
@@ -18,7 +34,7 @@ impl FragmentEncoder {
 
     pub fn encode(&mut self, bytes: &[u8]) -> &[BytesMut] {
         let mut size = 0;
-        for (i, chunk) in bytes.chunks(self.mtu - 20).enumerate() {
+        for (i, chunk) in bytes.chunks(self.mtu - FRAGMENT_HEAD_SIZE).enumerate() {
             {
                 if self.packets.get(i).is_none() {
                     self.packets.push(BytesMut::with_capacity(self.mtu));
@@ -44,31 +44,46 @@
 //!     Ok(())
 //! }
 
+mod epoll;
 mod fragments;
 mod options;
 mod server;
 mod socket;
 
 pub use self::{
-    fragments::{FragmentDecoder, FragmentEncoder},
+    epoll::Epoll,
+    fragments::{split_fragment, FragmentDecoder, FragmentEncoder, FRAGMENT_HEAD_SIZE},
     options::Options,
     server::Server,
     socket::Socket,
-    SRT_TRACEBSTATS as TraceStats,
+    SRT_EPOLL_EVENT as Event, SRT_KM_STATE as KmState, SRT_TRACEBSTATS as TraceStats,
 };
 
 use std::{
     ffi::{c_char, c_int, c_void, CStr},
-    io::Error,
-    ptr::null,
+    io::{Error, ErrorKind},
+    ptr::{null, null_mut},
 };
 
 use libc::sockaddr;
 use log::{log, Level};
 use utils::strings::Strings;
 
+// The error codes of the non-blocking operations that cannot be done now and
+// of the timeouts, see `SRT_ERRNO`.
+const SRT_EASYNCSND: c_int = 6001;
+const SRT_EASYNCRCV: c_int = 6002;
+const SRT_ETIMEOUT: c_int = 6003;
+
 pub(crate) fn error() -> Error {
-    Error::other(
+    let kind = match unsafe { srt_getlasterror(null_mut()) } {
+        SRT_EASYNCSND | SRT_EASYNCRCV => ErrorKind::WouldBlock,
+        SRT_ETIMEOUT => ErrorKind::TimedOut,
+        _ => ErrorKind::Other,
+    };
+
+    Error::new(
+        kind,
         unsafe { CStr::from_ptr(srt_getlasterror_str()) }
             .to_str()
             .map(|s| s.to_string())
@@ -124,6 +139,14 @@ pub fn cleanup() {
 
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) type SRTSOCKET = i32;
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SRT_EPOLL_EVENT {
+    pub fd: SRTSOCKET,
+    pub events: c_int,
+}
 pub(crate) const SRT_INVALID_SOCK: i32 = -1;
 
 #[repr(C)]
@@ -142,6 +165,39 @@ pub enum SRT_SOCKSTATUS {
     SRTS_NONEXIST,
 }
 
+/// The state of the key material exchange, which tells whether the
+/// connection is encrypted.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SRT_KM_STATE {
+    /// No encryption is used on either side.
+    SRT_KM_S_UNSECURED = 0,
+    /// The key material exchange is in progress.
+    SRT_KM_S_SECURING = 1,
+    /// The connection is encrypted.
+    SRT_KM_S_SECURED = 2,
+    /// The peer uses encryption but no passphrase was set on this side.
+    SRT_KM_S_NOSECRET = 3,
+    /// The passphrases of the two sides do not match.
+    SRT_KM_S_BADSECRET = 4,
+}
+
+impl TryFrom<i32> for SRT_KM_STATE {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::SRT_KM_S_UNSECURED,
+            1 => Self::SRT_KM_S_SECURING,
+            2 => Self::SRT_KM_S_SECURED,
+            3 => Self::SRT_KM_S_NOSECRET,
+            4 => Self::SRT_KM_S_BADSECRET,
+            _ => return Err(Error::other(format!("unknown km state={}", value))),
+        })
+    }
+}
+
 #[repr(C)]
 #[allow(unused)]
 #[allow(non_camel_case_types)]
@@ -335,6 +391,9 @@ pub struct SRT_TRACEBSTATS {
 
 extern "C" {
     pub(crate) fn srt_getlasterror_str() -> *const c_char;
+    /// Get the numeric code of the last error, and the system error code in
+    /// `errno_loc` if it is not null.
+    pub(crate) fn srt_getlasterror(errno_loc: *mut c_int) -> c_int;
     /// By default logs are printed to standard error stream. This function
     /// replaces the sending to a stream with a handler function that will
     /// receive them.
@@ -764,4 +823,29 @@ extern "C" {
     /// perf: Pointer to an object to be written with the statistics
     /// clear: 1 if the statistics should be cleared after retrieval
     pub fn srt_bstats(s: SRTSOCKET, perf: *mut SRT_TRACEBSTATS, clear: c_int) -> c_int;
+    /// Creates a new epoll container, returns its id or -1.
+    pub(crate) fn srt_epoll_create() -> c_int;
+    /// Sets the flags of the epoll container, such as
+    /// `SRT_EPOLL_ENABLE_EMPTY`, returns the previous flags or -1.
+    pub(crate) fn srt_epoll_set(eid: c_int, flags: i32) -> i32;
+    /// Adds a socket to the epoll container with the events to watch, which
+    /// are a combination of `SRT_EPOLL_IN`, `SRT_EPOLL_OUT` and
+    /// `SRT_EPOLL_ERR`.
+    pub(crate) fn srt_epoll_add_usock(eid: c_int, u: SRTSOCKET, events: *const c_int) -> c_int;
+    /// Changes the events watched for a socket already in the container.
+    pub(crate) fn srt_epoll_update_usock(eid: c_int, u: SRTSOCKET, events: *const c_int) -> c_int;
+    /// Removes a socket from the epoll container, closed sockets are removed
+    /// automatically.
+    pub(crate) fn srt_epoll_remove_usock(eid: c_int, u: SRTSOCKET) -> c_int;
+    /// Waits up to `ms_timeout` milliseconds, -1 for ever, for the sockets of
+    /// the container to be ready, fills `fds_set` with the ready sockets and
+    /// their events and returns the number of them, or -1.
+    pub(crate) fn srt_epoll_uwait(
+        eid: c_int,
+        fds_set: *mut SRT_EPOLL_EVENT,
+        fds_size: c_int,
+        ms_timeout: i64,
+    ) -> c_int;
+    /// Releases the epoll container, the sockets in it are not closed.
+    pub(crate) fn srt_epoll_release(eid: c_int) -> c_int;
 }
@@ -18,6 +18,13 @@ pub struct Options {
     pub fec: String,
     pub mtu: u32,
     pub fc: u32,
+    /// The passphrase of the encryption, both sides must use the same one,
+    /// it must be 10 to 79 characters long. `None` disables encryption.
+    pub passphrase: Option<String>,
+    /// The length of the encryption key in bytes, 16, 24 or 32, which is
+    /// AES-128, AES-192 or AES-256. 0 takes the key length of the peer, or
+    /// 16 if the peer has not set one either.
+    pub key_length: u32,
 }
 
 impl Options {
@@ -38,6 +45,15 @@ impl Options {
             set_sock_opt_str(fd, SRT_SOCKOPT::SRTO_STREAMID, stream_id)?;
         }
 
+        if let Some(passphrase) = &self.passphrase {
+            set_sock_opt_str(fd, SRT_SOCKOPT::SRTO_PASSPHRASE, passphrase)?;
+            set_sock_opt(fd, SRT_SOCKOPT::SRTO_PBKEYLEN, &self.key_length)?;
+
+            // Refuse a peer that does not use the same passphrase, instead of
+            // falling back to an unencrypted connection.
+            set_sock_opt(fd, SRT_SOCKOPT::SRTO_ENFORCEDENCRYPTION, &1_i32)?;
+        }
+
         Ok(())
     }
 
@@ -52,6 +68,8 @@ impl Default for Options {
             fec: "fec,layout:staircase,rows:5,cols:10,arq:onreq".to_string(),
             max_bandwidth: -1,
             stream_id: None,
+            passphrase: None,
+            key_length: 0,
             timeout: 5000,
             latency: 120,
             mtu: 1500,
@@ -60,7 +78,7 @@ impl Default for Options {
     }
 }
 
-fn set_sock_opt<T: Sized + Debug + PartialEq>(
+pub(crate) fn set_sock_opt<T: Sized + Debug + PartialEq>(
     sock: SRTSOCKET,
     opt: SRT_SOCKOPT,
     flag: &T,
@@ -106,3 +124,14 @@ pub(crate) fn get_sock_opt_str(sock: SRTSOCKET, opt: SRT_SOCKOPT) -> Option<Stri
         None
     }
 }
+
+pub(crate) fn get_sock_opt_i32(sock: SRTSOCKET, opt: SRT_SOCKOPT) -> Option<i32> {
+    let mut optval: i32 = 0;
+    let mut optlen = size_of::<i32>() as c_int;
+
+    if unsafe { srt_getsockflag(sock, opt, &mut optval as *mut i32 as *mut _, &mut optlen) } == 0 {
+        Some(optval)
+    } else {
+        None
+    }
+}
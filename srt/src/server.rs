@@ -17,6 +17,11 @@ unsafe impl Send for Server {}
 unsafe impl Sync for Server {}
 
 impl Server {
+    /// The raw handle of the listener, which identifies it in an `Epoll`.
+    pub fn as_raw(&self) -> i32 {
+        self.fd
+    }
+
     /// Reports the current statistics
     ///
     /// Arguments:
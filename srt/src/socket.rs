@@ -2,7 +2,10 @@ use std::{ffi::c_int, io::Error, net::SocketAddr};
 
 use os_socketaddr::OsSocketAddr;
 
-use crate::{options::get_sock_opt_str, SRT_SOCKOPT};
+use crate::{
+    options::{get_sock_opt_i32, get_sock_opt_str, set_sock_opt},
+    SRT_KM_STATE, SRT_SOCKOPT,
+};
 
 use super::{
     error, options::Options, srt_bstats, srt_close, srt_connect, srt_create_socket, srt_recv,
@@ -18,10 +21,36 @@ impl Socket {
         Self { fd }
     }
 
+    /// The raw handle of the socket, which identifies it in an `Epoll`.
+    pub fn as_raw(&self) -> i32 {
+        self.fd
+    }
+
     pub fn get_stream_id(&self) -> Option<String> {
         get_sock_opt_str(self.fd, SRT_SOCKOPT::SRTO_STREAMID)
     }
 
+    /// The state of the key material exchange of the connection, the
+    /// connection is encrypted when it is `SRT_KM_S_SECURED`.
+    pub fn get_km_state(&self) -> Result<SRT_KM_STATE, Error> {
+        get_sock_opt_i32(self.fd, SRT_SOCKOPT::SRTO_KMSTATE)
+            .ok_or_else(error)?
+            .try_into()
+    }
+
+    /// Change the maximum sending bandwidth of the connection in bytes per
+    /// second, -1 is unlimited. See `SRTO_MAXBW`.
+    pub fn set_max_bandwidth(&self, bytes: i64) -> Result<(), Error> {
+        set_sock_opt(self.fd, SRT_SOCKOPT::SRTO_MAXBW, &bytes)
+    }
+
+    /// Make the reads of the socket return an error of kind `WouldBlock`
+    /// instead of waiting when there is nothing to read, for the sockets
+    /// watched by an `Epoll`.
+    pub fn set_nonblocking(&self) -> Result<(), Error> {
+        set_sock_opt(self.fd, SRT_SOCKOPT::SRTO_RCVSYN, &0_i32)
+    }
+
     /// Reports the current statistics
     ///
     /// Arguments:
@@ -5,25 +5,29 @@ use std::{
         mpsc::{channel, Receiver, Sender},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
 use bytes::{Bytes, BytesMut};
+use service::{description::StreamDescription, package};
 use utils::atomic::{AtomicOption, EasyAtomic};
 
+use crate::stats::{StatsCollector, StreamStats, TransportMode};
+
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BufferFlag {
-    KeyFrame = 1,
-    Config = 2,
-    EndOfStream = 4,
-    Partial = 8,
+    KeyFrame = package::KEY_FRAME as i32,
+    Config = package::CONFIG as i32,
+    EndOfStream = package::END_OF_STREAM as i32,
+    Partial = package::PARTIAL as i32,
 }
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StreamKind {
-    Video = 0,
-    Audio = 1,
+    Video = package::VIDEO,
+    Audio = package::AUDIO,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -42,13 +46,20 @@ impl TryFrom<u8> for StreamKind {
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         Ok(match value {
-            0 => Self::Video,
-            1 => Self::Audio,
+            package::VIDEO => Self::Video,
+            package::AUDIO => Self::Audio,
             _ => return Err(StreamKindTryFromError),
         })
     }
 }
 
+/// The minimum interval between two key frame requests. Requests within this
+/// interval are discarded, so that packet loss on many receivers at the same
+/// time does not cause the encoder to produce a flood of key frames.
+pub const KEY_FRAME_REQUEST_INTERVAL: Duration = Duration::from_millis(1000);
+
+type SwitchHandler = Box<dyn Fn(TransportMode) + Send + Sync>;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StreamBufferInfo {
     Video(i32, u64),
@@ -63,10 +74,16 @@ pub enum StreamBufferInfo {
 #[derive(Default)]
 pub struct StreamSenderAdapter {
     multicast: AtomicBool,
+    auto_switch: AtomicBool,
+    switch_handler: Mutex<Option<SwitchHandler>>,
+    description: Mutex<Option<StreamDescription>>,
+    key_frame: AtomicBool,
+    key_frame_requested: Mutex<Option<Instant>>,
     audio_interval: AtomicU8,
     video_config: AtomicOption<BytesMut>,
     audio_config: AtomicOption<BytesMut>,
     channel: Channel<(BytesMut, StreamKind, i32, u64)>,
+    pub(crate) stats: StatsCollector,
 }
 
 impl StreamSenderAdapter {
@@ -87,6 +104,84 @@ impl StreamSenderAdapter {
         self.multicast.get()
     }
 
+    /// Toggle whether the sender switches between multicast and srt on its
+    /// own, based on the loss reported by the receivers and the srt
+    /// statistics.
+    ///
+    /// In auto mode the sender may override the transport chosen by
+    /// [`StreamSenderAdapter::set_multicast`] at any time.
+    pub fn set_auto_switch(&self, enable: bool) {
+        self.auto_switch.update(enable);
+    }
+
+    /// Get whether the sender switches the transport on its own.
+    pub fn get_auto_switch(&self) -> bool {
+        self.auto_switch.get()
+    }
+
+    /// Set the handler called when the sender switches the transport in auto
+    /// mode, it is called on the sending thread, so it should not block.
+    pub fn set_switch_handler<F>(&self, handler: F)
+    where
+        F: Fn(TransportMode) + Send + Sync + 'static,
+    {
+        self.switch_handler
+            .lock()
+            .unwrap()
+            .replace(Box::new(handler));
+    }
+
+    /// Set the description of the stream, it is sent to the receivers with
+    /// the signal of the channel, it must be set before the sender is
+    /// created.
+    pub fn set_description(&self, description: StreamDescription) {
+        self.description.lock().unwrap().replace(description);
+    }
+
+    /// Get the description of the stream.
+    pub fn get_description(&self) -> Option<StreamDescription> {
+        self.description.lock().unwrap().clone()
+    }
+
+    pub(crate) fn switch(&self, mode: TransportMode) {
+        self.multicast.update(mode == TransportMode::Multicast);
+
+        if let Some(handler) = self.switch_handler.lock().unwrap().as_ref() {
+            handler(mode);
+        }
+    }
+
+    /// Get a snapshot of the network statistics of the stream.
+    pub fn get_stats(&self) -> StreamStats {
+        self.stats.snapshot()
+    }
+
+    /// Request the encoder to produce a key frame as soon as possible, this is
+    /// usually triggered by a receiver that has lost packets.
+    ///
+    /// Requests are rate limited, if the previous request was accepted less
+    /// than [`KEY_FRAME_REQUEST_INTERVAL`] ago, the request is ignored and
+    /// false is returned.
+    pub fn request_key_frame(&self) -> bool {
+        let mut requested = self.key_frame_requested.lock().unwrap();
+        if let Some(time) = requested.as_ref() {
+            if time.elapsed() < KEY_FRAME_REQUEST_INTERVAL {
+                return false;
+            }
+        }
+
+        requested.replace(Instant::now());
+        self.key_frame.update(true);
+        true
+    }
+
+    /// Takes the pending key frame request, the encoder should call this
+    /// before encoding each video frame and force a key frame if it returns
+    /// true.
+    pub fn take_key_frame_request(&self) -> bool {
+        self.key_frame.update(false)
+    }
+
     pub fn close(&self) {
         self.channel.send(None);
     }
@@ -161,7 +256,11 @@ impl StreamSenderAdapter {
 pub trait StreamReceiverAdapterExt: Sync + Send {
     fn close(&self);
     fn loss_pkt(&self);
+    /// The stream continues with another video configuration, such as another
+    /// simulcast layer, the video is paused until the configuration arrives.
+    fn reconfigure(&self);
     fn send(&self, buf: Bytes, kind: StreamKind, flags: i32, timestamp: u64) -> bool;
+    fn stats(&self) -> &StatsCollector;
 }
 
 /// Video Audio Streaming Receiver Processing
@@ -174,6 +273,7 @@ pub struct StreamReceiverAdapter {
     channel: Channel<(Bytes, StreamKind, i32, u64)>,
     video_filter: PacketFilter,
     audio_filter: PacketFilter,
+    stats: StatsCollector,
 }
 
 impl StreamReceiverAdapter {
@@ -184,6 +284,11 @@ impl StreamReceiverAdapter {
     pub fn next(&self) -> Option<(Bytes, StreamKind, i32, u64)> {
         self.channel.recv()
     }
+
+    /// Get a snapshot of the network statistics of the stream.
+    pub fn get_stats(&self) -> StreamStats {
+        self.stats.snapshot()
+    }
 }
 
 impl StreamReceiverAdapterExt for StreamReceiverAdapter {
@@ -200,6 +305,10 @@ impl StreamReceiverAdapterExt for StreamReceiverAdapter {
         );
     }
 
+    fn reconfigure(&self) {
+        self.video_filter.reset();
+    }
+
     /// As soon as a keyframe is received, the keyframe is cached, and when a
     /// packet loss occurs, the previous keyframe is retransmitted directly into
     /// the decoder.
@@ -215,8 +324,13 @@ impl StreamReceiverAdapterExt for StreamReceiverAdapter {
             return self.channel.send(Some((buf, kind, flags, timestamp)));
         }
 
+        self.stats.filtered();
         true
     }
+
+    fn stats(&self) -> &StatsCollector {
+        &self.stats
+    }
 }
 
 /// Video Audio Streaming Receiver Processing
@@ -230,6 +344,7 @@ pub struct StreamMultiReceiverAdapter {
     audio_channel: Channel<(Bytes, i32, u64)>,
     video_filter: PacketFilter,
     audio_filter: PacketFilter,
+    stats: StatsCollector,
 }
 
 impl StreamMultiReceiverAdapter {
@@ -243,6 +358,11 @@ impl StreamMultiReceiverAdapter {
             StreamKind::Audio => self.audio_channel.recv(),
         }
     }
+
+    /// Get a snapshot of the network statistics of the stream.
+    pub fn get_stats(&self) -> StreamStats {
+        self.stats.snapshot()
+    }
 }
 
 impl StreamReceiverAdapterExt for StreamMultiReceiverAdapter {
@@ -260,6 +380,10 @@ impl StreamReceiverAdapterExt for StreamMultiReceiverAdapter {
         );
     }
 
+    fn reconfigure(&self) {
+        self.video_filter.reset();
+    }
+
     /// As soon as a keyframe is received, the keyframe is cached, and when a
     /// packet loss occurs, the previous keyframe is retransmitted directly into
     /// the decoder.
@@ -281,8 +405,13 @@ impl StreamReceiverAdapterExt for StreamMultiReceiverAdapter {
             }
         }
 
+        self.stats.filtered();
         true
     }
+
+    fn stats(&self) -> &StatsCollector {
+        &self.stats
+    }
 }
 
 struct Channel<T>(Sender<Option<T>>, Mutex<Receiver<Option<T>>>);
@@ -352,4 +481,8 @@ impl PacketFilter {
     fn loss(&self) {
         self.readable.update(false);
     }
+
+    fn reset(&self) {
+        self.initialized.update(false);
+    }
 }
@@ -0,0 +1,472 @@
+use std::{
+    io::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use hmac::{Hmac, Mac};
+use service::{description::StreamDescription, is_supported, PROTOCOL_VERSION};
+use sha2::Sha256;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::reconnect::ServiceLink;
+
+/// The udp port on which the senders broadcast the beacons of their streams
+/// in direct mode.
+pub const BEACON_PORT: u16 = 43166;
+
+/// The sender broadcasts the beacon of its stream at this interval.
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A stream is considered stopped when its beacon has not been received for
+/// this long, the sender may have exited without sending the stop beacon.
+pub const BEACON_TIMEOUT: Duration = Duration::from_secs(3);
+
+// ```text
+//  0                   1                   2                   3
+// 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                        Check Digit                            |
+// |                                                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                           Magic                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |    version    |     kind      |              id...
+//              ...id              |           srt port            |
+// |             port              |    family     | multicast...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                        description...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                        signature...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// ```
+//
+// The version is the protocol version of the sender, the rest of the beacon
+// depends on it. The stop beacon only carries the id, the family is 4 or 6
+// and the multicast group is 4 or 16 bytes, the description is optional and
+// takes the rest of the beacon up to the signature.
+//
+// The signature is only present when the peers share a secret, it is the
+// HMAC-SHA256 of the beacon from the magic to the signature with the secret,
+// the check digit covers it as well.
+
+const MAGIC: u32 = 0x4d424541;
+const SIGNATURE_SIZE: usize = 32;
+const START_KIND: u8 = 0;
+const STOP_KIND: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeaconError {
+    /// Not a beacon, or a damaged one.
+    Invalid,
+    /// The beacon of a sender speaking a protocol version that is not
+    /// supported.
+    UnsupportedVersion(u8),
+    /// The beacon is not signed with the shared secret.
+    Unauthenticated,
+}
+
+fn mac(secret: &str) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac can take a key of any size")
+}
+
+/// Broadcast by the senders in direct mode, in place of the signals of the
+/// service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Beacon {
+    /// The stream is published, the receivers connect to the srt port of the
+    /// sender, the address is the source address of the beacon.
+    Start {
+        id: u32,
+        srt_port: u16,
+        port: u16,
+        multicast: IpAddr,
+        description: Option<StreamDescription>,
+    },
+    /// The stream is stopped.
+    Stop { id: u32 },
+}
+
+impl Beacon {
+    /// Encode the beacon, it is signed when a secret is given.
+    pub fn encode(&self, secret: Option<&str>) -> Bytes {
+        let mut buf = BytesMut::with_capacity(64);
+        buf.put_u64(0);
+        buf.put_u32(MAGIC);
+        buf.put_u8(PROTOCOL_VERSION);
+
+        match self {
+            Self::Start {
+                id,
+                srt_port,
+                port,
+                multicast,
+                description,
+            } => {
+                buf.put_u8(START_KIND);
+                buf.put_u32(*id);
+                buf.put_u16(*srt_port);
+                buf.put_u16(*port);
+
+                match multicast {
+                    IpAddr::V4(ip) => {
+                        buf.put_u8(4);
+                        buf.put(&ip.octets()[..]);
+                    }
+                    IpAddr::V6(ip) => {
+                        buf.put_u8(6);
+                        buf.put(&ip.octets()[..]);
+                    }
+                }
+
+                if let Some(description) = description {
+                    buf.put(description.encode());
+                }
+            }
+            Self::Stop { id } => {
+                buf.put_u8(STOP_KIND);
+                buf.put_u32(*id);
+            }
+        }
+
+        if let Some(secret) = secret {
+            let signature = mac(secret).chain_update(&buf[8..]).finalize().into_bytes();
+            buf.put(&signature[..]);
+        }
+
+        let hash = xxh3_64(&buf[8..]);
+        buf[0..8].copy_from_slice(&hash.to_be_bytes());
+        buf.freeze()
+    }
+
+    /// Decode the beacon, when a secret is given only the beacons signed with
+    /// it are accepted.
+    pub fn decode(mut bytes: &[u8], secret: Option<&str>) -> Result<Self, BeaconError> {
+        let buf = bytes;
+        if bytes.len() < 18 || bytes.get_u64() != xxh3_64(bytes) || bytes.get_u32() != MAGIC {
+            return Err(BeaconError::Invalid);
+        }
+
+        let version = bytes.get_u8();
+        if !is_supported(version) {
+            return Err(BeaconError::UnsupportedVersion(version));
+        }
+
+        // The comparison of the signature takes constant time.
+        if let Some(secret) = secret {
+            if bytes.len() < SIGNATURE_SIZE + 5 {
+                return Err(BeaconError::Unauthenticated);
+            }
+
+            let (signed, signature) = buf.split_at(buf.len() - SIGNATURE_SIZE);
+            mac(secret)
+                .chain_update(&signed[8..])
+                .verify_slice(signature)
+                .map_err(|_| BeaconError::Unauthenticated)?;
+
+            bytes = &bytes[..bytes.len() - SIGNATURE_SIZE];
+        }
+
+        let kind = bytes.get_u8();
+        let id = bytes.get_u32();
+        if kind == STOP_KIND {
+            return Ok(Self::Stop { id });
+        }
+
+        if kind != START_KIND || bytes.len() < 5 {
+            return Err(BeaconError::Invalid);
+        }
+
+        let srt_port = bytes.get_u16();
+        let port = bytes.get_u16();
+        let multicast = match bytes.get_u8() {
+            4 if bytes.len() >= 4 => IpAddr::V4(Ipv4Addr::from(bytes.get_u32())),
+            6 if bytes.len() >= 16 => IpAddr::V6(Ipv6Addr::from(bytes.get_u128())),
+            _ => return Err(BeaconError::Invalid),
+        };
+
+        let description = if bytes.is_empty() {
+            None
+        } else {
+            Some(StreamDescription::decode(bytes).ok_or(BeaconError::Invalid)?)
+        };
+
+        Ok(Self::Start {
+            id,
+            srt_port,
+            port,
+            multicast,
+            description,
+        })
+    }
+}
+
+/// Create the socket on which the beacons are received, the port is shared
+/// with the other transports on the same host.
+pub fn beacon_receiver() -> Result<UdpSocket, Error> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+
+    socket.bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), BEACON_PORT).into())?;
+    socket.set_read_timeout(Some(BEACON_INTERVAL))?;
+    Ok(socket.into())
+}
+
+/// Broadcasts the beacons of a stream, signed with the shared secret if any.
+pub struct BeaconSender {
+    socket: UdpSocket,
+    secret: Option<String>,
+}
+
+impl BeaconSender {
+    pub fn new(secret: Option<String>) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))?;
+        socket.set_broadcast(true)?;
+        Ok(Self { socket, secret })
+    }
+
+    pub fn send(&self, beacon: &Beacon) -> Result<(), Error> {
+        let bytes = beacon.encode(self.secret.as_deref());
+
+        // The broadcast also reaches the receivers on the same host, it fails when
+        // there is no network, only the receivers on the same host are reached then.
+        if self
+            .socket
+            .send_to(
+                &bytes,
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), BEACON_PORT),
+            )
+            .is_err()
+        {
+            self.socket.send_to(
+                &bytes,
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), BEACON_PORT),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The receivers connected directly to the srt listener of a sender.
+pub struct Peers {
+    server: srt::Server,
+    sockets: RwLock<Vec<Arc<srt::Socket>>>,
+}
+
+impl Peers {
+    pub fn bind(addr: SocketAddr, opt: srt::Options) -> Result<Self, Error> {
+        Ok(Self {
+            server: srt::Server::bind(addr, opt, 10)?,
+            sockets: RwLock::new(Vec::with_capacity(10)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.local_addr()
+    }
+
+    pub fn accept(&self) -> Result<(srt::Socket, SocketAddr), Error> {
+        self.server.accept()
+    }
+
+    pub fn add(&self, socket: Arc<srt::Socket>) {
+        self.sockets.write().unwrap().push(socket);
+    }
+
+    /// Send to all the receivers, the receivers that cannot be sent to are
+    /// closed and removed, the sender keeps running without them.
+    pub fn send(&self, buf: &[u8]) {
+        let closed = self
+            .sockets
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|socket| socket.send(buf).is_err())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !closed.is_empty() {
+            self.sockets
+                .write()
+                .unwrap()
+                .retain(|socket| !closed.iter().any(|it| Arc::ptr_eq(it, socket)));
+
+            for socket in closed {
+                log::warn!("direct receiver is closed");
+
+                socket.close();
+            }
+        }
+    }
+
+    /// The totals of the packets sent and lost of all the receivers.
+    pub fn counters(&self) -> (u64, u64) {
+        self.sockets
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|socket| socket.get_stats().ok())
+            .fold((0, 0), |(sent, lost), trace| {
+                (
+                    sent + trace.pkt_sent_total.max(0) as u64,
+                    lost + trace.pkt_snd_loss_total.max(0) as u64,
+                )
+            })
+    }
+
+    pub fn close(&self) {
+        self.server.close();
+        for socket in self.sockets.write().unwrap().drain(..) {
+            socket.close();
+        }
+    }
+}
+
+/// Where the sender sends the srt data, the service relays it to the
+/// receivers, or the receivers are connected to the sender directly.
+pub enum Uplink {
+    Relay(Arc<ServiceLink>),
+    Direct(Arc<Peers>),
+}
+
+impl Uplink {
+    pub fn send(&self, buf: &[u8]) -> Result<(), Error> {
+        match self {
+            Self::Relay(link) => link.send(buf),
+            Self::Direct(peers) => {
+                peers.send(buf);
+                Ok(())
+            }
+        }
+    }
+
+    /// The totals of the srt packets sent and lost.
+    pub fn counters(&self) -> (u64, u64) {
+        match self {
+            Self::Relay(link) => link.counters(),
+            Self::Direct(peers) => peers.counters(),
+        }
+    }
+
+    pub fn close(&self) {
+        match self {
+            Self::Relay(link) => link.close(),
+            Self::Direct(peers) => peers.close(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(multicast: IpAddr, description: Option<StreamDescription>) -> Beacon {
+        Beacon::Start {
+            id: 1,
+            srt_port: 8080,
+            port: 8081,
+            multicast,
+            description,
+        }
+    }
+
+    #[test]
+    fn decode_an_encoded_beacon() {
+        let description = StreamDescription {
+            title: "title".to_string(),
+            ..Default::default()
+        };
+
+        for beacon in [
+            start(IpAddr::V4(Ipv4Addr::new(239, 0, 0, 1)), None),
+            start(
+                IpAddr::V6(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1)),
+                Some(description),
+            ),
+            Beacon::Stop { id: 1 },
+        ] {
+            assert_eq!(Beacon::decode(&beacon.encode(None), None), Ok(beacon));
+        }
+    }
+
+    #[test]
+    fn reject_a_damaged_beacon() {
+        let bytes = start(IpAddr::V4(Ipv4Addr::new(239, 0, 0, 1)), None).encode(None);
+        for index in [0, 8, bytes.len() - 1] {
+            let mut damaged = bytes.to_vec();
+            damaged[index] ^= 1;
+            assert_eq!(Beacon::decode(&damaged, None), Err(BeaconError::Invalid));
+        }
+
+        assert_eq!(
+            Beacon::decode(&bytes[..17], None),
+            Err(BeaconError::Invalid)
+        );
+        assert_eq!(Beacon::decode(&[], None), Err(BeaconError::Invalid));
+    }
+
+    #[test]
+    fn reject_a_truncated_beacon() {
+        // The check digit is valid, but the multicast group is cut.
+        let bytes = start(IpAddr::V4(Ipv4Addr::new(239, 0, 0, 1)), None).encode(None);
+        let mut truncated = bytes[..bytes.len() - 2].to_vec();
+        let hash = xxh3_64(&truncated[8..]);
+        truncated[0..8].copy_from_slice(&hash.to_be_bytes());
+        assert_eq!(Beacon::decode(&truncated, None), Err(BeaconError::Invalid));
+    }
+
+    #[test]
+    fn decode_a_signed_beacon() {
+        let description = StreamDescription {
+            title: "title".to_string(),
+            ..Default::default()
+        };
+
+        for beacon in [
+            start(IpAddr::V4(Ipv4Addr::new(239, 0, 0, 1)), Some(description)),
+            Beacon::Stop { id: 1 },
+        ] {
+            assert_eq!(
+                Beacon::decode(&beacon.encode(Some("secret")), Some("secret")),
+                Ok(beacon)
+            );
+        }
+    }
+
+    #[test]
+    fn reject_a_beacon_not_signed_with_the_secret() {
+        for beacon in [
+            start(IpAddr::V4(Ipv4Addr::new(239, 0, 0, 1)), None),
+            Beacon::Stop { id: 1 },
+        ] {
+            for bytes in [beacon.encode(None), beacon.encode(Some("other"))] {
+                assert_eq!(
+                    Beacon::decode(&bytes, Some("secret")),
+                    Err(BeaconError::Unauthenticated)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reject_an_unsupported_version() {
+        let mut bytes = Beacon::Stop { id: 1 }.encode(None).to_vec();
+        bytes[12] = PROTOCOL_VERSION + 1;
+        let hash = xxh3_64(&bytes[8..]);
+        bytes[0..8].copy_from_slice(&hash.to_be_bytes());
+        assert_eq!(
+            Beacon::decode(&bytes, None),
+            Err(BeaconError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+        );
+    }
+}
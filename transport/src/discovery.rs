@@ -0,0 +1,109 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use service::discovery::{Discovery, DISCOVERY_PORT};
+
+/// The query is sent again at this interval until the timeout, in case it or
+/// the answers are lost.
+const QUERY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A service found on the LAN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceInfo {
+    /// The address of the signaling of the service, it can be used as
+    /// [`TransportOptions::server`](crate::TransportOptions::server).
+    pub server: SocketAddr,
+    /// The port of the srt server, on the host of the signaling, the clients
+    /// are also told it when connecting to the service.
+    pub srt_port: u16,
+    /// The multicast group suggested by the service.
+    pub multicast: IpAddr,
+    pub mtu: usize,
+}
+
+/// Find the services on the LAN.
+///
+/// The query is broadcast to the discovery port, or sent to the loopback
+/// address when the broadcast is not possible, the services that answer
+/// within the timeout are returned.
+pub fn discover(timeout: Duration) -> Result<Vec<ServiceInfo>, Error> {
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))?;
+    socket.set_broadcast(true)?;
+
+    let broadcast = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), DISCOVERY_PORT);
+    let loopback = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DISCOVERY_PORT);
+
+    let query = Discovery::Query.encode();
+    let deadline = Instant::now() + timeout;
+    let mut services: Vec<ServiceInfo> = Vec::with_capacity(10);
+    let mut queried: Option<Instant> = None;
+    let mut buf = [0u8; 1024];
+
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+
+        if queried
+            .map(|it| it.elapsed() >= QUERY_INTERVAL)
+            .unwrap_or(true)
+        {
+            // The broadcast also reaches the services on the same host, it fails when
+            // there is no network, only the services on the same host are found then.
+            if let Err(e) = socket.send_to(&query, broadcast) {
+                log::warn!("not broadcast a discovery query, err={:?}", e);
+
+                socket.send_to(&query, loopback)?;
+            }
+
+            queried = Some(now);
+        }
+
+        let wait = (deadline - now).min(QUERY_INTERVAL);
+        socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+
+        let (size, addr) = match socket.recv_from(&mut buf) {
+            Ok(ret) => ret,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        if let Some(Discovery::Announcement {
+            mut server,
+            multicast,
+            mtu,
+            srt_port,
+        }) = Discovery::decode(&buf[..size])
+        {
+            // The service listens on all interfaces, it is reachable at the address the
+            // answer came from.
+            if server.ip().is_unspecified() {
+                server.set_ip(addr.ip());
+            }
+
+            if !services.iter().any(|it| it.server == server) {
+                log::info!(
+                    "discover a service, server={}, srt_port={:?}, multicast={}",
+                    server,
+                    srt_port,
+                    multicast
+                );
+
+                services.push(ServiceInfo {
+                    srt_port: srt_port.unwrap_or(server.port()),
+                    mtu: mtu as usize,
+                    multicast,
+                    server,
+                });
+            }
+        }
+    }
+
+    Ok(services)
+}
@@ -0,0 +1,47 @@
+use std::{fmt, net::SocketAddr, sync::RwLock};
+
+use crate::stats::TransportMode;
+
+/// What happens to the channels and the streams of the transport, see
+/// [`crate::Transport::set_event_handler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The channel is published, it is reported again when the publisher
+    /// describes the channel, or when the service sends the channels again
+    /// after the signal connection is reestablished.
+    ChannelPublished { id: u32 },
+    /// The channel is stopped by its publisher.
+    ChannelStopped { id: u32 },
+    /// The receiver of the channel joined the multicast of the publisher.
+    MulticastStarted { id: u32, endpoint: SocketAddr },
+    /// The receiver of the channel lost packets, the stream is paused until
+    /// the next key frame, which is requested from the publisher. It is
+    /// reported at most once per key frame request.
+    PacketLoss { id: u32 },
+    /// The sender of the channel switched the transport in auto mode.
+    ModeSwitched { id: u32, mode: TransportMode },
+}
+
+type EventHandler = Box<dyn Fn(Event) + Send + Sync>;
+
+/// Passes the events to the handler set by the application.
+#[derive(Default)]
+pub struct Events(RwLock<Option<EventHandler>>);
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Events")
+    }
+}
+
+impl Events {
+    pub fn set_handler(&self, handler: EventHandler) {
+        self.0.write().unwrap().replace(handler);
+    }
+
+    pub fn emit(&self, event: Event) {
+        if let Some(handler) = self.0.read().unwrap().as_ref() {
+            handler(event);
+        }
+    }
+}
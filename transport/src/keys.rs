@@ -0,0 +1,160 @@
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use multicast::{Key, Keys};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// How often the publisher rotates the multicast key.
+const ROTATION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// A new key is announced this long before it is used, so that the
+/// subscribers have received it when the first fragments encrypted with it
+/// arrive.
+const ACTIVATION_DELAY: Duration = Duration::from_secs(2);
+
+// ```text
+//  0                   1                   2                   3
+// 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                        Check Digit                            |
+// |                                                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                           Magic                               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |      id       |                    key...
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// ```
+//
+// The magic is where the srt fragments carry the high bits of their sequence,
+// which never reach it, so the key messages cannot be mistaken for fragments.
+
+const MAGIC: u32 = 0x4d4b4559;
+const SIZE: usize = 8 + 4 + 1 + 32;
+
+/// A multicast key sent by the publisher to the subscribers over srt, the
+/// service forwards it like the audio and video data.
+///
+/// The key is only as confidential as the srt connection, the srt encryption
+/// should be enabled together with the multicast encryption.
+pub struct StreamKey {
+    pub id: u8,
+    pub key: Key,
+}
+
+impl StreamKey {
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(SIZE);
+        buf.put_u64(0);
+        buf.put_u32(MAGIC);
+        buf.put_u8(self.id);
+        buf.put(&self.key[..]);
+
+        let hash = xxh3_64(&buf[8..]);
+        buf[0..8].copy_from_slice(&hash.to_be_bytes());
+        buf.freeze()
+    }
+
+    pub fn decode(mut bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != SIZE || bytes.get_u64() != xxh3_64(bytes) || bytes.get_u32() != MAGIC {
+            return None;
+        }
+
+        let id = bytes.get_u8();
+        let mut key = Key::default();
+        bytes.copy_to_slice(&mut key);
+        Some(Self { id, key })
+    }
+}
+
+/// Rotates the multicast key of the publisher.
+///
+/// The new key is generated and announced first, and used for encryption
+/// after the activation delay, the subscribers keep the previous keys for the
+/// fragments still in flight.
+pub struct KeyRotation {
+    keys: Keys,
+    rotated: Instant,
+    pending: Option<(u8, Instant)>,
+}
+
+impl KeyRotation {
+    /// Generates and activates the first key.
+    pub fn new(keys: Keys) -> Self {
+        let (id, _) = keys.generate();
+        keys.activate(id);
+
+        Self {
+            rotated: Instant::now(),
+            pending: None,
+            keys,
+        }
+    }
+
+    /// Returns the new key when it is time to rotate, the key has to be
+    /// announced to the subscribers.
+    pub fn poll(&mut self) -> Option<StreamKey> {
+        if let Some((id, time)) = self.pending {
+            if time.elapsed() >= ACTIVATION_DELAY {
+                self.keys.activate(id);
+                self.pending = None;
+            }
+
+            return None;
+        }
+
+        if self.rotated.elapsed() < ROTATION_INTERVAL {
+            return None;
+        }
+
+        let (id, key) = self.keys.generate();
+        self.rotated = Instant::now();
+        self.pending = Some((id, self.rotated));
+        Some(StreamKey { id, key })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_an_encoded_key() {
+        let key = StreamKey {
+            id: 3,
+            key: [9; 32],
+        };
+
+        let decoded = StreamKey::decode(&key.encode()).unwrap();
+        assert_eq!(decoded.id, 3);
+        assert_eq!(decoded.key, [9; 32]);
+    }
+
+    #[test]
+    fn reject_a_damaged_key() {
+        let bytes = StreamKey {
+            id: 3,
+            key: [9; 32],
+        }
+        .encode();
+
+        for index in [0, 8, 12, SIZE - 1] {
+            let mut damaged = bytes.to_vec();
+            damaged[index] ^= 1;
+            assert!(StreamKey::decode(&damaged).is_none());
+        }
+
+        assert!(StreamKey::decode(&bytes[..SIZE - 1]).is_none());
+        assert!(StreamKey::decode(&[&bytes[..], &[0]].concat()).is_none());
+    }
+
+    #[test]
+    fn not_mistake_a_fragment_for_a_key() {
+        // The fragments are as long as the key messages, and their check digits are
+        // valid.
+        let mut encoder = srt::FragmentEncoder::new(SIZE);
+        for fragment in encoder.encode(&[9; 100]) {
+            assert_eq!(fragment.len(), SIZE);
+            assert!(StreamKey::decode(fragment).is_none());
+        }
+    }
+}
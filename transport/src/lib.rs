@@ -1,26 +1,60 @@
 pub mod adapter;
 pub mod package;
+pub mod stats;
+
+mod direct;
+mod discovery;
+mod event;
+mod keys;
+mod reconnect;
+mod switch;
+
+pub use self::{
+    discovery::{discover, ServiceInfo},
+    event::Event,
+    reconnect::{ConnectionState, Link, RESUME_TIMEOUT},
+};
+pub use service::{
+    description::{AudioDescription, StreamDescription, VideoDescription, SIMULCAST_TAG},
+    SocketKind,
+};
 
 use std::{
-    collections::HashMap,
-    io::{Error, Read},
-    net::{Ipv4Addr, SocketAddr, TcpStream},
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{Error, ErrorKind, Read, Write},
+    mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, TcpStream},
     sync::{
         atomic::{AtomicU32, AtomicU64},
         mpsc::{channel, Sender},
         Arc, Mutex, RwLock, Weak,
     },
     thread,
+    time::{Duration, Instant},
 };
 
 use bytes::BytesMut;
-use service::{signal::Signal, SocketKind, StreamInfo};
+use multicast::Keys;
+use service::{
+    auth,
+    feedback::Feedback,
+    is_supported,
+    signal::{Credential, Handshake, Signal, HANDSHAKE_TIMEOUT},
+    StreamInfo, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
 use smallvec::SmallVec;
 use utils::atomic::EasyAtomic;
 
 use crate::{
-    adapter::{StreamReceiverAdapterExt, StreamSenderAdapter},
-    package::{Package, PacketInfo, UnPackage},
+    adapter::{StreamReceiverAdapterExt, StreamSenderAdapter, KEY_FRAME_REQUEST_INTERVAL},
+    direct::{Beacon, BeaconError, BeaconSender, Peers, Uplink, BEACON_INTERVAL, BEACON_TIMEOUT},
+    event::Events,
+    keys::{KeyRotation, StreamKey},
+    package::{Package, PacketInfo, UnPackage, UnPackageError},
+    reconnect::{Backoff, ServiceLink, States},
+    stats::TransportMode,
+    switch::{AutoSwitch, Counters},
 };
 
 pub fn startup() -> bool {
@@ -31,38 +65,264 @@ pub fn shutdown() {
     srt::cleanup()
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct TransportOptions {
     pub server: SocketAddr,
-    pub multicast: Ipv4Addr,
+    /// The multicast group, which can be either an IPV4 or an IPV6 address.
+    pub multicast: IpAddr,
+    /// The index of the network interface used by the IPV6 multicast, 0 lets
+    /// the system choose the interface, it is ignored by IPV4.
+    pub interface: u32,
+    /// The bounds of the multicast receiver's jitter buffer in milliseconds,
+    /// the hold time of the reordering queue adapts to the network between
+    /// them.
+    pub min_jitter_delay: u32,
+    pub max_jitter_delay: u32,
     pub mtu: usize,
+    /// The number of multicast fragments protected by one parity fragment,
+    /// for example 10 means 10% redundancy, 0 disables forward error
+    /// correction.
+    pub fec: u8,
+    /// Provides the tokens of the streams and the signal connection, it is
+    /// only needed when the service requires authentication.
+    pub tokens: Option<TokenProvider>,
+    /// The shared secret of the senders and receivers in direct mode, the
+    /// senders only accept the receivers with a token signed with it, and the
+    /// receivers only accept the beacons signed with it. It is
+    /// not used with the service, the service keeps its secret to itself and
+    /// the tokens are issued by the application, see `tokens`.
+    pub secret: Option<String>,
+    /// The passphrase of the srt encryption, it must match the passphrase of
+    /// the service, `None` disables encryption.
+    pub passphrase: Option<String>,
+    /// The length of the srt encryption key in bytes, 16, 24 or 32, 0 uses
+    /// the key length of the service.
+    pub key_length: u32,
+    /// Encrypt and authenticate the multicast of the senders, the receivers
+    /// only accept encrypted multicast. The keys are distributed over srt, so
+    /// the srt encryption should be enabled as well.
+    pub encrypt_multicast: bool,
+    /// Work without the service, the senders listen for the receivers on srt
+    /// and announce their streams with a beacon broadcast on the LAN, and the
+    /// receivers connect to the senders directly. `server` is ignored.
+    pub direct: bool,
 }
 
-#[derive(Debug)]
+impl TransportOptions {
+    // The token of the stream, from the application, or in direct mode signed with
+    // the shared secret of the peers.
+    fn token(&self, id: u32, kind: SocketKind) -> Option<String> {
+        if let Some(tokens) = self.tokens.as_ref() {
+            return tokens.get(id, kind);
+        }
+
+        if self.direct {
+            self.secret
+                .as_ref()
+                .map(|secret| auth::sign_with_ttl(secret, id, kind))
+        } else {
+            None
+        }
+    }
+}
+
+/// Provides the token of a channel and role, issued by the application with
+/// the secret of the service, see [`service::auth::sign`].
+///
+/// It is called every time a stream connects to the service, so a token
+/// with a short validity can be issued each time, and with the channel 0 and
+/// the subscriber role for the signal connection, which accepts the token
+/// of any channel. Returning `None` connects without a token.
+#[derive(Clone)]
+pub struct TokenProvider(Arc<dyn Fn(u32, SocketKind) -> Option<String> + Send + Sync>);
+
+impl TokenProvider {
+    pub fn new<F>(provider: F) -> Self
+    where
+        F: Fn(u32, SocketKind) -> Option<String> + Send + Sync + 'static,
+    {
+        Self(Arc::new(provider))
+    }
+
+    pub fn get(&self, id: u32, kind: SocketKind) -> Option<String> {
+        (self.0)(id, kind)
+    }
+}
+
+impl fmt::Debug for TokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TokenProvider")
+    }
+}
+
+/// A published channel, see [`Transport::channels`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelInfo {
+    pub id: u32,
+    /// The multicast group and port of the channel.
+    pub endpoint: SocketAddr,
+    /// The description supplied by the publisher, see
+    /// [`StreamSenderAdapter::set_description`].
+    pub description: Option<StreamDescription>,
+}
+
+// A channel published on the service, or announced by a beacon in direct mode.
+#[derive(Debug, Clone)]
+struct Published {
+    endpoint: SocketAddr,
+    description: Option<StreamDescription>,
+}
+
+// The srt connection of a receiver and the channel it receives.
+#[derive(Debug, Clone)]
+struct ReceiverSocket {
+    stream_id: u32,
+    socket: Weak<srt::Socket>,
+}
+
+/// The connection to the service and the streams of the senders and receivers
+/// are reestablished when they are lost, for example when the service
+/// restarts, see [`Transport::set_state_handler`].
+#[derive(Debug, Clone)]
 pub struct Transport {
-    index: AtomicU32,
+    index: Arc<AtomicU32>,
     options: TransportOptions,
-    publishs: Arc<RwLock<HashMap<u32, u16>>>,
+    publishs: Arc<RwLock<HashMap<u32, Published>>>,
     channels: Arc<RwLock<HashMap<u32, Sender<Signal>>>>,
+    // The srt listeners of the senders announced by the beacons in direct mode.
+    listeners: Arc<RwLock<HashMap<u32, SocketAddr>>>,
+    // The channels stopped by their publishers, their receivers are not resumed.
+    stopped: Arc<RwLock<HashSet<u32>>>,
+    // The simulcast layers selected for the receivers of the channels, and the srt
+    // connections of the receivers by their index.
+    layers: Arc<RwLock<HashMap<u32, u32>>>,
+    receivers: Arc<RwLock<HashMap<u32, ReceiverSocket>>>,
+    // The srt server of the service, told by the service on the signal connection.
+    srt_server: Arc<RwLock<SocketAddr>>,
+    states: Arc<States>,
+    events: Arc<Events>,
 }
 
 impl Transport {
     pub fn new(options: TransportOptions) -> Result<Self, Error> {
         let channels: Arc<RwLock<HashMap<u32, Sender<Signal>>>> = Default::default();
-        let publishs: Arc<RwLock<HashMap<u32, u16>>> = Default::default();
+        let publishs: Arc<RwLock<HashMap<u32, Published>>> = Default::default();
+        let listeners: Arc<RwLock<HashMap<u32, SocketAddr>>> = Default::default();
+        let stopped: Arc<RwLock<HashSet<u32>>> = Default::default();
+        let srt_server = Arc::new(RwLock::new(options.server));
+        let states: Arc<States> = Default::default();
+        let events: Arc<Events> = Default::default();
 
-        // Connecting to a mirror server
-        let mut socket = TcpStream::connect(options.server)?;
+        if options.direct {
+            Self::receive_beacons(
+                &options, &channels, &publishs, &listeners, &stopped, &events,
+            )?;
+        } else {
+            Self::receive_signals(
+                &options,
+                &channels,
+                &publishs,
+                &stopped,
+                &srt_server,
+                &states,
+                &events,
+            )?;
+        }
 
-        // The role of this thread is to forward all received signals to all subscribers
-        let channels_ = Arc::downgrade(&channels);
-        let publishs_ = Arc::downgrade(&publishs);
+        Ok(Self {
+            index: Default::default(),
+            options,
+            channels,
+            publishs,
+            listeners,
+            stopped,
+            srt_server,
+            states,
+            events,
+            layers: Default::default(),
+            receivers: Default::default(),
+        })
+    }
+
+    /// Receive a simulcast layer of the channel, the id of the channel
+    /// publishing the layer, see [`SIMULCAST_TAG`], or the id of the channel
+    /// itself to receive it again. It applies to all the receivers of the
+    /// channel, and to the receivers created afterwards.
+    ///
+    /// The service switches the receivers at the next key frame of the layer,
+    /// until then they keep receiving the current one. The layers are relayed
+    /// by the service over srt, the multicast of the channel is ignored while
+    /// the receivers get another layer.
+    pub fn select_layer(&self, stream_id: u32, layer: u32) -> Result<(), Error> {
+        log::info!("select layer, id={}, layer={}", stream_id, layer);
+
+        if layer == stream_id {
+            self.layers.write().unwrap().remove(&stream_id);
+        } else {
+            self.layers.write().unwrap().insert(stream_id, layer);
+        }
+
+        let sockets = self
+            .receivers
+            .read()
+            .unwrap()
+            .values()
+            .filter(|it| it.stream_id == stream_id)
+            .filter_map(|it| it.socket.upgrade())
+            .collect::<Vec<_>>();
+
+        for socket in sockets {
+            socket.send(&Feedback::SelectLayer { id: layer }.encode())?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the handler called when a connection is lost, reestablished, or
+    /// given up, it is called on the threads of the transport, so it should
+    /// not block.
+    pub fn set_state_handler<F>(&self, handler: F)
+    where
+        F: Fn(Link, ConnectionState) + Send + Sync + 'static,
+    {
+        self.states.set_handler(Box::new(handler));
+    }
+
+    /// Set the handler called with the events of the channels and the
+    /// streams, it is called on the threads of the transport, so it should
+    /// not block.
+    pub fn set_event_handler<F>(&self, handler: F)
+    where
+        F: Fn(Event) + Send + Sync + 'static,
+    {
+        self.events.set_handler(Box::new(handler));
+    }
+
+    fn receive_signals(
+        options: &TransportOptions,
+        channels: &Arc<RwLock<HashMap<u32, Sender<Signal>>>>,
+        publishs: &Arc<RwLock<HashMap<u32, Published>>>,
+        stopped: &Arc<RwLock<HashSet<u32>>>,
+        srt_server: &Arc<RwLock<SocketAddr>>,
+        states: &Arc<States>,
+        events: &Arc<Events>,
+    ) -> Result<(), Error> {
+        let (mut socket, mut bytes, server) = connect_signal(options)?;
+        *srt_server.write().unwrap() = server;
+        let mut buf = [0u8; 1024];
+
+        // The role of this thread is to forward all received signals to all subscribers,
+        // the connection is reestablished as long as the transport is alive.
+        let options = options.clone();
+        let states = states.clone();
+        let events = events.clone();
+        let channels_ = Arc::downgrade(channels);
+        let publishs_ = Arc::downgrade(publishs);
+        let stopped_ = Arc::downgrade(stopped);
+        let srt_server = srt_server.clone();
         thread::Builder::new()
             .name("MirrorSignalReceiverThread".to_string())
-            .spawn(move || {
-                let mut buf = [0u8; 1024];
-                let mut bytes = BytesMut::with_capacity(2000);
-
+            .spawn(move || loop {
                 while let Ok(size) = socket.read(&mut buf) {
                     log::info!("signal socket read buf, size={}", size);
 
@@ -70,63 +330,270 @@ impl Transport {
                         break;
                     }
 
-                    // Try to decode all data received
+                    // Try to decode all data received, the service sends all the published
+                    // channels at once after connecting.
                     bytes.extend_from_slice(&buf[..size]);
-                    if let Some((size, signal)) = Signal::decode(&bytes) {
+                    while let Some((size, signal)) = Signal::decode(&bytes) {
                         let _ = bytes.split_to(size);
 
                         log::info!("recv a signal={:?}", signal);
 
-                        if let Some(channels) = channels_.upgrade() {
-                            match signal {
-                                Signal::Start { id, port } => {
-                                    if let Some(publishs) = publishs_.upgrade() {
-                                        publishs.write().unwrap().insert(id, port);
-                                    }
-                                }
-                                Signal::Stop { id } => {
-                                    if let Some(publishs) = publishs_.upgrade() {
-                                        publishs.write().unwrap().remove(&id);
-                                    }
+                        if let (Some(channels), Some(publishs), Some(stopped)) =
+                            (channels_.upgrade(), publishs_.upgrade(), stopped_.upgrade())
+                        {
+                            dispatch(
+                                signal,
+                                options.multicast,
+                                &channels,
+                                &publishs,
+                                &stopped,
+                                &events,
+                            );
+                        } else {
+                            return;
+                        }
+                    }
+                }
 
-                                    if channels.write().unwrap().remove(&id).is_some() {
-                                        log::info!("channel is close, id={}", id)
-                                    }
-                                }
-                            }
+                log::warn!("signal connection is lost, server={}", options.server);
 
-                            let mut closeds: SmallVec<[u32; 10]> = SmallVec::with_capacity(10);
+                states.report(Link::Signal, ConnectionState::Reconnecting);
 
-                            // Forwards the signal to all subscribers
-                            {
-                                for (id, tx) in channels.read().unwrap().iter() {
-                                    if tx.send(signal).is_err() {
-                                        closeds.push(*id);
-                                    }
-                                }
+                let mut backoff = Backoff::default();
+                (socket, bytes) = loop {
+                    thread::sleep(backoff.next());
+
+                    if channels_.strong_count() == 0 {
+                        return;
+                    }
+
+                    match connect_signal(&options) {
+                        Ok((socket, bytes, server)) => {
+                            *srt_server.write().unwrap() = server;
+
+                            break (socket, bytes);
+                        }
+                        Err(e) => {
+                            log::warn!("failed to reconnect signal, err={:?}", e);
+                        }
+                    }
+                };
+
+                // The service sends the published channels again after the credential, the
+                // channels that were stopped meanwhile are not among them.
+                if let Some(publishs) = publishs_.upgrade() {
+                    publishs.write().unwrap().clear();
+                }
+
+                states.report(Link::Signal, ConnectionState::Connected);
+            })?;
+
+        Ok(())
+    }
+    // In direct mode the beacons of the senders take the place of the signals, the
+    // beacons are repeated, only new or changed streams are signaled, and a stream
+    // is stopped when its beacons are no longer received.
+    fn receive_beacons(
+        options: &TransportOptions,
+        channels: &Arc<RwLock<HashMap<u32, Sender<Signal>>>>,
+        publishs: &Arc<RwLock<HashMap<u32, Published>>>,
+        listeners: &Arc<RwLock<HashMap<u32, SocketAddr>>>,
+        stopped: &Arc<RwLock<HashSet<u32>>>,
+        events: &Arc<Events>,
+    ) -> Result<(), Error> {
+        let socket = direct::beacon_receiver()?;
+        let secret = options.secret.clone();
+
+        let channels_ = Arc::downgrade(channels);
+        let publishs_ = Arc::downgrade(publishs);
+        let listeners_ = Arc::downgrade(listeners);
+        let stopped_ = Arc::downgrade(stopped);
+        let events = events.clone();
+        thread::Builder::new()
+            .name("MirrorBeaconReceiverThread".to_string())
+            .spawn(move || {
+                let mut buf = [0u8; 2000];
+                let mut seen: HashMap<u32, Instant> = HashMap::with_capacity(10);
+                let mut incompatibles: HashSet<SocketAddr> = HashSet::with_capacity(10);
+                let mut unauthenticated: HashSet<SocketAddr> = HashSet::with_capacity(10);
+
+                loop {
+                    let (channels, publishs, listeners, stopped) = match (
+                        channels_.upgrade(),
+                        publishs_.upgrade(),
+                        listeners_.upgrade(),
+                        stopped_.upgrade(),
+                    ) {
+                        (Some(channels), Some(publishs), Some(listeners), Some(stopped)) => {
+                            (channels, publishs, listeners, stopped)
+                        }
+                        _ => break,
+                    };
+
+                    let (size, addr) = match socket.recv_from(&mut buf) {
+                        Ok(ret) => ret,
+                        Err(e)
+                            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                        {
+                            (0, SocketAddr::from(([0, 0, 0, 0], 0)))
+                        }
+                        Err(e) => {
+                            log::error!("beacon socket is closed, err={:?}", e);
+
+                            break;
+                        }
+                    };
+
+                    let beacon = Beacon::decode(&buf[..size], secret.as_deref());
+                    match beacon.clone() {
+                        Ok(Beacon::Start {
+                            id,
+                            srt_port,
+                            port,
+                            multicast,
+                            description,
+                        }) => {
+                            seen.insert(id, Instant::now());
+
+                            // The stream is signaled again when any of the announced state
+                            // changes, e.g. the sender restarted on another srt port.
+                            let listener = SocketAddr::new(addr.ip(), srt_port);
+                            let endpoint = SocketAddr::new(multicast, port);
+                            let changed = listeners.write().unwrap().insert(id, listener)
+                                != Some(listener)
+                                || publishs
+                                    .read()
+                                    .unwrap()
+                                    .get(&id)
+                                    .map(|it| (it.endpoint, &it.description))
+                                    != Some((endpoint, &description));
+
+                            if changed {
+                                log::info!("recv a beacon={:?}, addr={}", beacon, addr);
+
+                                let signal = Signal::Start {
+                                    multicast: Some(multicast),
+                                    description,
+                                    port,
+                                    id,
+                                };
+
+                                dispatch(
+                                    signal, multicast, &channels, &publishs, &stopped, &events,
+                                );
                             }
+                        }
+                        Ok(Beacon::Stop { id }) => {
+                            log::info!("recv a beacon={:?}, addr={}", beacon, addr);
 
-                            // Clean up closed subscribers
-                            if !closeds.is_empty() {
-                                for id in closeds {
-                                    if channels.write().unwrap().remove(&id).is_some() {
-                                        log::info!("channel is close, id={}", id)
-                                    }
-                                }
+                            seen.remove(&id);
+                            listeners.write().unwrap().remove(&id);
+                            dispatch(
+                                Signal::Stop { id },
+                                addr.ip(),
+                                &channels,
+                                &publishs,
+                                &stopped,
+                                &events,
+                            );
+                        }
+                        // The senders speaking another protocol version are ignored, the
+                        // warning is only logged once for each of them.
+                        Err(BeaconError::UnsupportedVersion(version)) => {
+                            if incompatibles.insert(addr) {
+                                log::warn!(
+                                    "ignore an incompatible sender, addr={}, version={}",
+                                    addr,
+                                    version
+                                );
                             }
-                        } else {
-                            break;
                         }
+                        Err(BeaconError::Unauthenticated) => {
+                            if unauthenticated.insert(addr) {
+                                log::warn!("ignore an unauthenticated beacon, addr={}", addr);
+                            }
+                        }
+                        Err(BeaconError::Invalid) => (),
+                    }
+
+                    let expired = seen
+                        .iter()
+                        .filter(|(_, time)| time.elapsed() >= BEACON_TIMEOUT)
+                        .map(|(id, _)| *id)
+                        .collect::<SmallVec<[u32; 10]>>();
+
+                    for id in expired {
+                        log::info!("beacon timeout, id={}", id);
+
+                        seen.remove(&id);
+                        listeners.write().unwrap().remove(&id);
+                        dispatch(
+                            Signal::Stop { id },
+                            addr.ip(),
+                            &channels,
+                            &publishs,
+                            &stopped,
+                            &events,
+                        );
                     }
                 }
             })?;
 
-        Ok(Self {
-            index: AtomicU32::new(0),
-            options,
-            channels,
-            publishs,
-        })
+        Ok(())
+    }
+
+    // The address the receiver connects to, the service, or in direct mode the srt
+    // listener of the sender, which may not have been announced yet.
+    fn server_of(&self, stream_id: u32) -> Result<SocketAddr, Error> {
+        if !self.options.direct {
+            return Ok(*self.srt_server.read().unwrap());
+        }
+
+        let deadline = Instant::now() + BEACON_TIMEOUT;
+        loop {
+            if let Some(addr) = self.listeners.read().unwrap().get(&stream_id) {
+                return Ok(*addr);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    "the stream has not been announced",
+                ));
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Get the channels currently published, sorted by id, in direct mode
+    /// these are the senders announced on the LAN.
+    pub fn channels(&self) -> Vec<ChannelInfo> {
+        let mut channels = self
+            .publishs
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, published)| ChannelInfo {
+                description: published.description.clone(),
+                endpoint: published.endpoint,
+                id: *id,
+            })
+            .collect::<Vec<_>>();
+
+        channels.sort_by_key(|it| it.id);
+        channels
+    }
+
+    /// Get the description of a published channel, it is only available when
+    /// the publisher supplied one, see
+    /// [`StreamSenderAdapter::set_description`].
+    pub fn get_description(&self, stream_id: u32) -> Option<StreamDescription> {
+        self.publishs
+            .read()
+            .unwrap()
+            .get(&stream_id)
+            .and_then(|it| it.description.clone())
     }
 
     pub fn create_sender(
@@ -138,33 +605,211 @@ impl Transport {
 
         // Create a multicast sender, the port is automatically assigned an idle port by
         // the system
+        let keys = self.options.encrypt_multicast.then(Keys::default);
+        let mut rotation = keys.clone().map(KeyRotation::new);
         let mut mcast_sender = multicast::Server::new(
             self.options.multicast,
-            format!("0.0.0.0:{}", port).parse().unwrap(),
+            unspecified(self.options.multicast, port, self.options.interface),
             self.options.mtu,
+            self.options.fec,
+            keys.clone(),
         )?;
 
+        if keys.is_some() && self.options.passphrase.is_none() {
+            log::warn!(
+                "the multicast keys are sent over srt without encryption, id={}",
+                stream_id
+            );
+        }
+
         log::info!("create multicast sender, port={}", port);
 
+        let auto_switch = Arc::new(Mutex::new(AutoSwitch::new(if adapter.get_multicast() {
+            TransportMode::Multicast
+        } else {
+            TransportMode::Srt
+        })));
+
         // Create an srt configuration and carry stream information
         let mut opt = srt::Options::default();
         opt.fc = 32;
         opt.latency = 40;
         opt.mtu = self.options.mtu as u32;
-        opt.stream_id = Some(
-            StreamInfo {
-                kind: SocketKind::Publisher,
-                port: Some(port),
-                id: stream_id,
-            }
-            .encode(),
-        );
+        opt.passphrase = self.options.passphrase.clone();
+        opt.key_length = self.options.key_length;
 
-        // Create an srt connection to the server
         let mut encoder = srt::FragmentEncoder::new(opt.max_pkt_size());
-        let sender = Arc::new(srt::Socket::connect(self.options.server, opt)?);
-        log::info!("sender connect to server={}", self.options.server);
+        let uplink = if self.options.direct {
+            let peers = Arc::new(Peers::bind(SocketAddr::from(([0, 0, 0, 0], 0)), opt)?);
+            let srt_port = peers
+                .local_addr()
+                .map(|it| it.port())
+                .ok_or_else(|| Error::other("srt listener has no local address"))?;
+
+            log::info!("sender listen for direct receivers, port={}", srt_port);
+
+            let peers_ = peers.clone();
+            let adapter_ = Arc::downgrade(adapter);
+            let auto_switch_ = auto_switch.clone();
+            let keys_ = keys.clone();
+            let secret = self.options.secret.clone();
+            thread::Builder::new()
+                .name("MirrorStreamSenderListenerThread".to_string())
+                .spawn(move || {
+                    while let Ok((socket, addr)) = peers_.accept() {
+                        // The receiver carries the same stream information as it would to the
+                        // service, and is verified the same way.
+                        let accepted = match socket
+                            .get_stream_id()
+                            .and_then(|it| StreamInfo::decode(&it))
+                        {
+                            Some(info)
+                                if info.kind == SocketKind::Subscriber
+                                    && info.id == stream_id
+                                    && is_supported(info.version) =>
+                            {
+                                secret
+                                    .as_ref()
+                                    .map(|secret| auth::verify(secret, &info).is_ok())
+                                    .unwrap_or(true)
+                            }
+                            _ => false,
+                        };
+
+                        if !accepted {
+                            log::error!(
+                                "reject a direct receiver, addr={:?}, id={}",
+                                addr,
+                                stream_id
+                            );
+
+                            continue;
+                        }
+
+                        log::info!(
+                            "accept a direct receiver, addr={:?}, id={}",
+                            addr,
+                            stream_id
+                        );
+
+                        let socket = Arc::new(socket);
+                        if let Some(adapter) = adapter_.upgrade() {
+                            adapter.stats.set_srt_socket(&socket);
+                        } else {
+                            break;
+                        }
+
+                        if spawn_feedback_thread(
+                            &socket,
+                            stream_id,
+                            adapter_.clone(),
+                            auto_switch_.clone(),
+                            keys_.clone(),
+                            |_| (),
+                        )
+                        .is_err()
+                        {
+                            break;
+                        }
+
+                        peers_.add(socket);
+                    }
+
+                    log::info!("sender listener is closed, id={}", stream_id);
+                })?;
 
+            // The beacon is broadcast as long as the sender is running.
+            let beacon = BeaconSender::new(self.options.secret.clone())?;
+            let start = Beacon::Start {
+                multicast: self.options.multicast,
+                description: adapter.get_description(),
+                id: stream_id,
+                srt_port,
+                port,
+            };
+
+            let peers_ = Arc::downgrade(&peers);
+            thread::Builder::new()
+                .name("MirrorStreamSenderBeaconThread".to_string())
+                .spawn(move || {
+                    while peers_.strong_count() > 0 {
+                        if let Err(e) = beacon.send(&start) {
+                            log::error!("failed to send beacon, err={:?}", e);
+
+                            break;
+                        }
+
+                        thread::sleep(BEACON_INTERVAL);
+                    }
+
+                    let _ = beacon.send(&Beacon::Stop { id: stream_id });
+                })?;
+
+            Uplink::Direct(peers)
+        } else {
+            let publisher = Publisher {
+                server: self.srt_server.clone(),
+                multicast: self.options.multicast,
+                tokens: self.options.tokens.clone(),
+                adapter: Arc::downgrade(adapter),
+                auto_switch: auto_switch.clone(),
+                keys: keys.clone(),
+                stream_id,
+                port,
+                opt,
+            };
+
+            let (link, lost) = ServiceLink::new();
+            publisher.connect(&link)?;
+
+            // The connection to the service is reestablished in the background when it is
+            // lost, the sender drops the srt data meanwhile.
+            let states = self.states.clone();
+            let link_ = Arc::downgrade(&link);
+            thread::Builder::new()
+                .name("MirrorStreamSenderReconnectThread".to_string())
+                .spawn(move || {
+                    while lost.recv().is_ok() {
+                        log::warn!("sender connection is lost, id={}", stream_id);
+
+                        states.report(Link::Sender(stream_id), ConnectionState::Reconnecting);
+
+                        let mut backoff = Backoff::default();
+                        loop {
+                            thread::sleep(backoff.next());
+
+                            let link = if let Some(link) = link_.upgrade() {
+                                link
+                            } else {
+                                return;
+                            };
+
+                            if let Err(e) = publisher.connect(&link) {
+                                log::warn!(
+                                    "failed to reconnect sender, id={}, err={:?}",
+                                    stream_id,
+                                    e
+                                );
+
+                                continue;
+                            }
+
+                            // The receivers have lost the stream, they wait for the next key
+                            // frame to resume decoding.
+                            if let Some(adapter) = publisher.adapter.upgrade() {
+                                adapter.request_key_frame();
+                            }
+
+                            states.report(Link::Sender(stream_id), ConnectionState::Connected);
+                            break;
+                        }
+                    }
+                })?;
+
+            Uplink::Relay(link)
+        };
+
+        let events = self.events.clone();
         let adapter_ = Arc::downgrade(adapter);
         thread::Builder::new()
             .name("MirrorStreamSenderThread".to_string())
@@ -194,17 +839,71 @@ impl Transport {
 
                                 break 'a;
                             }
+
+                            adapter.stats.sent(TransportMode::Multicast, payload.len());
+                            adapter
+                                .stats
+                                .set_multicast_repaired(mcast_sender.repaired());
                         } else {
                             // SRT does not perform data fragmentation. It needs to be split into
-                            // fragments that do not exceed the MTU size.
+                            // fragments that do not exceed the MTU size. The rest of the payload
+                            // is dropped when the connection to the service is lost, it is
+                            // reestablished in the background.
                             for chunk in encoder.encode(&payload) {
-                                if let Err(e) = sender.send(chunk) {
-                                    log::error!("failed to send buf in srt, err={:?}", e);
+                                if let Err(e) = uplink.send(chunk) {
+                                    if e.kind() != ErrorKind::NotConnected {
+                                        log::error!("failed to send buf in srt, err={:?}", e);
+                                    }
+
+                                    break;
+                                }
+                            }
+
+                            adapter.stats.sent(TransportMode::Srt, payload.len());
+                        }
+
+                        if let Some(key) = rotation.as_mut().and_then(|it| it.poll()) {
+                            log::info!("rotate multicast key, id={}, key={}", stream_id, key.id);
 
-                                    break 'a;
+                            // The receivers request all the keys again after reconnecting.
+                            if let Err(e) = uplink.send(&key.encode()) {
+                                if e.kind() != ErrorKind::NotConnected {
+                                    log::error!("failed to send multicast key in srt, err={:?}", e);
                                 }
                             }
                         }
+
+                        if adapter.get_auto_switch() {
+                            let mode = if adapter.get_multicast() {
+                                TransportMode::Multicast
+                            } else {
+                                TransportMode::Srt
+                            };
+
+                            let counters = || {
+                                let (srt_sent, srt_lost) = uplink.counters();
+                                Counters {
+                                    multicast_sent: mcast_sender.sent(),
+                                    multicast_repaired: mcast_sender.repaired(),
+                                    srt_sent,
+                                    srt_lost,
+                                }
+                            };
+
+                            if let Some(mode) = auto_switch.lock().unwrap().poll(mode, counters) {
+                                log::info!(
+                                    "sender switches transport, id={}, mode={:?}",
+                                    stream_id,
+                                    mode
+                                );
+
+                                adapter.switch(mode);
+                                events.emit(Event::ModeSwitched {
+                                    id: stream_id,
+                                    mode,
+                                });
+                            }
+                        }
                     } else {
                         break;
                     }
@@ -214,29 +913,119 @@ impl Transport {
 
                 if let Some(adapter) = adapter_.upgrade() {
                     adapter.close();
-                    sender.close();
                 }
+
+                // Closing the sockets also stops the feedback threads.
+                uplink.close();
             })?;
 
         Ok(())
     }
 
+    /// The receiver is resumed when its connection is lost, unless the
+    /// channel has been stopped by its publisher, the adapter is closed if the
+    /// channel is not published again within [`RESUME_TIMEOUT`].
     pub fn create_receiver<T>(&self, stream_id: u32, adapter: &Arc<T>) -> Result<(), Error>
+    where
+        T: StreamReceiverAdapterExt + 'static,
+    {
+        self.open_receiver(stream_id, adapter, false)
+    }
+
+    // Reestablish the stream of a receiver whose connection is lost, the channel may
+    // not have been published again yet.
+    fn resume_receiver<T>(&self, stream_id: u32, adapter: Weak<T>)
+    where
+        T: StreamReceiverAdapterExt + 'static,
+    {
+        let this = self.clone();
+        let link = Link::Receiver(stream_id);
+        let adapter_ = adapter.clone();
+        if let Err(e) = thread::Builder::new()
+            .name("MirrorStreamReceiverReconnectThread".to_string())
+            .spawn(move || {
+                this.states.report(link, ConnectionState::Reconnecting);
+
+                let deadline = Instant::now() + RESUME_TIMEOUT;
+                let mut backoff = Backoff::default();
+                loop {
+                    thread::sleep(backoff.next());
+
+                    let adapter = if let Some(adapter) = adapter.upgrade() {
+                        adapter
+                    } else {
+                        break;
+                    };
+
+                    if this.stopped.read().unwrap().contains(&stream_id)
+                        || Instant::now() >= deadline
+                    {
+                        log::warn!("receiver is not resumed, id={}", stream_id);
+
+                        adapter.close();
+                        this.states.report(link, ConnectionState::Disconnected);
+                        break;
+                    }
+
+                    // Wait for the publisher to publish the channel again.
+                    if !this.publishs.read().unwrap().contains_key(&stream_id) {
+                        continue;
+                    }
+
+                    match this.open_receiver(stream_id, &adapter, true) {
+                        Ok(()) => {
+                            this.states.report(link, ConnectionState::Connected);
+                            break;
+                        }
+                        Err(e) => {
+                            log::warn!("failed to resume receiver, id={}, err={:?}", stream_id, e);
+                        }
+                    }
+                }
+            })
+        {
+            log::error!("failed to resume receiver, id={}, err={:?}", stream_id, e);
+
+            if let Some(adapter) = adapter_.upgrade() {
+                adapter.close();
+            }
+        }
+    }
+
+    fn open_receiver<T>(&self, stream_id: u32, adapter: &Arc<T>, resume: bool) -> Result<(), Error>
     where
         T: StreamReceiverAdapterExt + 'static,
     {
         let current_mcast_rceiver = Arc::new(Mutex::new(None));
 
+        // The channel whose packets the service relays to the receiver, the channel
+        // itself or one of its simulcast layers, it is told by the service.
+        let source = Arc::new(AtomicU32::new(stream_id));
+
         // Creating a multicast receiver
+        let delay = (
+            self.options.min_jitter_delay as usize,
+            self.options.max_jitter_delay as usize,
+        );
+
+        let keys = self.options.encrypt_multicast.then(Keys::default);
+        let keys_ = keys.clone();
+        let events = self.events.clone();
+        let source_ = source.clone();
         let current_mcast_rceiver_ = current_mcast_rceiver.clone();
         let create_mcast_receiver = move |receiver: Weak<srt::Socket>,
+                                          requester: Arc<KeyFrameRequester>,
                                           sequence: Arc<AtomicU64>,
                                           adapter: Weak<T>,
-                                          multicast,
-                                          port| {
-            let mcast_rceiver = if let Ok(socket) =
-                multicast::Socket::new(multicast, SocketAddr::new("0.0.0.0".parse().unwrap(), port))
-            {
+                                          endpoint: SocketAddr,
+                                          interface| {
+            let mcast_rceiver = if let Ok(socket) = multicast::Socket::new(
+                endpoint.ip(),
+                unspecified(endpoint.ip(), endpoint.port(), interface),
+                delay.0,
+                delay.1,
+                keys_.clone(),
+            ) {
                 let socket = Arc::new(socket);
                 if let Some(socket) = current_mcast_rceiver_
                     .lock()
@@ -255,8 +1044,19 @@ impl Transport {
                 return;
             };
 
-            log::info!("create multicast receiver, port={}", port);
+            log::info!("create multicast receiver, endpoint={}", endpoint);
+
+            events.emit(Event::MulticastStarted {
+                id: stream_id,
+                endpoint,
+            });
 
+            if let Some(adapter) = adapter.upgrade() {
+                adapter.stats().set_multicast_socket(&mcast_rceiver);
+            }
+
+            let events = events.clone();
+            let source = source_.clone();
             thread::Builder::new()
                 .name("MirrorStreamMulticastReceiverThread".to_string())
                 .spawn(move || {
@@ -265,22 +1065,42 @@ impl Transport {
                             break;
                         }
 
+                        // Only the publisher of the channel sends the multicast.
+                        if source.get() != stream_id {
+                            continue;
+                        }
+
                         if let Some(adapter) = adapter.upgrade() {
+                            adapter
+                                .stats()
+                                .received(TransportMode::Multicast, bytes.len());
+
                             // Check whether the sequence number is continuous, in
                             // order to check whether packet loss has occurred
                             if seq == 0 || seq - 1 == sequence.get() {
-                                if let Some((info, package)) = UnPackage::unpack(bytes) {
-                                    if !adapter.send(package, info.kind, info.flags, info.timestamp)
-                                    {
-                                        log::error!("adapter on buf failed.");
+                                match UnPackage::unpack(bytes) {
+                                    Ok((info, package)) => {
+                                        if !adapter.send(
+                                            package,
+                                            info.kind,
+                                            info.flags,
+                                            info.timestamp,
+                                        ) {
+                                            log::error!("adapter on buf failed.");
 
-                                        break;
+                                            break;
+                                        }
+                                    }
+                                    Err(UnPackageError::UnsupportedVersion(version)) => {
+                                        incompatible(&*adapter, stream_id, version);
+                                    }
+                                    Err(UnPackageError::Corrupted) => {
+                                        adapter.stats().checksum_dropped();
+                                        lost(&*adapter, &requester, &events, stream_id);
                                     }
-                                } else {
-                                    adapter.loss_pkt();
                                 }
                             } else {
-                                adapter.loss_pkt()
+                                lost(&*adapter, &requester, &events, stream_id);
                             }
 
                             sequence.update(seq);
@@ -303,11 +1123,18 @@ impl Transport {
         opt.fc = 32;
         opt.latency = 40;
         opt.mtu = self.options.mtu as u32;
+        opt.passphrase = self.options.passphrase.clone();
+        opt.key_length = self.options.key_length;
+        let token = self.options.token(stream_id, SocketKind::Subscriber);
         opt.stream_id = Some(
             StreamInfo {
                 kind: SocketKind::Subscriber,
+                version: PROTOCOL_VERSION,
                 id: stream_id,
+                multicast: None,
                 port: None,
+                layer: self.layers.read().unwrap().get(&stream_id).copied(),
+                token,
             }
             .encode(),
         );
@@ -320,16 +1147,90 @@ impl Transport {
         // Create an srt connection to the server
         let sequence = Arc::new(AtomicU64::new(0));
         let mut decoder = srt::FragmentDecoder::new();
-        let receiver = Arc::new(srt::Socket::connect(self.options.server, opt)?);
-        log::info!("receiver connect to server={}", self.options.server);
+        let server = self.server_of(stream_id)?;
+        let receiver = Arc::new(srt::Socket::connect(server, opt)?);
+        log::info!("receiver connect to server={}", server);
+
+        let requester = Arc::new(KeyFrameRequester::new(&receiver));
+        adapter.stats().set_srt_socket(&receiver);
+
+        self.receivers.write().unwrap().insert(
+            index,
+            ReceiverSocket {
+                socket: Arc::downgrade(&receiver),
+                stream_id,
+            },
+        );
+
+        // The multicast is encrypted, request the keys from the publisher.
+        if keys.is_some() {
+            receiver.send(&Feedback::KeyRequest.encode())?;
+        }
+
+        // The stream was interrupted, the decoding resumes from the next key frame.
+        if resume {
+            adapter.loss_pkt();
+            requester.request();
+        }
+
+        // Report the multicast loss to the publisher periodically, in auto mode the
+        // publisher falls back to srt when the multicast is not reliable enough.
+        let receiver_ = Arc::downgrade(&receiver);
+        let current_mcast_rceiver_ = current_mcast_rceiver.clone();
+        thread::Builder::new()
+            .name("MirrorStreamReceiverReportThread".to_string())
+            .spawn(move || {
+                let mut last = (0, 0);
+
+                loop {
+                    thread::sleep(switch::WINDOW);
+
+                    let receiver = if let Some(receiver) = receiver_.upgrade() {
+                        receiver
+                    } else {
+                        break;
+                    };
+
+                    // Nothing is reported until the multicast receiver exists, and the
+                    // counters start from zero again when it is recreated.
+                    let current = match current_mcast_rceiver_.lock().unwrap().as_ref() {
+                        Some(socket) => {
+                            let stats = socket.stats();
+                            (stats.received, stats.queue.lost)
+                        }
+                        None => {
+                            last = (0, 0);
+                            continue;
+                        }
+                    };
+
+                    let report = Feedback::LossReport {
+                        received: current.0.saturating_sub(last.0) as u32,
+                        lost: current.1.saturating_sub(last.1) as u32,
+                    };
+
+                    last = current;
+                    if receiver.send(&report.encode()).is_err() {
+                        break;
+                    }
+                }
+            })?;
 
         {
-            let multicast = self.options.multicast;
+            let options = self.options.clone();
+            let requester = requester.clone();
             let sequence = sequence.clone();
             let adapter = Arc::downgrade(adapter);
             let receiver = Arc::downgrade(&receiver);
-            if let Some(port) = self.publishs.read().unwrap().get(&stream_id) {
-                create_mcast_receiver(receiver, sequence, adapter, multicast, *port);
+            if let Some(published) = self.publishs.read().unwrap().get(&stream_id) {
+                create_mcast_receiver(
+                    receiver,
+                    requester,
+                    sequence,
+                    adapter,
+                    published.endpoint,
+                    options.interface,
+                );
             } else {
                 // Add a message receiver to the list
                 let (tx, rx) = channel();
@@ -338,16 +1239,30 @@ impl Transport {
                 thread::Builder::new()
                     .name("MirrorReceiverSignalProcessThread".to_string())
                     .spawn(move || {
+                        let mut current = None;
+
                         while let Ok(signal) = rx.recv() {
-                            if let Signal::Start { id, port } = signal {
-                                // Only process messages from the current receiving end
-                                if id == stream_id {
+                            if let Signal::Start {
+                                id,
+                                port,
+                                multicast,
+                                ..
+                            } = signal
+                            {
+                                let endpoint =
+                                    SocketAddr::new(multicast.unwrap_or(options.multicast), port);
+
+                                // Only process messages from the current receiving end, the
+                                // signal is repeated when the publisher describes the channel.
+                                if id == stream_id && current != Some(endpoint) {
+                                    current = Some(endpoint);
                                     create_mcast_receiver(
                                         receiver.clone(),
+                                        requester.clone(),
                                         sequence.clone(),
                                         adapter.clone(),
-                                        multicast,
-                                        port,
+                                        endpoint,
+                                        options.interface,
                                     );
                                 }
                             }
@@ -356,75 +1271,550 @@ impl Transport {
             }
         }
 
-        let channels = self.channels.clone();
+        let this = self.clone();
         let adapter_ = Arc::downgrade(adapter);
         thread::Builder::new()
             .name("MirrorStreamReceiverThread".to_string())
             .spawn(move || {
                 let mut buf = [0u8; 2000];
 
-                loop {
+                // Whether the packets of another simulcast layer start, their sequence
+                // does not follow the previous packets.
+                let mut switched = false;
+
+                // Whether the connection is lost, rather than the adapter closed.
+                let lost = loop {
                     match receiver.read(&mut buf) {
                         Ok(size) => {
                             if size == 0 {
-                                break;
+                                break true;
+                            }
+
+                            match Feedback::decode(&buf[..size]) {
+                                // The service switched the layer, the partial package of the
+                                // previous layer is dropped and the video is configured again.
+                                Some(Feedback::LayerSwitched { id }) => {
+                                    log::info!("layer switched, id={}, layer={}", stream_id, id);
+
+                                    if id != source.get() {
+                                        source.update(id);
+                                        switched = true;
+                                        decoder = srt::FragmentDecoder::new();
+
+                                        if let Some(adapter) = adapter_.upgrade() {
+                                            adapter.reconfigure();
+                                        }
+                                    }
+
+                                    continue;
+                                }
+                                Some(Feedback::Rejected { reason }) => {
+                                    log::error!(
+                                        "service rejects the receiver, id={}, reason={}",
+                                        stream_id,
+                                        reason
+                                    );
+
+                                    continue;
+                                }
+                                _ => (),
+                            }
+
+                            // The multicast keys are sent by the publisher between the fragments.
+                            if let Some(keys) = keys.as_ref() {
+                                if let Some(key) = StreamKey::decode(&buf[..size]) {
+                                    log::info!(
+                                        "recv multicast key, id={}, key={}",
+                                        stream_id,
+                                        key.id
+                                    );
+
+                                    keys.insert(key.id, key.key);
+                                    continue;
+                                }
                             }
 
                             // All the fragments received from SRT are split and need to be
                             // reassembled here
                             if let Some((seq, bytes)) = decoder.decode(&buf[..size]) {
                                 if let Some(adapter) = adapter_.upgrade() {
+                                    adapter.stats().received(TransportMode::Srt, bytes.len());
+
                                     // Check whether the sequence number is continuous, in order to
                                     // check whether packet loss has
                                     // occurred
-                                    if seq == 0 || seq - 1 == sequence.get() {
-                                        if let Some((info, package)) = UnPackage::unpack(bytes) {
-                                            if !adapter.send(
-                                                package,
-                                                info.kind,
-                                                info.flags,
-                                                info.timestamp,
-                                            ) {
-                                                log::error!("adapter on buf failed.");
-
-                                                break;
+                                    if mem::take(&mut switched)
+                                        || seq == 0
+                                        || seq - 1 == sequence.get()
+                                    {
+                                        match UnPackage::unpack(bytes) {
+                                            Ok((info, package)) => {
+                                                if !adapter.send(
+                                                    package,
+                                                    info.kind,
+                                                    info.flags,
+                                                    info.timestamp,
+                                                ) {
+                                                    log::error!("adapter on buf failed.");
+
+                                                    break false;
+                                                }
+                                            }
+                                            Err(UnPackageError::UnsupportedVersion(version)) => {
+                                                incompatible(&*adapter, stream_id, version);
+                                            }
+                                            Err(UnPackageError::Corrupted) => {
+                                                adapter.stats().checksum_dropped();
+                                                lost(
+                                                    &*adapter,
+                                                    &requester,
+                                                    &this.events,
+                                                    stream_id,
+                                                );
                                             }
-                                        } else {
-                                            adapter.loss_pkt();
                                         }
                                     } else {
-                                        adapter.loss_pkt()
+                                        lost(&*adapter, &requester, &this.events, stream_id);
                                     }
 
                                     sequence.update(seq);
                                 } else {
-                                    break;
+                                    break false;
                                 }
                             }
                         }
                         Err(e) => {
                             log::error!("{:?}", e);
 
-                            break;
+                            break true;
                         }
                     }
-                }
+                };
 
                 log::warn!("srt receiver is closed, id={}", stream_id);
 
                 // Remove the sender, which is intended to stop the signal receiver thread.
-                let _ = channels.write().unwrap().remove(&index);
+                let _ = this.channels.write().unwrap().remove(&index);
+                this.receivers.write().unwrap().remove(&index);
+
+                if let Some(socket) = current_mcast_rceiver.lock().unwrap().take() {
+                    socket.close()
+                }
 
                 if let Some(adapter) = adapter_.upgrade() {
-                    adapter.close();
                     receiver.close();
-                }
 
-                if let Some(socket) = current_mcast_rceiver.lock().unwrap().take() {
-                    socket.close()
+                    if lost {
+                        this.resume_receiver(stream_id, adapter_);
+                    } else {
+                        adapter.close();
+                    }
                 }
             })?;
 
         Ok(())
     }
 }
+
+// Connect to the signal server of the service, agree on the protocol version and
+// send the credential, the signals already received after the handshake are
+// returned with the connection, and the address of the srt server.
+fn connect_signal(options: &TransportOptions) -> Result<(TcpStream, BytesMut, SocketAddr), Error> {
+    let mut socket = TcpStream::connect(options.server)?;
+    let mut buf = [0u8; 1024];
+    let mut bytes = BytesMut::with_capacity(2000);
+
+    // Agree on the protocol version with the service first, the service rejects the
+    // clients it has no version in common with.
+    socket.write_all(
+        &Handshake::Hello {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+        }
+        .encode(),
+    )?;
+
+    socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let handshake = loop {
+        if let Some((size, handshake)) = Handshake::decode(&bytes) {
+            let _ = bytes.split_to(size);
+            break handshake;
+        }
+
+        // A service older than the versioned protocol does not answer at all.
+        let size = socket.read(&mut buf).map_err(|e| {
+            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                Error::new(
+                    ErrorKind::TimedOut,
+                    "the service does not answer the protocol handshake",
+                )
+            } else {
+                e
+            }
+        })?;
+
+        if size == 0 {
+            return Err(Error::new(
+                ErrorKind::ConnectionAborted,
+                "the service closed the connection during the protocol handshake",
+            ));
+        }
+
+        bytes.extend_from_slice(&buf[..size]);
+    };
+
+    socket.set_read_timeout(None)?;
+    let srt_server = match handshake {
+        Handshake::Accept { version, srt_port } => {
+            log::info!(
+                "signal protocol handshake, version={}, srt_port={:?}",
+                version,
+                srt_port
+            );
+
+            SocketAddr::new(
+                options.server.ip(),
+                srt_port.unwrap_or(options.server.port()),
+            )
+        }
+        Handshake::Reject {
+            version,
+            min_version,
+        } => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "incompatible protocol version, supported={}..={}, service={}..={}",
+                    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, min_version, version
+                ),
+            ));
+        }
+        Handshake::Hello { .. } => {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected handshake"));
+        }
+    };
+
+    // The service only reveals the channel list after receiving a valid credential.
+    if let Some(token) = options.token(0, SocketKind::Subscriber) {
+        let credential = StreamInfo {
+            kind: SocketKind::Subscriber,
+            token: Some(token),
+            version: PROTOCOL_VERSION,
+            ..Default::default()
+        };
+
+        socket.write_all(&Credential(credential.encode()).encode())?;
+    }
+
+    Ok((socket, bytes, srt_server))
+}
+
+// The stream lost packets, it is paused until the next key frame, which is
+// requested from the publisher.
+fn lost<T: StreamReceiverAdapterExt + ?Sized>(
+    adapter: &T,
+    requester: &KeyFrameRequester,
+    events: &Events,
+    stream_id: u32,
+) {
+    adapter.loss_pkt();
+
+    if requester.request() {
+        events.emit(Event::PacketLoss { id: stream_id });
+    }
+}
+
+// The packages of a sender using another package layout cannot be unpacked, they
+// are counted, the key frames would not help, and the error is logged once.
+fn incompatible<T: StreamReceiverAdapterExt + ?Sized>(adapter: &T, stream_id: u32, version: u8) {
+    if adapter.stats().incompatible_dropped() == 0 {
+        log::error!(
+            "the sender uses an unsupported package version, id={}, version={}, supported={}",
+            stream_id,
+            version,
+            Package::VERSION
+        );
+    }
+}
+
+// Record the published channels and forward the signal to all subscribers.
+fn dispatch(
+    signal: Signal,
+    default_multicast: IpAddr,
+    channels: &RwLock<HashMap<u32, Sender<Signal>>>,
+    publishs: &RwLock<HashMap<u32, Published>>,
+    stopped: &RwLock<HashSet<u32>>,
+    events: &Events,
+) {
+    match signal {
+        Signal::Start {
+            id,
+            port,
+            multicast,
+            ref description,
+        } => {
+            stopped.write().unwrap().remove(&id);
+            publishs.write().unwrap().insert(
+                id,
+                Published {
+                    endpoint: SocketAddr::new(multicast.unwrap_or(default_multicast), port),
+                    description: description.clone(),
+                },
+            );
+        }
+        Signal::Stop { id } => {
+            stopped.write().unwrap().insert(id);
+            publishs.write().unwrap().remove(&id);
+
+            if channels.write().unwrap().remove(&id).is_some() {
+                log::info!("channel is close, id={}", id)
+            }
+        }
+    }
+
+    events.emit(match signal {
+        Signal::Start { id, .. } => Event::ChannelPublished { id },
+        Signal::Stop { id } => Event::ChannelStopped { id },
+    });
+
+    let mut closeds: SmallVec<[u32; 10]> = SmallVec::with_capacity(10);
+
+    // Forwards the signal to all subscribers
+    {
+        for (id, tx) in channels.read().unwrap().iter() {
+            if tx.send(signal.clone()).is_err() {
+                closeds.push(*id);
+            }
+        }
+    }
+
+    // Clean up closed subscribers
+    if !closeds.is_empty() {
+        for id in closeds {
+            if channels.write().unwrap().remove(&id).is_some() {
+                log::info!("channel is close, id={}", id)
+            }
+        }
+    }
+}
+
+// What the publisher of a channel sends to the service after connecting, again
+// every time the connection is reestablished.
+struct Publisher {
+    server: Arc<RwLock<SocketAddr>>,
+    multicast: IpAddr,
+    tokens: Option<TokenProvider>,
+    adapter: Weak<StreamSenderAdapter>,
+    auto_switch: Arc<Mutex<AutoSwitch>>,
+    keys: Option<Keys>,
+    stream_id: u32,
+    port: u16,
+    opt: srt::Options,
+}
+
+impl Publisher {
+    fn connect(&self, link: &Arc<ServiceLink>) -> Result<(), Error> {
+        let adapter = self
+            .adapter
+            .upgrade()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "the sender is closed"))?;
+
+        // The token is asked for again, the previous one may have expired.
+        let mut opt = self.opt.clone();
+        opt.stream_id = Some(
+            StreamInfo {
+                kind: SocketKind::Publisher,
+                version: PROTOCOL_VERSION,
+                multicast: Some(self.multicast),
+                port: Some(self.port),
+                id: self.stream_id,
+                layer: None,
+                token: self
+                    .tokens
+                    .as_ref()
+                    .and_then(|it| it.get(self.stream_id, SocketKind::Publisher)),
+            }
+            .encode(),
+        );
+
+        // Create an srt connection to the server
+        let server = *self.server.read().unwrap();
+        let sender = Arc::new(srt::Socket::connect(server, opt)?);
+        log::info!("sender connect to server={}", server);
+
+        adapter.stats.set_srt_socket(&sender);
+
+        // The service keeps the description of the channel and sends it to the
+        // receivers with the signal, it is not essential to the stream.
+        if let Some(description) = adapter.get_description() {
+            if let Err(e) = sender.send(&description.encode()) {
+                log::warn!(
+                    "not send the description, id={}, err={:?}",
+                    self.stream_id,
+                    e
+                );
+            }
+        }
+
+        // Announce the multicast keys to the subscribers that are already connected,
+        // the others request them after connecting.
+        if let Some(keys) = self.keys.as_ref() {
+            announce(&sender, keys)?;
+        }
+
+        // The server forwards the feedback of the subscribers to the publisher, such
+        // as key frame requests after packet loss. The connection is lost when the
+        // feedback can no longer be read.
+        let link_ = Arc::downgrade(link);
+        spawn_feedback_thread(
+            &sender,
+            self.stream_id,
+            self.adapter.clone(),
+            self.auto_switch.clone(),
+            self.keys.clone(),
+            move |socket| {
+                if let Some(link) = link_.upgrade() {
+                    link.lost(socket);
+                }
+            },
+        )?;
+
+        link.replace(sender);
+        Ok(())
+    }
+}
+
+// Handle the feedback of the subscribers read from the socket, which is connected
+// to the service, or to a receiver in direct mode.
+// The socket is passed to `closed` when the thread exits.
+fn spawn_feedback_thread<F>(
+    socket: &Arc<srt::Socket>,
+    stream_id: u32,
+    adapter: Weak<StreamSenderAdapter>,
+    auto_switch: Arc<Mutex<AutoSwitch>>,
+    keys: Option<Keys>,
+    closed: F,
+) -> Result<(), Error>
+where
+    F: FnOnce(&Weak<srt::Socket>) + Send + 'static,
+{
+    let socket = Arc::downgrade(socket);
+    thread::Builder::new()
+        .name("MirrorStreamSenderFeedbackThread".to_string())
+        .spawn(move || {
+            let mut buf = [0u8; 2000];
+
+            while let Some(socket) = socket.upgrade() {
+                let size = match socket.read(&mut buf) {
+                    Ok(size) if size > 0 => size,
+                    _ => break,
+                };
+
+                if let Some(adapter) = adapter.upgrade() {
+                    match Feedback::decode(&buf[..size]) {
+                        Some(Feedback::KeyFrameRequest) if adapter.request_key_frame() => {
+                            log::info!("receiver requests a key frame, id={}", stream_id);
+                        }
+                        Some(Feedback::LossReport { received, lost }) => {
+                            auto_switch.lock().unwrap().report(received, lost);
+                        }
+                        Some(Feedback::KeyRequest) => {
+                            if let Some(keys) = keys.as_ref() {
+                                if announce(&socket, keys).is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                        Some(Feedback::BandwidthLimit { max_bandwidth }) => {
+                            log::info!(
+                                "service limits the bandwidth, id={}, max_bandwidth={}",
+                                stream_id,
+                                max_bandwidth
+                            );
+
+                            if let Err(e) = socket.set_max_bandwidth(max_bandwidth) {
+                                log::warn!("not limit the bandwidth, err={:?}", e);
+                            }
+                        }
+                        Some(Feedback::Rejected { reason }) => {
+                            log::error!(
+                                "service rejects the sender, id={}, reason={}",
+                                stream_id,
+                                reason
+                            );
+                        }
+                        _ => (),
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            log::info!("sender feedback is closed, id={}", stream_id);
+
+            closed(&socket);
+        })?;
+
+    Ok(())
+}
+
+// Send all the multicast keys of the publisher, from the oldest to the newest.
+fn announce(socket: &srt::Socket, keys: &Keys) -> Result<(), Error> {
+    for (id, key) in keys.all() {
+        socket.send(&StreamKey { id, key }.encode())?;
+    }
+
+    Ok(())
+}
+
+// The unspecified address of the same address family as the multicast group,
+// for IPV6 the scope id is the index of the interface used by the multicast.
+fn unspecified(multicast: IpAddr, port: u16, interface: u32) -> SocketAddr {
+    match multicast {
+        IpAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
+        IpAddr::V6(_) => {
+            SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, interface))
+        }
+    }
+}
+
+/// After packet loss the receiver has to wait for the next key frame, this
+/// requests the publisher to emit one immediately through the srt subscriber
+/// socket.
+///
+/// The requests of each receiver are rate limited, and the publisher rate
+/// limits the requests of all receivers again.
+struct KeyFrameRequester {
+    socket: Weak<srt::Socket>,
+    requested: Mutex<Option<Instant>>,
+}
+
+impl KeyFrameRequester {
+    fn new(socket: &Arc<srt::Socket>) -> Self {
+        Self {
+            socket: Arc::downgrade(socket),
+            requested: Mutex::new(None),
+        }
+    }
+
+    // Returns whether a request has been sent.
+    fn request(&self) -> bool {
+        let mut requested = self.requested.lock().unwrap();
+        if let Some(time) = requested.as_ref() {
+            if time.elapsed() < KEY_FRAME_REQUEST_INTERVAL {
+                return false;
+            }
+        }
+
+        if let Some(socket) = self.socket.upgrade() {
+            if let Err(e) = socket.send(&Feedback::KeyFrameRequest.encode()) {
+                log::warn!("failed to send key frame request, err={:?}", e);
+            } else {
+                requested.replace(Instant::now());
+                return true;
+            }
+        }
+
+        false
+    }
+}
@@ -1,6 +1,9 @@
+use std::fmt;
+
 use crate::adapter::StreamKind;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use service::package;
 use xxhash_rust::xxh3::xxh3_64;
 
 // ```text
@@ -13,18 +16,40 @@ use xxhash_rust::xxh3::xxh3_64;
 // |                           Lenght                              |
 // |                                                               |
 // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-// |     type      |     flags     |           timestamp...
-//          ...timestamp           |                               |
+// |    version    |     type      |     flags     | timestamp...
+//                         ...timestamp                            |
+//    ...timestamp |                                               |
 // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 // ```
+//
+// The version is the version of the package layout, the receiver only
+// unpacks the version it knows.
 
 #[derive(Debug)]
 pub struct PacketInfo {
     pub kind: StreamKind,
     pub flags: i32,
+    /// The capture time in microseconds, the audio and video of the same
+    /// stream share the same clock.
     pub timestamp: u64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnPackageError {
+    /// The checksum or the length does not match, or the type is unknown.
+    Corrupted,
+    /// The package was packed by a sender using another layout.
+    UnsupportedVersion(u8),
+}
+
+impl std::error::Error for UnPackageError {}
+
+impl fmt::Display for UnPackageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UnPackageError: {:?}", self)
+    }
+}
+
 /// Creates a BytesMut and copies from src to a buffer. The created buffer
 /// contains the initial message header required for message encoding, which is
 /// an optimization to reduce data copying in the process.
@@ -48,7 +73,10 @@ pub fn with_capacity(size: usize) -> BytesMut {
 pub struct Package;
 
 impl Package {
-    const HEAD_SIZE: usize = 26;
+    const HEAD_SIZE: usize = package::HEAD_SIZE;
+
+    /// The version of the package layout.
+    pub const VERSION: u8 = package::VERSION;
 
     /// The result of the encoding may be null, this is because an empty packet
     /// may be passed in from outside.
@@ -61,6 +89,7 @@ impl Package {
 
         bytes.put_u64(0);
         bytes.put_u64(size as u64);
+        bytes.put_u8(Self::VERSION);
         bytes.put_u8(info.kind as u8);
         bytes.put_u8(info.flags as u8);
         bytes.put_u64(info.timestamp);
@@ -80,23 +109,28 @@ impl Package {
 pub struct UnPackage;
 
 impl UnPackage {
-    pub fn unpack(mut bytes: Bytes) -> Option<(PacketInfo, Bytes)> {
+    pub fn unpack(mut bytes: Bytes) -> Result<(PacketInfo, Bytes), UnPackageError> {
         let count = bytes.len();
-        if bytes.get_u64() == xxh3_64(&bytes) {
-            if bytes.get_u64() as usize == count {
-                Some((
-                    PacketInfo {
-                        kind: StreamKind::try_from(bytes.get_u8()).ok()?,
-                        flags: bytes.get_u8() as i32,
-                        timestamp: bytes.get_u64(),
-                    },
-                    bytes,
-                ))
-            } else {
-                None
-            }
-        } else {
-            None
+        if count < Package::HEAD_SIZE
+            || bytes.get_u64() != xxh3_64(&bytes)
+            || bytes.get_u64() as usize != count
+        {
+            return Err(UnPackageError::Corrupted);
         }
+
+        let version = bytes.get_u8();
+        if version != Package::VERSION {
+            return Err(UnPackageError::UnsupportedVersion(version));
+        }
+
+        Ok((
+            PacketInfo {
+                kind: StreamKind::try_from(bytes.get_u8())
+                    .map_err(|_| UnPackageError::Corrupted)?,
+                flags: bytes.get_u8() as i32,
+                timestamp: bytes.get_u64(),
+            },
+            bytes,
+        ))
     }
 }
@@ -0,0 +1,185 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    sync::{
+        atomic::AtomicBool,
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex, RwLock, Weak,
+    },
+    time::Duration,
+};
+
+use utils::atomic::EasyAtomic;
+
+/// The delay before the first attempt to reconnect, it is doubled after each
+/// failed attempt up to [`MAX_BACKOFF`].
+pub const MIN_BACKOFF: Duration = Duration::from_millis(500);
+
+pub const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// How long a receiver tries to resume its stream, if the channel is not
+/// published again within this time the receiver is closed.
+pub const RESUME_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The delays between the attempts to reconnect.
+pub struct Backoff(Duration);
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self(MIN_BACKOFF)
+    }
+}
+
+impl Backoff {
+    /// The delay before the next attempt.
+    pub fn next(&mut self) -> Duration {
+        let delay = self.0;
+        self.0 = (self.0 * 2).min(MAX_BACKOFF);
+        delay
+    }
+}
+
+/// The connections of the transport whose state is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    /// The signal connection to the service.
+    Signal,
+    /// The srt connection of the sender of the channel to the service.
+    Sender(u32),
+    /// The srt connection of the receiver of the channel.
+    Receiver(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The connection has been reestablished.
+    Connected,
+    /// The connection is lost and is being reestablished.
+    Reconnecting,
+    /// The connection is lost for good, the receiver is closed.
+    Disconnected,
+}
+
+type StateHandler = Box<dyn Fn(Link, ConnectionState) + Send + Sync>;
+
+/// Reports the state transitions of the connections to the handler set by
+/// the application.
+#[derive(Default)]
+pub struct States(RwLock<Option<StateHandler>>);
+
+impl fmt::Debug for States {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "States")
+    }
+}
+
+impl States {
+    pub fn set_handler(&self, handler: StateHandler) {
+        self.0.write().unwrap().replace(handler);
+    }
+
+    pub fn report(&self, link: Link, state: ConnectionState) {
+        log::info!(
+            "connection state changed, link={:?}, state={:?}",
+            link,
+            state
+        );
+
+        if let Some(handler) = self.0.read().unwrap().as_ref() {
+            handler(link, state);
+        }
+    }
+}
+
+/// The srt connection of a sender to the service, it is replaced by a new
+/// connection when it is lost. The data sent while there is no connection is
+/// dropped.
+#[derive(Default)]
+pub struct ServiceLink {
+    socket: RwLock<Option<Arc<srt::Socket>>>,
+    lost: Mutex<Option<Sender<()>>>,
+    closed: AtomicBool,
+}
+
+impl ServiceLink {
+    /// Create a link without a connection, the receiver is notified every time
+    /// the connection is lost, it is disconnected when the link is released.
+    pub fn new() -> (Arc<Self>, Receiver<()>) {
+        let (tx, rx) = channel();
+        let link = Arc::new(Self::default());
+        link.lost.lock().unwrap().replace(tx);
+
+        (link, rx)
+    }
+
+    pub fn send(&self, buf: &[u8]) -> Result<(), Error> {
+        let socket = if let Some(socket) = self.socket.read().unwrap().clone() {
+            socket
+        } else {
+            return Err(Error::new(
+                ErrorKind::NotConnected,
+                "the connection to the service is being reestablished",
+            ));
+        };
+
+        socket.send(buf).inspect_err(|_| {
+            self.lost(&Arc::downgrade(&socket));
+        })
+    }
+
+    /// The connection is lost, unless it has already been replaced.
+    pub fn lost(&self, socket: &Weak<srt::Socket>) {
+        let mut current = self.socket.write().unwrap();
+        if current
+            .as_ref()
+            .map(|it| Arc::as_ptr(it) == socket.as_ptr())
+            .unwrap_or(false)
+        {
+            if let Some(socket) = current.take() {
+                socket.close();
+            }
+
+            if let Some(tx) = self.lost.lock().unwrap().as_ref() {
+                let _ = tx.send(());
+            }
+        }
+    }
+
+    /// Use the new connection, the link may have been closed in the meantime,
+    /// the connection is closed then.
+    pub fn replace(&self, socket: Arc<srt::Socket>) {
+        if self.closed.get() {
+            socket.close();
+            return;
+        }
+
+        if let Some(socket) = self.socket.write().unwrap().replace(socket) {
+            socket.close();
+        }
+    }
+
+    /// The totals of the srt packets sent and lost of the current connection.
+    pub fn counters(&self) -> (u64, u64) {
+        self.socket
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|socket| socket.get_stats().ok())
+            .map(|trace| {
+                (
+                    trace.pkt_sent_total.max(0) as u64,
+                    trace.pkt_snd_loss_total.max(0) as u64,
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn close(&self) {
+        self.closed.update(true);
+        self.lost.lock().unwrap().take();
+
+        if let Some(socket) = self.socket.write().unwrap().take() {
+            socket.close();
+        }
+    }
+}
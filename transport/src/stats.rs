@@ -0,0 +1,222 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Instant,
+};
+
+use utils::atomic::EasyAtomic;
+
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TransportMode {
+    #[default]
+    Srt = 0,
+    Multicast = 1,
+}
+
+impl From<u8> for TransportMode {
+    fn from(value: u8) -> Self {
+        if value == TransportMode::Multicast as u8 {
+            Self::Multicast
+        } else {
+            Self::Srt
+        }
+    }
+}
+
+/// The encryption state of the srt connection of a stream.
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionState {
+    /// The connection is not encrypted.
+    #[default]
+    Unsecured = 0,
+    /// The keys are being exchanged.
+    Securing = 1,
+    /// The connection is encrypted.
+    Secured = 2,
+    /// The service encrypts the connection but no passphrase is set.
+    NoSecret = 3,
+    /// The passphrase does not match the passphrase of the service.
+    BadSecret = 4,
+}
+
+impl From<srt::KmState> for EncryptionState {
+    fn from(value: srt::KmState) -> Self {
+        match value {
+            srt::KmState::SRT_KM_S_UNSECURED => Self::Unsecured,
+            srt::KmState::SRT_KM_S_SECURING => Self::Securing,
+            srt::KmState::SRT_KM_S_SECURED => Self::Secured,
+            srt::KmState::SRT_KM_S_NOSECRET => Self::NoSecret,
+            srt::KmState::SRT_KM_S_BADSECRET => Self::BadSecret,
+        }
+    }
+}
+
+/// A snapshot of the network statistics of a stream.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StreamStats {
+    /// The transport currently carrying the audio and video data.
+    pub mode: TransportMode,
+    /// Round trip time of the srt connection in milliseconds.
+    pub rtt: f64,
+    /// Bitrate of the audio and video data sent since the last snapshot, in
+    /// Mbps.
+    pub send_bitrate: f64,
+    /// Bitrate of the audio and video data received since the last snapshot,
+    /// in Mbps.
+    pub recv_bitrate: f64,
+    /// The total number of packets lost, both in srt and multicast.
+    pub lost_packets: u64,
+    /// The total number of packets retransmitted by srt, repaired at the
+    /// request of the multicast receivers, or rebuilt by forward error
+    /// correction.
+    pub retransmitted_packets: u64,
+    /// The total number of packets and fragments dropped because the checksum
+    /// does not match.
+    pub checksum_dropped: u64,
+    /// The total number of frames dropped by the receiver while waiting for a
+    /// key frame after packet loss.
+    pub filtered_frames: u64,
+    /// The total number of packets dropped because the sender packed them
+    /// with a version this receiver does not support, the sender and the
+    /// receiver have to be upgraded to the same version.
+    pub incompatible_dropped: u64,
+    /// The current delay of the multicast jitter buffer in milliseconds.
+    pub jitter_delay: u32,
+    /// The encryption state of the srt connection.
+    pub encryption: EncryptionState,
+}
+
+/// Collects the statistics of a stream.
+///
+/// Owned by the stream adapter, the transport threads of the stream record
+/// what they send and receive in it, and the sockets of the stream are
+/// queried when taking a snapshot.
+pub struct StatsCollector {
+    mode: AtomicU8,
+    srt: Mutex<Weak<srt::Socket>>,
+    multicast: Mutex<Weak<multicast::Socket>>,
+    multicast_repaired: AtomicU64,
+    srt_recv_retrans: AtomicU64,
+    sent_bytes: AtomicU64,
+    recv_bytes: AtomicU64,
+    checksum_dropped: AtomicU64,
+    filtered_frames: AtomicU64,
+    incompatible_dropped: AtomicU64,
+    last: Mutex<(Instant, u64, u64)>,
+}
+
+impl Default for StatsCollector {
+    fn default() -> Self {
+        Self {
+            mode: AtomicU8::new(TransportMode::Srt as u8),
+            srt: Mutex::new(Weak::new()),
+            multicast: Mutex::new(Weak::new()),
+            multicast_repaired: AtomicU64::new(0),
+            srt_recv_retrans: AtomicU64::new(0),
+            sent_bytes: AtomicU64::new(0),
+            recv_bytes: AtomicU64::new(0),
+            checksum_dropped: AtomicU64::new(0),
+            filtered_frames: AtomicU64::new(0),
+            incompatible_dropped: AtomicU64::new(0),
+            last: Mutex::new((Instant::now(), 0, 0)),
+        }
+    }
+}
+
+impl StatsCollector {
+    pub(crate) fn set_srt_socket(&self, socket: &Arc<srt::Socket>) {
+        *self.srt.lock().unwrap() = Arc::downgrade(socket);
+    }
+
+    pub(crate) fn set_multicast_socket(&self, socket: &Arc<multicast::Socket>) {
+        *self.multicast.lock().unwrap() = Arc::downgrade(socket);
+    }
+
+    pub(crate) fn set_multicast_repaired(&self, count: u64) {
+        self.multicast_repaired.update(count);
+    }
+
+    pub(crate) fn sent(&self, mode: TransportMode, size: usize) {
+        self.mode.update(mode as u8);
+        self.sent_bytes.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn received(&self, mode: TransportMode, size: usize) {
+        self.mode.update(mode as u8);
+        self.recv_bytes.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn checksum_dropped(&self) {
+        self.checksum_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn filtered(&self) {
+        self.filtered_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the previous count.
+    pub(crate) fn incompatible_dropped(&self) -> u64 {
+        self.incompatible_dropped.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Take a snapshot of the statistics, the bitrates are measured since the
+    /// previous snapshot.
+    pub fn snapshot(&self) -> StreamStats {
+        let mut stats = StreamStats {
+            mode: TransportMode::from(self.mode.get()),
+            retransmitted_packets: self.multicast_repaired.get(),
+            checksum_dropped: self.checksum_dropped.get(),
+            filtered_frames: self.filtered_frames.get(),
+            incompatible_dropped: self.incompatible_dropped.get(),
+            ..Default::default()
+        };
+
+        if let Some(socket) = self.srt.lock().unwrap().upgrade() {
+            if let Ok(state) = socket.get_km_state() {
+                stats.encryption = state.into();
+            }
+
+            if let Ok(trace) = socket.get_stats() {
+                // The interval counters are cleared by each query, so the received
+                // retransmissions are accumulated here.
+                let recv_retrans = self
+                    .srt_recv_retrans
+                    .fetch_add(trace.pkt_rcv_retrans.max(0) as u64, Ordering::Relaxed)
+                    + trace.pkt_rcv_retrans.max(0) as u64;
+
+                stats.rtt = trace.ms_rtt;
+                stats.lost_packets +=
+                    (trace.pkt_snd_loss_total.max(0) + trace.pkt_rcv_loss_total.max(0)) as u64;
+                stats.retransmitted_packets += trace.pkt_retrans_total.max(0) as u64 + recv_retrans;
+            }
+        }
+
+        if let Some(socket) = self.multicast.lock().unwrap().upgrade() {
+            let multicast = socket.stats();
+            stats.lost_packets += multicast.queue.lost;
+            stats.retransmitted_packets += multicast.recovered;
+            stats.checksum_dropped += multicast.checksum_dropped;
+            stats.jitter_delay = multicast.queue.delay.as_millis() as u32;
+        }
+
+        {
+            let sent = self.sent_bytes.get();
+            let recv = self.recv_bytes.get();
+
+            let mut last = self.last.lock().unwrap();
+            let elapsed = last.0.elapsed().as_secs_f64();
+            if elapsed > 0.0 {
+                stats.send_bitrate = (sent - last.1) as f64 * 8.0 / elapsed / 1_000_000.0;
+                stats.recv_bitrate = (recv - last.2) as f64 * 8.0 / elapsed / 1_000_000.0;
+            }
+
+            *last = (Instant::now(), sent, recv);
+        }
+
+        stats
+    }
+}
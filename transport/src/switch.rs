@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+use crate::stats::TransportMode;
+
+/// The link quality is evaluated once per window, the receivers also report
+/// their multicast loss at this interval.
+pub const WINDOW: Duration = Duration::from_secs(1);
+
+/// A window is bad for multicast when a receiver still loses more than this
+/// share of the fragments after repair and forward error correction.
+const MAX_MULTICAST_LOSS: f64 = 0.03;
+
+/// A window is bad for multicast when more than this share of the fragments
+/// has to be repaired, the stream survives only by flooding the group with
+/// repairs.
+const MAX_REPAIR_RATE: f64 = 0.15;
+
+/// The number of consecutive bad windows before falling back to srt.
+const BAD_WINDOWS: u32 = 3;
+
+/// Multicast is only probed again while the srt loss is below this share, if
+/// the srt link of the publisher is lossy itself, the multicast will not be
+/// better.
+const MAX_SRT_LOSS: f64 = 0.01;
+
+/// The reports of the first windows after switching to multicast are ignored,
+/// the receivers need some time to join the group.
+const GRACE: Duration = Duration::from_secs(2);
+
+/// How long to stay on srt before probing multicast again, doubled each time
+/// the multicast fails again shortly after the probe.
+const MIN_HOLDOFF: Duration = Duration::from_secs(10);
+const MAX_HOLDOFF: Duration = Duration::from_secs(300);
+
+/// The multicast is considered stable after working for this long, and the
+/// holdoff is reset.
+const STABLE: Duration = Duration::from_secs(60);
+
+/// The counters of the publisher sockets, all of them are totals.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counters {
+    pub multicast_sent: u64,
+    pub multicast_repaired: u64,
+    pub srt_sent: u64,
+    pub srt_lost: u64,
+}
+
+/// Decides when the publisher switches between multicast and srt.
+///
+/// The multicast is left after the loss reports of the receivers or the
+/// repair rate stay bad for several windows, and it is probed again after a
+/// holdoff that grows each time the probe fails, so that an unstable network
+/// does not make the publisher switch back and forth.
+pub struct AutoSwitch {
+    mode: TransportMode,
+    since: Instant,
+    window: Instant,
+    bad: u32,
+    holdoff: Duration,
+    // The worst loss rate reported by the receivers in the current window.
+    reported: Option<f64>,
+    last: Counters,
+}
+
+impl AutoSwitch {
+    pub fn new(mode: TransportMode) -> Self {
+        Self {
+            since: Instant::now(),
+            window: Instant::now(),
+            holdoff: MIN_HOLDOFF,
+            last: Counters::default(),
+            reported: None,
+            bad: 0,
+            mode,
+        }
+    }
+
+    /// Record a loss report of a receiver, a report without any packet is not
+    /// a sample, e.g. the multicast is idle while the publisher uses srt.
+    pub fn report(&mut self, received: u32, lost: u32) {
+        let total = received as u64 + lost as u64;
+        if total == 0 {
+            return;
+        }
+
+        let rate = lost as f64 / total as f64;
+
+        self.reported = Some(self.reported.map(|it| it.max(rate)).unwrap_or(rate));
+    }
+
+    /// Evaluate the link quality at the end of each window, returns the new
+    /// transport if the publisher should switch.
+    ///
+    /// `mode` is the transport currently used, it may have been changed by
+    /// the application in the meantime. The counters are only queried at the
+    /// end of the window.
+    pub fn poll<F>(&mut self, mode: TransportMode, counters: F) -> Option<TransportMode>
+    where
+        F: FnOnce() -> Counters,
+    {
+        if mode != self.mode {
+            self.switched(mode);
+        }
+
+        if self.window.elapsed() < WINDOW {
+            return None;
+        }
+
+        let counters = counters();
+        let sent = counters
+            .multicast_sent
+            .saturating_sub(self.last.multicast_sent);
+        let repaired = counters
+            .multicast_repaired
+            .saturating_sub(self.last.multicast_repaired);
+        let srt_sent = counters.srt_sent.saturating_sub(self.last.srt_sent);
+        let srt_lost = counters.srt_lost.saturating_sub(self.last.srt_lost);
+        let reported = self.reported.take();
+
+        self.window = Instant::now();
+        self.last = counters;
+
+        let next = match self.mode {
+            TransportMode::Multicast => {
+                if self.since.elapsed() < GRACE || sent == 0 {
+                    return None;
+                }
+
+                let repair_rate = repaired as f64 / sent as f64;
+                if reported.unwrap_or(0.0) > MAX_MULTICAST_LOSS || repair_rate > MAX_REPAIR_RATE {
+                    self.bad += 1;
+                } else {
+                    self.bad = 0;
+                }
+
+                if self.bad < BAD_WINDOWS {
+                    return None;
+                }
+
+                // The multicast has failed again shortly after the probe, wait longer
+                // before the next probe.
+                self.holdoff = if self.since.elapsed() < STABLE {
+                    (self.holdoff * 2).min(MAX_HOLDOFF)
+                } else {
+                    MIN_HOLDOFF
+                };
+
+                TransportMode::Srt
+            }
+            TransportMode::Srt => {
+                if self.since.elapsed() < self.holdoff {
+                    return None;
+                }
+
+                if srt_sent > 0 && srt_lost as f64 / srt_sent as f64 > MAX_SRT_LOSS {
+                    return None;
+                }
+
+                TransportMode::Multicast
+            }
+        };
+
+        self.switched(next);
+        Some(next)
+    }
+
+    fn switched(&mut self, mode: TransportMode) {
+        self.since = Instant::now();
+        self.reported = None;
+        self.mode = mode;
+        self.bad = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignore_a_report_without_packets() {
+        let mut switch = AutoSwitch::new(TransportMode::Srt);
+        switch.report(0, 0);
+        assert_eq!(switch.reported, None);
+
+        switch.report(90, 10);
+        switch.report(0, 0);
+        assert_eq!(switch.reported, Some(0.1));
+    }
+}